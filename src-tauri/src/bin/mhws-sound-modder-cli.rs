@@ -0,0 +1,3 @@
+fn main() -> std::process::ExitCode {
+    mhws_sound_modder_lib::run_cli()
+}
@@ -0,0 +1,350 @@
+//! Structured diff between two banks, e.g. vanilla and modded.
+
+use indexmap::IndexMap;
+use re_sound::bnk::{Bnk, SectionPayload};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use super::hirc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeStatus {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BnkDiff {
+    /// Sections other than DIDX, DATA and HIRC.
+    pub sections: Vec<SectionChange>,
+    pub media: Vec<MediaChange>,
+    pub hirc: Vec<HircChange>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionChange {
+    pub magic: String,
+    pub status: ChangeStatus,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaChange {
+    pub id: u32,
+    pub status: ChangeStatus,
+    pub old_size: Option<u32>,
+    pub new_size: Option<u32>,
+    pub size_delta: i64,
+    /// Sha256 of DATA payload, missing if DATA is not loaded.
+    pub old_hash: Option<String>,
+    pub new_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HircChange {
+    pub id: u32,
+    pub entry_type: String,
+    pub status: ChangeStatus,
+    /// Changed fields, empty for added and removed entries.
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    /// JSON pointer of the field inside the entry.
+    pub pointer: String,
+    /// `null` if the field is added.
+    pub old: Value,
+    /// `null` if the field is removed.
+    pub new: Value,
+}
+
+/// Embedded wem in DIDX, with payload if DATA is loaded.
+pub struct Media<'a> {
+    pub length: u32,
+    pub data: Option<&'a [u8]>,
+}
+
+impl Media<'_> {
+    fn size(&self) -> u32 {
+        self.data.map(|d| d.len() as u32).unwrap_or(self.length)
+    }
+}
+
+pub fn diff(old: &Bnk, new: &Bnk) -> eyre::Result<BnkDiff> {
+    Ok(BnkDiff {
+        sections: diff_sections(old, new)?,
+        media: diff_media(&media_map(old), &media_map(new)),
+        hirc: diff_hirc(&hirc_map(old)?, &hirc_map(new)?),
+    })
+}
+
+/// Embedded wems by id, in DIDX order.
+pub fn media_map(bnk: &Bnk) -> IndexMap<u32, Media<'_>> {
+    let mut didx = None;
+    let mut data = None;
+    for section in bnk.sections.iter() {
+        match &section.payload {
+            SectionPayload::Didx { entries } => didx = Some(entries),
+            SectionPayload::Data { data_list } => data = Some(data_list),
+            _ => {}
+        }
+    }
+    let Some(didx) = didx else {
+        return IndexMap::new();
+    };
+    // DATA may be stripped when loaded with section filter
+    let data = data.filter(|d| d.len() == didx.len());
+
+    didx.iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let media = Media {
+                length: entry.length,
+                data: data.map(|d| d[i].as_slice()),
+            };
+            (entry.id, media)
+        })
+        .collect()
+}
+
+/// Serialized HIRC entries by id, in bank order.
+pub fn hirc_map(bnk: &Bnk) -> eyre::Result<IndexMap<u32, Value>> {
+    Ok(hirc::entries(bnk)?
+        .into_iter()
+        .filter_map(|entry| Some((hirc::entry_id(&entry)?, entry)))
+        .collect())
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn diff_sections(old: &Bnk, new: &Bnk) -> eyre::Result<Vec<SectionChange>> {
+    let payloads = |bnk: &Bnk| -> eyre::Result<IndexMap<[u8; 4], Value>> {
+        let mut map = IndexMap::new();
+        for section in bnk.sections.iter() {
+            if matches!(
+                section.payload,
+                SectionPayload::Didx { .. }
+                    | SectionPayload::Data { .. }
+                    | SectionPayload::Hirc { .. }
+            ) {
+                continue;
+            }
+            map.insert(section.magic, serde_json::to_value(&section.payload)?);
+        }
+        Ok(map)
+    };
+    let old = payloads(old)?;
+    let new = payloads(new)?;
+
+    let mut changes = vec![];
+    for (magic, payload) in new.iter() {
+        let status = match old.get(magic) {
+            None => ChangeStatus::Added,
+            Some(old_payload) if old_payload != payload => ChangeStatus::Changed,
+            Some(_) => continue,
+        };
+        changes.push(SectionChange {
+            magic: String::from_utf8_lossy(magic).to_string(),
+            status,
+        });
+    }
+    for magic in old.keys().filter(|magic| !new.contains_key(*magic)) {
+        changes.push(SectionChange {
+            magic: String::from_utf8_lossy(magic).to_string(),
+            status: ChangeStatus::Removed,
+        });
+    }
+    Ok(changes)
+}
+
+fn diff_media(old: &IndexMap<u32, Media>, new: &IndexMap<u32, Media>) -> Vec<MediaChange> {
+    let hash = |media: Option<&Media>| media.and_then(|m| m.data).map(sha256_hex);
+
+    let mut changes = vec![];
+    for (&id, media) in new.iter() {
+        let old_media = old.get(&id);
+        let status = match old_media {
+            None => ChangeStatus::Added,
+            Some(old_media) => {
+                let same = match (old_media.data, media.data) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => old_media.size() == media.size(),
+                };
+                if same {
+                    continue;
+                }
+                ChangeStatus::Changed
+            }
+        };
+        let old_size = old_media.map(|m| m.size());
+        changes.push(MediaChange {
+            id,
+            status,
+            old_size,
+            new_size: Some(media.size()),
+            size_delta: media.size() as i64 - old_size.unwrap_or_default() as i64,
+            old_hash: hash(old_media),
+            new_hash: hash(Some(media)),
+        });
+    }
+    for (&id, media) in old.iter().filter(|(id, _)| !new.contains_key(*id)) {
+        changes.push(MediaChange {
+            id,
+            status: ChangeStatus::Removed,
+            old_size: Some(media.size()),
+            new_size: None,
+            size_delta: -(media.size() as i64),
+            old_hash: hash(Some(media)),
+            new_hash: None,
+        });
+    }
+    changes
+}
+
+fn diff_hirc(old: &IndexMap<u32, Value>, new: &IndexMap<u32, Value>) -> Vec<HircChange> {
+    let mut changes = vec![];
+    for (&id, entry) in new.iter() {
+        let (status, fields) = match old.get(&id) {
+            None => (ChangeStatus::Added, vec![]),
+            Some(old_entry) => {
+                let fields = diff_values(old_entry, entry);
+                if fields.is_empty() {
+                    continue;
+                }
+                (ChangeStatus::Changed, fields)
+            }
+        };
+        changes.push(HircChange {
+            id,
+            entry_type: hirc::entry_type(entry).to_string(),
+            status,
+            fields,
+        });
+    }
+    for (&id, entry) in old.iter().filter(|(id, _)| !new.contains_key(*id)) {
+        changes.push(HircChange {
+            id,
+            entry_type: hirc::entry_type(entry).to_string(),
+            status: ChangeStatus::Removed,
+            fields: vec![],
+        });
+    }
+    changes
+}
+
+/// Field level changes between two json values.
+///
+/// Arrays are compared item by item only if lengths are equal,
+/// otherwise the whole array is reported. Raw byte arrays are
+/// always reported as a whole.
+pub fn diff_values(old: &Value, new: &Value) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    walk_diff(old, new, &mut String::new(), &mut changes);
+    changes
+}
+
+fn walk_diff(old: &Value, new: &Value, pointer: &mut String, changes: &mut Vec<FieldChange>) {
+    if old == new {
+        return;
+    }
+    let mut visit = |key: &str, old: &Value, new: &Value, changes: &mut Vec<FieldChange>| {
+        let len = pointer.len();
+        pointer.push('/');
+        pointer.push_str(key);
+        walk_diff(old, new, pointer, changes);
+        pointer.truncate(len);
+    };
+
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, new_value) in new_map.iter() {
+                let old_value = old_map.get(key).unwrap_or(&Value::Null);
+                visit(key, old_value, new_value, changes);
+            }
+            for (key, old_value) in old_map.iter() {
+                if !new_map.contains_key(key) {
+                    visit(key, old_value, &Value::Null, changes);
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items))
+            if old_items.len() == new_items.len() && !is_byte_array(old_items) =>
+        {
+            for (i, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                visit(&i.to_string(), old_item, new_item, changes);
+            }
+        }
+        _ => changes.push(FieldChange {
+            pointer: pointer.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+/// Apply a field change, `null` removes the field.
+pub fn apply_change(entry: &mut Value, change: &FieldChange) {
+    let Some((parent, key)) = change.pointer.rsplit_once('/') else {
+        *entry = change.new.clone();
+        return;
+    };
+    match entry.pointer_mut(parent) {
+        Some(Value::Object(map)) if change.new.is_null() => {
+            map.remove(key);
+        }
+        Some(Value::Object(map)) => {
+            map.insert(key.to_string(), change.new.clone());
+        }
+        Some(Value::Array(items)) => {
+            if let Some(item) = key.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                *item = change.new.clone();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_byte_array(items: &[Value]) -> bool {
+    items.first().is_some_and(|v| v.is_number())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_values() {
+        let old = json!({
+            "id": 1,
+            "playlist": [{ "play_at": 0.0 }, { "play_at": 10.0 }],
+            "sources": [1, 2],
+            "data": [0, 1, 2],
+        });
+        let new = json!({
+            "id": 1,
+            "playlist": [{ "play_at": 0.0 }, { "play_at": 20.0 }],
+            "sources": [1, 2, 3],
+            "data": [0, 1, 3],
+        });
+
+        let mut pointers: Vec<_> = diff_values(&old, &new)
+            .into_iter()
+            .map(|c| c.pointer)
+            .collect();
+        pointers.sort();
+        assert_eq!(pointers, ["/data", "/playlist/1/play_at", "/sources"]);
+        assert!(diff_values(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+//! Resolve what an event can play, down to wem sources.
+//!
+//! Event, EventAction and music objects are walked through their decoded
+//! fields. Other containers are only available as raw `data`, their children
+//! are read from it after the node params, and marked as inferred in the
+//! result.
+
+use std::collections::{HashMap, HashSet};
+
+use indexmap::{IndexMap, IndexSet};
+use re_sound::bnk::Bnk;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{
+    diff,
+    hirc::{self, RefKind},
+};
+
+/// Entry types of the actor-mixer and music hierarchies.
+const HIERARCHY_TYPES: &[&str] = &[
+    "Sound",
+    "RandomOrSequenceContainer",
+    "SwitchContainer",
+    "ActorMixer",
+    "BlendContainer",
+    "MusicSegment",
+    "MusicTrack",
+    "MusicSwitchContainer",
+    "MusicRanSeqCntr",
+];
+/// Containers only available as raw `data`, with the size of their own
+/// params between node params and the children list.
+const RAW_CONTAINERS: &[(&str, usize)] = &[
+    // loop count and modifiers, transition times, avoid repeat, modes
+    ("RandomOrSequenceContainer", 24),
+    // group type, group id, default switch, continuous validation
+    ("SwitchContainer", 10),
+    ("ActorMixer", 0),
+    ("BlendContainer", 0),
+    // music node flags come before node params instead
+    ("MusicSwitchContainer", 0),
+];
+/// Upper bound of children count when reading raw data.
+const MAX_RAW_CHILDREN: usize = 1024;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+    pub id: u32,
+    /// `None` if the object is not in this bank.
+    pub entry_type: Option<String>,
+    /// Whether the link from parent is inferred from raw data.
+    pub inferred: bool,
+    /// Wem sources played directly by this node.
+    pub media: Vec<u32>,
+    pub children: Vec<GraphNode>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventGraph {
+    pub root: GraphNode,
+    /// All wem sources reachable from the event.
+    pub media: Vec<u32>,
+    /// Objects referenced but not found in this bank.
+    pub missing: Vec<u32>,
+}
+
+/// Walk the event with `event_id` down to its wem sources.
+pub fn resolve_event(bnk: &Bnk, event_id: u32) -> eyre::Result<EventGraph> {
+    let entries = diff::hirc_map(bnk)?;
+    match entries.get(&event_id) {
+        Some(entry) if hirc::entry_type(entry) == "Event" => {}
+        Some(entry) => eyre::bail!(
+            "HIRC entry {} is a {}, not an Event",
+            event_id,
+            hirc::entry_type(entry)
+        ),
+        None => eyre::bail!("Event {} not found in bnk", event_id),
+    }
+
+    let mut resolver = Resolver::new(&entries);
+    let root = resolver.visit(event_id, false);
+    Ok(EventGraph {
+        root,
+        media: resolver.media.into_iter().collect(),
+        missing: resolver.missing.into_iter().collect(),
+    })
+}
+
+/// Events able to play each wem source, in HIRC order.
+pub fn media_events(bnk: &Bnk) -> eyre::Result<IndexMap<u32, Vec<u32>>> {
+    let entries = diff::hirc_map(bnk)?;
+    let mut resolver = Resolver::new(&entries);
+    let mut result: IndexMap<u32, Vec<u32>> = IndexMap::new();
+    for (&id, entry) in entries.iter() {
+        if hirc::entry_type(entry) != "Event" {
+            continue;
+        }
+        resolver.media.clear();
+        resolver.visit(id, false);
+        for &media in resolver.media.iter() {
+            result.entry(media).or_default().push(id);
+        }
+    }
+    Ok(result)
+}
+
+struct Resolver<'a> {
+    entries: &'a IndexMap<u32, Value>,
+    /// Inferred children of raw entries.
+    raw_children: HashMap<u32, Vec<u32>>,
+    path: HashSet<u32>,
+    media: IndexSet<u32>,
+    missing: IndexSet<u32>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(entries: &'a IndexMap<u32, Value>) -> Self {
+        let hierarchy: HashSet<u32> = entries
+            .iter()
+            .filter(|(_, entry)| HIERARCHY_TYPES.contains(&hirc::entry_type(entry)))
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut raw_children = HashMap::new();
+        for (&id, entry) in entries.iter() {
+            if let Some(children) = raw_children_of(id, entry, &hierarchy) {
+                raw_children.insert(id, children);
+            }
+        }
+
+        Resolver {
+            entries,
+            raw_children,
+            path: HashSet::new(),
+            media: Default::default(),
+            missing: Default::default(),
+        }
+    }
+
+    fn visit(&mut self, id: u32, inferred: bool) -> GraphNode {
+        let mut node = GraphNode {
+            id,
+            entry_type: None,
+            inferred,
+            media: vec![],
+            children: vec![],
+        };
+        let Some(entry) = self.entries.get(&id) else {
+            self.missing.insert(id);
+            return node;
+        };
+        node.entry_type = Some(hirc::entry_type(entry).to_string());
+        // guard against cycles
+        if !self.path.insert(id) {
+            return node;
+        }
+
+        let mut children = vec![];
+        for reference in hirc::references(entry) {
+            match reference.kind {
+                RefKind::Media if !node.media.contains(&reference.id) => {
+                    node.media.push(reference.id)
+                }
+                RefKind::Action | RefKind::Child => children.push((reference.id, false)),
+                RefKind::Target if hirc::targets_object(entry) => {
+                    children.push((reference.id, false))
+                }
+                _ => {}
+            }
+        }
+        if let Some(raw_children) = self.raw_children.get(&id) {
+            children.extend(raw_children.iter().map(|&child| (child, true)));
+        }
+
+        self.media.extend(node.media.iter().copied());
+        let mut seen = HashSet::new();
+        for (child, inferred) in children {
+            if seen.insert(child) {
+                node.children.push(self.visit(child, inferred));
+            }
+        }
+
+        self.path.remove(&id);
+        node
+    }
+}
+
+/// Children of a container only available as raw `data`.
+fn raw_children_of(id: u32, entry: &Value, hierarchy: &HashSet<u32>) -> Option<Vec<u32>> {
+    let entry_type = hirc::entry_type(entry);
+    let &(_, params_size) = RAW_CONTAINERS.iter().find(|(ty, _)| *ty == entry_type)?;
+    let data = raw_data(entry)?;
+
+    let mut reader = Reader::new(&data);
+    if entry_type == "MusicSwitchContainer" {
+        reader.skip(1)?;
+    }
+    read_children(id, &mut reader, params_size, hierarchy)
+}
+
+/// Raw `data` bytes of an entry.
+fn raw_data(entry: &Value) -> Option<Vec<u8>> {
+    entry
+        .get("data")?
+        .as_array()?
+        .iter()
+        .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect()
+}
+
+/// Read the children list following node params and `params_size` bytes
+/// of container params.
+///
+/// The list is dropped unless all ids are distinct hierarchy objects of the
+/// bank, so a misread layout does not show up as children.
+fn read_children(
+    id: u32,
+    reader: &mut Reader,
+    params_size: usize,
+    hierarchy: &HashSet<u32>,
+) -> Option<Vec<u32>> {
+    skip_node_base_params(reader)?;
+    reader.skip(params_size)?;
+    let count = reader.u32()? as usize;
+    if count > MAX_RAW_CHILDREN {
+        return None;
+    }
+    let children = (0..count)
+        .map(|_| reader.u32())
+        .collect::<Option<Vec<u32>>>()?;
+
+    let unique: HashSet<_> = children.iter().collect();
+    let valid = unique.len() == children.len()
+        && children
+            .iter()
+            .all(|child| *child != id && hierarchy.contains(child));
+    valid.then_some(children)
+}
+
+/// Skip NodeBaseParams, laid out as decoded by re-sound for music objects.
+/// Effect chains are not decoded there, nodes with them are not read.
+fn skip_node_base_params(reader: &mut Reader) -> Option<()> {
+    // fx params: override parent, fx count
+    reader.skip(1)?;
+    if reader.u8()? != 0 {
+        return None;
+    }
+    // metadata: override parent, fx count, then index, fx id and share set
+    reader.skip(1)?;
+    let num_metadata = reader.u8()? as usize;
+    reader.skip(num_metadata * 6)?;
+    // override attachment params, override bus, direct parent, priority bits
+    reader.skip(1 + 4 + 4 + 1)?;
+
+    // prop bundle of values, then of ranged modifiers
+    let num_props = reader.u8()? as usize;
+    reader.skip(num_props + num_props * 4)?;
+    let num_ranged = reader.u8()? as usize;
+    reader.skip(num_ranged + num_ranged * 8)?;
+
+    // positioning
+    let bits_positioning = reader.u8()?;
+    let has_positioning = bits_positioning & 0x01 != 0;
+    let has_3d = bits_positioning & 0x02 != 0;
+    if has_positioning && has_3d {
+        reader.skip(1)?;
+        let position_type = (bits_positioning >> 5) & 0x03;
+        if position_type != 0 {
+            // path mode, transition time
+            reader.skip(1 + 4)?;
+            let num_vertices = reader.u32()? as usize;
+            reader.skip(num_vertices * 16)?;
+            let num_items = reader.u32()? as usize;
+            // item offsets, then ranges of each item
+            reader.skip(num_items * 8 + num_items * 12)?;
+        }
+    }
+
+    // aux params
+    let bits_aux = reader.u8()?;
+    if bits_aux & 0x08 != 0 {
+        reader.skip(4 * 4)?;
+    }
+    reader.skip(4)?;
+    // advanced settings
+    reader.skip(6)?;
+
+    // state chunk
+    let num_state_props = reader.var()?;
+    for _ in 0..num_state_props {
+        reader.var()?;
+        reader.skip(2)?;
+    }
+    let num_state_groups = reader.var()?;
+    for _ in 0..num_state_groups {
+        reader.skip(4 + 1)?;
+        let num_states = reader.var()? as usize;
+        reader.skip(num_states * 8)?;
+    }
+
+    // initial rtpc
+    let num_curves = reader.u16()?;
+    for _ in 0..num_curves {
+        reader.skip(4 + 1 + 1)?;
+        reader.var()?;
+        reader.skip(4 + 1)?;
+        let num_points = reader.u16()? as usize;
+        reader.skip(num_points * 12)?;
+    }
+    Some(())
+}
+
+/// Little endian reader over raw entry data, `None` past the end.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    /// Variable length integer, 7 bits per byte, high bits first.
+    fn var(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..5 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// NodeBaseParams without effects, positioning, states or rtpc.
+    fn node_base_params(parent: u32) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 0, 0];
+        data.extend(0u32.to_le_bytes());
+        data.extend(parent.to_le_bytes());
+        data.push(0);
+        // one volume prop, no ranged modifiers
+        data.extend([1, 0x00]);
+        data.extend((-3.0f32).to_le_bytes());
+        data.push(0);
+        // positioning, aux with reflections bus, advanced settings
+        data.push(0);
+        data.push(0);
+        data.extend(0u32.to_le_bytes());
+        data.extend([0; 6]);
+        // one state group with a state, no rtpc
+        data.push(0);
+        data.push(1);
+        data.extend(7u32.to_le_bytes());
+        data.push(0);
+        data.push(1);
+        data.extend([0; 8]);
+        data.extend(0u16.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_read_children() {
+        let hierarchy = HashSet::from([100, 200, 300]);
+        let mut data = node_base_params(300);
+        data.extend(2u32.to_le_bytes());
+        data.extend(100u32.to_le_bytes());
+        data.extend(200u32.to_le_bytes());
+
+        let children = read_children(1, &mut Reader::new(&data), 0, &hierarchy);
+        assert_eq!(children, Some(vec![100, 200]));
+        let children = read_children(1, &mut Reader::new(&data[..data.len() - 1]), 0, &hierarchy);
+        assert_eq!(children, None);
+    }
+
+    #[test]
+    fn test_sound_children() {
+        // the parent id of a sound reads like a single child list
+        let mut sound_data = vec![0, 0];
+        sound_data.extend(1u32.to_le_bytes());
+        sound_data.extend(2u32.to_le_bytes());
+        let mut mixer_data = node_base_params(0);
+        mixer_data.extend(1u32.to_le_bytes());
+        mixer_data.extend(3u32.to_le_bytes());
+
+        let entries: IndexMap<u32, Value> = IndexMap::from([
+            (
+                2,
+                json!({ "entry_type": "ActorMixer", "id": 2, "data": mixer_data }),
+            ),
+            (
+                3,
+                json!({ "entry_type": "Sound", "id": 3, "source_id": 10, "data": sound_data }),
+            ),
+        ]);
+        let mut resolver = Resolver::new(&entries);
+        assert!(!resolver.raw_children.contains_key(&3));
+
+        let root = resolver.visit(2, false);
+        assert_eq!(root.children.len(), 1);
+        assert!(root.children[0].inferred);
+        assert!(root.children[0].children.is_empty());
+        assert_eq!(root.children[0].media, [10]);
+    }
+}
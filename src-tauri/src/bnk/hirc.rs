@@ -0,0 +1,470 @@
+//! HIRC entries accessed through their serialized form.
+//!
+//! The json shape is the same contract the frontend models rely on
+//! (`entry_type` tag with flattened fields), so backend passes can walk
+//! and patch every entry type uniformly.
+
+use std::io::Cursor;
+
+use re_sound::bnk::{Bnk, Section, SectionPayload};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// EventAction types targeting a hierarchy object.
+/// Others target buses, states or game parameters, which are not HIRC
+/// objects of the bank.
+const OBJECT_ACTIONS: &[(u64, &str)] = &[
+    (1, "Stop"),
+    (2, "Pause"),
+    (3, "Resume"),
+    (4, "Play"),
+    (6, "Mute"),
+    (7, "UnMute"),
+    (8, "SetVoicePitch"),
+    (9, "ResetVoicePitch"),
+    (10, "SetVoiceVolume"),
+    (11, "ResetVoiceVolume"),
+    (14, "SetVoiceLowPassFilter"),
+    (15, "ResetVoiceLowPassFilter"),
+    (24, "Break"),
+    (25, "Seek"),
+];
+
+/// Bytes written before the id of the only entry of a HIRC section:
+/// section magic and length, entry count, entry type and entry length.
+const ENTRY_HEADER_SIZE: usize = 4 + 4 + 4 + 1 + 4;
+
+/// Kind of an id referenced by a HIRC entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RefKind {
+    /// Wem source, embedded in DIDX or streamed.
+    Media,
+    /// EventAction triggered by an Event.
+    Action,
+    /// Object targeted by an EventAction.
+    Target,
+    /// Direct parent node in the hierarchy.
+    Parent,
+    /// Output bus override.
+    Bus,
+    /// Child node of a container.
+    Child,
+}
+
+/// An id referenced by a HIRC entry.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub kind: RefKind,
+    pub id: u32,
+    /// JSON pointer of the field inside the entry.
+    pub pointer: String,
+}
+
+/// Id of a serialized HIRC entry.
+pub fn entry_id(entry: &Value) -> Option<u32> {
+    entry.get("id")?.as_u64().map(|id| id as u32)
+}
+
+/// Type name of a serialized HIRC entry, like `MusicTrack`.
+pub fn entry_type(entry: &Value) -> &str {
+    entry
+        .get("entry_type")
+        .and_then(|t| t.as_str())
+        .unwrap_or("Unknown")
+}
+
+/// Whether a serialized EventAction targets a hierarchy object.
+pub fn targets_object(entry: &Value) -> bool {
+    let Some(action_type) = entry.get("action_type") else {
+        return false;
+    };
+    OBJECT_ACTIONS.iter().any(|(value, name)| {
+        action_type.as_u64() == Some(*value) || action_type.as_str() == Some(name)
+    })
+}
+
+/// Collect all non-zero ids referenced by a serialized entry.
+///
+/// Only fields decoded by re-sound are visible, references inside
+/// raw `data` payloads are not reported.
+pub fn references(entry: &Value) -> Vec<Reference> {
+    let mut refs = vec![];
+    if entry_type(entry) == "EventAction" {
+        push_reference(
+            &mut refs,
+            RefKind::Target,
+            entry.get("game_object_id"),
+            "/game_object_id",
+        );
+    }
+    walk_references(entry, &mut String::new(), &mut refs);
+    refs
+}
+
+fn walk_references(value: &Value, pointer: &mut String, refs: &mut Vec<Reference>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter() {
+                let len = pointer.len();
+                pointer.push('/');
+                pointer.push_str(key);
+                let kind = match key.as_str() {
+                    "source_id" => Some(RefKind::Media),
+                    "direct_parent_id" => Some(RefKind::Parent),
+                    "override_bus_id" => Some(RefKind::Bus),
+                    "action_ids" => Some(RefKind::Action),
+                    "children" if child.is_array() => Some(RefKind::Child),
+                    _ => None,
+                };
+                match (kind, child) {
+                    (Some(kind), Value::Array(ids)) => {
+                        for (i, id) in ids.iter().enumerate() {
+                            push_reference(refs, kind, Some(id), &format!("{}/{}", pointer, i));
+                        }
+                    }
+                    (Some(kind), _) => push_reference(refs, kind, Some(child), pointer),
+                    (None, _) => walk_references(child, pointer, refs),
+                }
+                pointer.truncate(len);
+            }
+        }
+        // skip raw byte arrays
+        Value::Array(items) if items.first().is_some_and(|v| !v.is_number()) => {
+            for (i, item) in items.iter().enumerate() {
+                let len = pointer.len();
+                pointer.push_str(&format!("/{}", i));
+                walk_references(item, pointer, refs);
+                pointer.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn push_reference(refs: &mut Vec<Reference>, kind: RefKind, id: Option<&Value>, pointer: &str) {
+    let Some(id) = id.and_then(|id| id.as_u64()) else {
+        return;
+    };
+    if id == 0 {
+        return;
+    }
+    refs.push(Reference {
+        kind,
+        id: id as u32,
+        pointer: pointer.to_string(),
+    });
+}
+
+/// Serialize all HIRC entries in bank order.
+pub fn entries(bnk: &Bnk) -> eyre::Result<Vec<Value>> {
+    let mut values = vec![];
+    for section in bnk.sections.iter() {
+        let SectionPayload::Hirc { entries } = &section.payload else {
+            continue;
+        };
+        for entry in entries.iter() {
+            values.push(serde_json::to_value(entry)?);
+        }
+    }
+    Ok(values)
+}
+
+/// Replace all HIRC entries with serialized entries.
+pub fn set_entries(bnk: &mut Bnk, values: Vec<Value>) -> eyre::Result<()> {
+    let Some(entries) = bnk.sections.iter_mut().find_map(|s| match &mut s.payload {
+        SectionPayload::Hirc { entries } => Some(entries),
+        _ => None,
+    }) else {
+        eyre::bail!("HIRC section not found.");
+    };
+    *entries = values
+        .into_iter()
+        .map(|value| {
+            let id = entry_id(&value).unwrap_or_default();
+            serde_json::from_value(value)
+                .map_err(|e| eyre::eyre!("Invalid HIRC entry {}: {}", id, e))
+        })
+        .collect::<eyre::Result<_>>()?;
+    Ok(())
+}
+
+/// Visit every HIRC entry as json, writing back the entries
+/// for which `visit` returns `true`.
+pub fn edit_entries<F>(bnk: &mut Bnk, mut visit: F) -> eyre::Result<()>
+where
+    F: FnMut(&mut Value) -> eyre::Result<bool>,
+{
+    for section in bnk.sections.iter_mut() {
+        let SectionPayload::Hirc { entries } = &mut section.payload else {
+            continue;
+        };
+        for entry in entries.iter_mut() {
+            let mut value = serde_json::to_value(&*entry)?;
+            if visit(&mut value)? {
+                let id = entry_id(&value).unwrap_or_default();
+                *entry = serde_json::from_value(value)
+                    .map_err(|e| eyre::eyre!("Invalid HIRC entry {} after edit: {}", id, e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set a field of a serialized entry by JSON pointer, e.g.
+/// `/music_track_initial_values/playlist/0/play_at`.
+///
+/// The field must already exist, and arrays or objects must keep their
+/// length and keys, so this never changes the entry layout.
+pub fn set_field(entry: &mut Value, pointer: &str, value: Value) -> eyre::Result<()> {
+    let id = entry_id(entry).unwrap_or_default();
+    let Some(target) = entry.pointer_mut(pointer) else {
+        eyre::bail!("Field '{}' not found in HIRC entry {}", pointer, id);
+    };
+    if std::mem::discriminant(target) != std::mem::discriminant(&value) {
+        eyre::bail!(
+            "Type mismatch for field '{}' in HIRC entry {}: expected {}, got {}",
+            pointer,
+            id,
+            json_type(target),
+            json_type(&value)
+        );
+    }
+    if !same_layout(target, &value) {
+        eyre::bail!(
+            "Field '{}' in HIRC entry {} can not change its layout, \
+             arrays must keep their length and objects their keys",
+            pointer,
+            id
+        );
+    }
+    *target = value;
+    Ok(())
+}
+
+/// Whether both values have the same types, array lengths and object keys.
+fn same_layout(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_layout(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| same_layout(a, b)))
+        }
+        _ => std::mem::discriminant(a) == std::mem::discriminant(b),
+    }
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Size of a serialized entry when written by re-sound, which is the value
+/// of its `length` field: the id and everything after it.
+pub fn entry_length(entry: &Value) -> eyre::Result<u32> {
+    let id = entry_id(entry).unwrap_or_default();
+    let entry = serde_json::from_value(entry.clone())
+        .map_err(|e| eyre::eyre!("Invalid HIRC entry {}: {}", id, e))?;
+    let mut bnk = Bnk {
+        sections: vec![Section {
+            magic: *b"HIRC",
+            section_length: 0,
+            payload: SectionPayload::Hirc {
+                entries: vec![entry],
+            },
+        }],
+    };
+    let mut writer = Cursor::new(vec![]);
+    bnk.write_to(&mut writer)?;
+    let written = writer.into_inner().len();
+    written
+        .checked_sub(ENTRY_HEADER_SIZE)
+        .map(|length| length as u32)
+        .ok_or_else(|| eyre::eyre!("Failed to measure HIRC entry {}", id))
+}
+
+/// Set count fields to the length of their arrays and `length` to the
+/// written size, after an edit adding or removing array items.
+pub fn update_sizes(entry: &mut Value) -> eyre::Result<()> {
+    update_counts(entry);
+    let length = entry_length(entry)?;
+    if let Some(field) = entry.get_mut("length") {
+        *field = length.into();
+    }
+    Ok(())
+}
+
+/// Array counted by a count field, e.g. `sources` of `num_sources`
+/// and `playlist` of `num_playlist_items`.
+pub fn counted_array<'a>(map: &'a Map<String, Value>, key: &str) -> Option<&'a Vec<Value>> {
+    let name = key.strip_prefix("num_")?;
+    [Some(name), name.strip_suffix("_items")]
+        .into_iter()
+        .flatten()
+        .find_map(|name| map.get(name)?.as_array())
+}
+
+/// Set count fields to the length of their arrays.
+fn update_counts(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            let counts: Vec<(String, usize)> = map
+                .keys()
+                .filter(|key| map[key.as_str()].is_number())
+                .filter_map(|key| Some((key.clone(), counted_array(map, key)?.len())))
+                .collect();
+            for (key, count) in counts {
+                map.insert(key, count.into());
+            }
+            map.values_mut().for_each(update_counts);
+        }
+        Value::Array(items) => items.iter_mut().for_each(update_counts),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A MusicTrack playing each source once, with valid sizes.
+    pub fn music_track(id: u32, sources: &[u32]) -> Value {
+        let empty_bundle = json!({ "num_props": 0, "props": [] });
+        let mut entry = json!({
+            "entry_type": "MusicTrack",
+            "length": 0,
+            "id": id,
+            "music_track_initial_values": {
+                "flags": 0,
+                "num_sources": 0,
+                "sources": sources.iter().map(|&source_id| json!({
+                    "plugin_id": 0x40001,
+                    "stream_type": 0,
+                    "media_information": {
+                        "source_id": source_id,
+                        "in_memory_media_size": 1000,
+                        "source_bits": 0,
+                    },
+                })).collect::<Vec<_>>(),
+                "num_playlist_items": 0,
+                "playlist": sources.iter().map(|&source_id| json!({
+                    "track_id": 0,
+                    "source_id": source_id,
+                    "event_id": 0,
+                    "play_at": 0.0,
+                    "begin_trim_offset": 0.0,
+                    "end_trim_offset": 0.0,
+                    "src_duration": 1000.0,
+                })).collect::<Vec<_>>(),
+                "num_sub_track": 1,
+                "num_clip_automations": 0,
+                "clip_automations": [],
+                "node_base_params": {
+                    "node_initial_fx_params": { "is_override_parent_fx": 0, "num_fx": 0 },
+                    "is_override_parent_metadata": 0,
+                    "num_fx": 0,
+                    "override_attachment_params": 0,
+                    "override_bus_id": 0,
+                    "direct_parent_id": 0,
+                    "by_bit_vector": 0,
+                    "node_initial_params": {
+                        "ak_prop_bundle1": empty_bundle,
+                        "ak_prop_bundle2": empty_bundle,
+                    },
+                    "positioning_params": {
+                        "bits_positioning": 0,
+                        "bits_3d": 0,
+                        "is_dynamic": 0,
+                        "e_path_mode": 0,
+                        "transition_time": 0,
+                        "vertices": [],
+                        "play_list_items": [],
+                        "params": [],
+                    },
+                    "aux_params": { "by_bit_vector": 0, "reflections_aux_bus": 0 },
+                    "adv_settings_params": {
+                        "by_bit_vector": 0,
+                        "virtual_queue_behavior": 0,
+                        "max_num_instance": 0,
+                        "below_threshold_behavior": 0,
+                        "by_bit_vector2": 0,
+                    },
+                    "state_chunk": {
+                        "num_state_props": 0,
+                        "state_props": [],
+                        "num_state_groups": 0,
+                        "state_groups": [],
+                    },
+                    "initial_rtpc": { "num_curves": 0, "curves": [] },
+                },
+                "track_type": 0,
+                "look_ahead_time": 0,
+            },
+        });
+        update_sizes(&mut entry).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_update_sizes() {
+        let one = music_track(1, &[10]);
+        let two = music_track(1, &[10, 20]);
+        assert_eq!(two["music_track_initial_values"]["num_sources"], 2);
+        assert_eq!(two["music_track_initial_values"]["num_playlist_items"], 2);
+        assert!(two["length"].as_u64() > one["length"].as_u64());
+
+        // adding the items back by hand gives the same sizes
+        let mut entry = one.clone();
+        let values = &mut entry["music_track_initial_values"];
+        values["sources"] = two["music_track_initial_values"]["sources"].clone();
+        values["playlist"] = two["music_track_initial_values"]["playlist"].clone();
+        update_sizes(&mut entry).unwrap();
+        assert_eq!(entry, two);
+    }
+
+    #[test]
+    fn test_set_field() {
+        let mut entry = music_track(1, &[10, 20]);
+        set_field(
+            &mut entry,
+            "/music_track_initial_values/playlist/1/play_at",
+            json!(500.0),
+        )
+        .unwrap();
+        assert_eq!(
+            entry["music_track_initial_values"]["playlist"][1]["play_at"],
+            500.0
+        );
+
+        let sources = entry["music_track_initial_values"]["sources"].clone();
+        let mut swapped = sources.clone();
+        swapped.as_array_mut().unwrap().reverse();
+        set_field(&mut entry, "/music_track_initial_values/sources", swapped).unwrap();
+
+        // layout changes are rejected
+        let pointer = "/music_track_initial_values/sources";
+        assert!(set_field(&mut entry, pointer, json!([sources[0]])).is_err());
+        assert!(
+            set_field(
+                &mut entry,
+                &format!("{pointer}/0"),
+                json!({ "plugin_id": 0 })
+            )
+            .is_err()
+        );
+        assert!(set_field(&mut entry, &format!("{pointer}/0/stream_type"), json!("0")).is_err());
+        assert!(set_field(&mut entry, "/missing", json!(0)).is_err());
+    }
+}
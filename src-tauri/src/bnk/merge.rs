@@ -0,0 +1,529 @@
+//! Three-way merge of banks modified from the same vanilla bank.
+//!
+//! Changes of both sides are applied on top of base. Media is merged per
+//! wem id, HIRC entries per field, other sections as a whole.
+
+use std::collections::HashSet;
+
+use indexmap::IndexMap;
+use re_sound::bnk::{Bnk, SectionPayload};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    diff::{self, FieldChange, apply_change},
+    hirc,
+};
+
+/// How to resolve a conflicting change.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    /// Keep base and report, nothing should be written.
+    #[default]
+    Fail,
+    Ours,
+    Theirs,
+}
+
+impl ConflictPolicy {
+    fn side(self) -> Option<Side> {
+        match self {
+            ConflictPolicy::Fail => None,
+            ConflictPolicy::Ours => Some(Side::Ours),
+            ConflictPolicy::Theirs => Some(Side::Theirs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictKind {
+    /// Id is the section magic as u32 LE.
+    Section,
+    Media,
+    Hirc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeConflict {
+    pub kind: ConflictKind,
+    pub id: u32,
+    /// JSON pointer of the conflicting HIRC field, empty for the whole object.
+    pub pointer: String,
+    /// Value of each side, `null` if removed. Media is shown by sha256.
+    pub ours: Value,
+    pub theirs: Value,
+    /// Side taken, `None` if unresolved.
+    pub resolution: Option<Side>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeReport {
+    pub media_changed: usize,
+    pub hirc_changed: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergeReport {
+    pub fn has_unresolved(&self) -> bool {
+        self.conflicts.iter().any(|c| c.resolution.is_none())
+    }
+}
+
+/// Merge changes of `ours` and `theirs` into `base`.
+///
+/// Unresolved conflicts keep the base value, check
+/// [`MergeReport::has_unresolved`] before saving the result.
+pub fn merge(
+    base: &mut Bnk,
+    ours: &Bnk,
+    theirs: &Bnk,
+    policy: ConflictPolicy,
+) -> eyre::Result<MergeReport> {
+    let mut report = MergeReport::default();
+    merge_sections(base, ours, theirs, policy, &mut report)?;
+    merge_media(base, ours, theirs, policy, &mut report)?;
+    merge_hirc(base, ours, theirs, policy, &mut report)?;
+    Ok(report)
+}
+
+/// Pick the merged value of a single item, `None` means conflict.
+fn pick<'a, T: PartialEq>(base: &'a T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn resolve<'a, T>(policy: ConflictPolicy, ours: &'a T, theirs: &'a T) -> Option<(Side, &'a T)> {
+    match policy.side()? {
+        Side::Ours => Some((Side::Ours, ours)),
+        Side::Theirs => Some((Side::Theirs, theirs)),
+    }
+}
+
+fn merge_sections(
+    base: &mut Bnk,
+    ours: &Bnk,
+    theirs: &Bnk,
+    policy: ConflictPolicy,
+    report: &mut MergeReport,
+) -> eyre::Result<()> {
+    let payload = |bnk: &Bnk, magic: &[u8; 4]| -> eyre::Result<Value> {
+        match bnk.sections.iter().find(|s| &s.magic == magic) {
+            Some(section) => Ok(serde_json::to_value(&section.payload)?),
+            None => Ok(Value::Null),
+        }
+    };
+
+    for section in base.sections.iter_mut() {
+        if matches!(
+            section.payload,
+            SectionPayload::Didx { .. } | SectionPayload::Data { .. } | SectionPayload::Hirc { .. }
+        ) {
+            continue;
+        }
+        let base_payload = serde_json::to_value(&section.payload)?;
+        let ours_payload = payload(ours, &section.magic)?;
+        let theirs_payload = payload(theirs, &section.magic)?;
+
+        let merged = match pick(&base_payload, &ours_payload, &theirs_payload) {
+            Some(merged) => Some(merged),
+            None => {
+                let resolved = resolve(policy, &ours_payload, &theirs_payload);
+                report.conflicts.push(MergeConflict {
+                    kind: ConflictKind::Section,
+                    id: u32::from_le_bytes(section.magic),
+                    pointer: String::new(),
+                    ours: ours_payload.clone(),
+                    theirs: theirs_payload.clone(),
+                    resolution: resolved.map(|(side, _)| side),
+                });
+                resolved.map(|(_, merged)| merged)
+            }
+        };
+        // removing sections is not supported, keep base
+        if let Some(merged) = merged.filter(|m| !m.is_null() && **m != base_payload) {
+            section.payload = serde_json::from_value(merged.clone())?;
+        }
+    }
+    Ok(())
+}
+
+fn merge_media(
+    base: &mut Bnk,
+    ours: &Bnk,
+    theirs: &Bnk,
+    policy: ConflictPolicy,
+    report: &mut MergeReport,
+) -> eyre::Result<()> {
+    let ours_media = diff::media_map(ours);
+    let theirs_media = diff::media_map(theirs);
+    let base_media = diff::media_map(base);
+    let data_of = |map: &IndexMap<u32, diff::Media>, id: u32| -> eyre::Result<Option<Vec<u8>>> {
+        let Some(media) = map.get(&id) else {
+            return Ok(None);
+        };
+        match media.data {
+            Some(data) => Ok(Some(data.to_vec())),
+            None => eyre::bail!("DATA section is required to merge media."),
+        }
+    };
+
+    // base order first, then additions of ours and theirs
+    let mut ids: Vec<u32> = base_media.keys().copied().collect();
+    let mut seen: HashSet<u32> = ids.iter().copied().collect();
+    for &id in ours_media.keys().chain(theirs_media.keys()) {
+        if seen.insert(id) {
+            ids.push(id);
+        }
+    }
+
+    let mut merged_media = vec![];
+    for id in ids {
+        let base_data = data_of(&base_media, id)?;
+        let ours_data = data_of(&ours_media, id)?;
+        let theirs_data = data_of(&theirs_media, id)?;
+
+        let merged = match pick(&base_data, &ours_data, &theirs_data) {
+            Some(merged) => merged,
+            None => {
+                let hash = |data: &Option<Vec<u8>>| {
+                    data.as_deref()
+                        .map(|d| Value::String(diff::sha256_hex(d)))
+                        .unwrap_or_default()
+                };
+                let resolved = resolve(policy, &ours_data, &theirs_data);
+                report.conflicts.push(MergeConflict {
+                    kind: ConflictKind::Media,
+                    id,
+                    pointer: String::new(),
+                    ours: hash(&ours_data),
+                    theirs: hash(&theirs_data),
+                    resolution: resolved.map(|(side, _)| side),
+                });
+                resolved.map(|(_, merged)| merged).unwrap_or(&base_data)
+            }
+        };
+        if *merged != base_data {
+            report.media_changed += 1;
+        }
+        if let Some(data) = merged {
+            merged_media.push((id, data.clone()));
+        }
+    }
+    if report.media_changed == 0 {
+        return Ok(());
+    }
+
+    super::set_media(base, merged_media);
+    Ok(())
+}
+
+fn merge_hirc(
+    base: &mut Bnk,
+    ours: &Bnk,
+    theirs: &Bnk,
+    policy: ConflictPolicy,
+    report: &mut MergeReport,
+) -> eyre::Result<()> {
+    let base_entries = diff::hirc_map(base)?;
+    let ours_entries = diff::hirc_map(ours)?;
+    let theirs_entries = diff::hirc_map(theirs)?;
+
+    let mut ids: Vec<u32> = base_entries.keys().copied().collect();
+    let mut seen: HashSet<u32> = ids.iter().copied().collect();
+    for &id in ours_entries.keys().chain(theirs_entries.keys()) {
+        if seen.insert(id) {
+            ids.push(id);
+        }
+    }
+
+    let mut merged_entries = vec![];
+    for id in ids {
+        let base_entry = base_entries.get(&id).cloned().unwrap_or_default();
+        let ours_entry = ours_entries.get(&id).cloned().unwrap_or_default();
+        let theirs_entry = theirs_entries.get(&id).cloned().unwrap_or_default();
+
+        let merged = match pick(&base_entry, &ours_entry, &theirs_entry) {
+            Some(merged) => merged.clone(),
+            // both modified, merge by field
+            None if [&base_entry, &ours_entry, &theirs_entry]
+                .iter()
+                .all(|e| e.is_object()) =>
+            {
+                merge_entry(id, &base_entry, &ours_entry, &theirs_entry, policy, report)?
+            }
+            None => {
+                let resolved = resolve(policy, &ours_entry, &theirs_entry);
+                report.conflicts.push(MergeConflict {
+                    kind: ConflictKind::Hirc,
+                    id,
+                    pointer: String::new(),
+                    ours: ours_entry.clone(),
+                    theirs: theirs_entry.clone(),
+                    resolution: resolved.map(|(side, _)| side),
+                });
+                resolved
+                    .map(|(_, merged)| merged)
+                    .unwrap_or(&base_entry)
+                    .clone()
+            }
+        };
+        if merged != base_entry {
+            report.hirc_changed += 1;
+        }
+        if !merged.is_null() {
+            merged_entries.push(merged);
+        }
+    }
+    if report.hirc_changed == 0 {
+        return Ok(());
+    }
+
+    hirc::set_entries(base, merged_entries)
+}
+
+/// Merge field changes of an entry modified by both sides.
+///
+/// Derived size fields are left out and recomputed after merging, as
+/// unrelated changes of both sides would otherwise conflict on them.
+fn merge_entry(
+    id: u32,
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    policy: ConflictPolicy,
+    report: &mut MergeReport,
+) -> eyre::Result<Value> {
+    let changes = |side: &Value| -> Vec<FieldChange> {
+        diff::diff_values(base, side)
+            .into_iter()
+            .filter(|change| !is_derived(base, side, &change.pointer))
+            .collect()
+    };
+    let ours_changes = changes(ours);
+    let theirs_changes = changes(theirs);
+    let overlapping = |change: &FieldChange, others: &[FieldChange]| {
+        others
+            .iter()
+            .position(|other| overlaps(&change.pointer, &other.pointer))
+    };
+
+    let mut merged = base.clone();
+    for change in ours_changes.iter() {
+        let Some(index) = overlapping(change, &theirs_changes) else {
+            apply_change(&mut merged, change);
+            continue;
+        };
+        let other = &theirs_changes[index];
+        if other.pointer == change.pointer && other.new == change.new {
+            apply_change(&mut merged, change);
+            continue;
+        }
+        let resolution = policy.side();
+        if resolution == Some(Side::Ours) {
+            apply_change(&mut merged, change);
+        }
+        report.conflicts.push(MergeConflict {
+            kind: ConflictKind::Hirc,
+            id,
+            pointer: change.pointer.clone(),
+            ours: change.new.clone(),
+            theirs: other.new.clone(),
+            resolution,
+        });
+    }
+    for change in theirs_changes.iter() {
+        if overlapping(change, &ours_changes).is_none() || policy == ConflictPolicy::Theirs {
+            apply_change(&mut merged, change);
+        }
+    }
+
+    hirc::update_sizes(&mut merged)?;
+    Ok(merged)
+}
+
+/// Whether the field is derived from others: the entry length, or a count
+/// field mirrored by an array.
+fn is_derived(base: &Value, side: &Value, pointer: &str) -> bool {
+    if pointer == "/length" {
+        return true;
+    }
+    let Some((parent, key)) = pointer.rsplit_once('/') else {
+        return false;
+    };
+    [base, side].iter().any(|entry| {
+        entry
+            .pointer(parent)
+            .and_then(Value::as_object)
+            .is_some_and(|map| hirc::counted_array(map, key).is_some())
+    })
+}
+
+/// Whether one pointer is the same as or inside the other.
+fn overlaps(a: &str, b: &str) -> bool {
+    let inside = |inner: &str, outer: &str| {
+        inner
+            .strip_prefix(outer)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    };
+    inside(a, b) || inside(b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::bnk::hirc::tests::music_track;
+
+    /// Set fields of the MusicTrack initial values and update sizes.
+    fn edit_track(mut entry: Value, fields: &[(&str, Value)]) -> Value {
+        let values = &mut entry["music_track_initial_values"];
+        for (pointer, value) in fields {
+            *values.pointer_mut(pointer).unwrap() = value.clone();
+        }
+        hirc::update_sizes(&mut entry).unwrap();
+        entry
+    }
+
+    #[test]
+    fn test_merge_entry() {
+        let base = music_track(1, &[10]);
+        let ours = edit_track(
+            base.clone(),
+            &[("/flags", json!(1)), ("/playlist/0/play_at", json!(100.0))],
+        );
+        let theirs = edit_track(
+            base.clone(),
+            &[
+                ("/look_ahead_time", json!(5)),
+                ("/playlist/0/play_at", json!(200.0)),
+            ],
+        );
+
+        let mut report = MergeReport::default();
+        let merged =
+            merge_entry(1, &base, &ours, &theirs, ConflictPolicy::Fail, &mut report).unwrap();
+        let expected = edit_track(
+            base.clone(),
+            &[("/flags", json!(1)), ("/look_ahead_time", json!(5))],
+        );
+        assert_eq!(merged, expected);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(
+            report.conflicts[0].pointer,
+            "/music_track_initial_values/playlist/0/play_at"
+        );
+        assert!(report.has_unresolved());
+
+        let mut report = MergeReport::default();
+        let merged = merge_entry(
+            1,
+            &base,
+            &ours,
+            &theirs,
+            ConflictPolicy::Theirs,
+            &mut report,
+        )
+        .unwrap();
+        let expected = edit_track(expected, &[("/playlist/0/play_at", json!(200.0))]);
+        assert_eq!(merged, expected);
+        assert!(!report.has_unresolved());
+    }
+
+    #[test]
+    fn test_merge_entry_sizes() {
+        let base = music_track(1, &[10]);
+        let added = music_track(1, &[10, 20]);
+        let added_sources = added["music_track_initial_values"]["sources"].clone();
+        let added_playlist = added["music_track_initial_values"]["playlist"].clone();
+        // ours adds a source, theirs a playlist item
+        let ours = edit_track(base.clone(), &[("/sources", added_sources.clone())]);
+        let theirs = edit_track(base.clone(), &[("/playlist", added_playlist.clone())]);
+
+        let mut report = MergeReport::default();
+        let merged =
+            merge_entry(1, &base, &ours, &theirs, ConflictPolicy::Fail, &mut report).unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(merged, added);
+    }
+
+    #[test]
+    fn test_merge_entry_identical_sizes() {
+        // both sides add the same source, which counts once
+        let base = music_track(1, &[10]);
+        let added = music_track(1, &[10, 20]);
+        let ours = edit_track(added.clone(), &[("/flags", json!(1))]);
+        let theirs = edit_track(added.clone(), &[("/look_ahead_time", json!(5))]);
+
+        let mut report = MergeReport::default();
+        let merged =
+            merge_entry(1, &base, &ours, &theirs, ConflictPolicy::Fail, &mut report).unwrap();
+        assert!(report.conflicts.is_empty());
+        let expected = edit_track(
+            added.clone(),
+            &[("/flags", json!(1)), ("/look_ahead_time", json!(5))],
+        );
+        assert_eq!(merged, expected);
+        assert_eq!(merged["length"], added["length"]);
+
+        // taking theirs on conflicts does not count the shared change twice
+        let theirs = edit_track(added.clone(), &[("/flags", json!(2))]);
+        let mut report = MergeReport::default();
+        let merged = merge_entry(
+            1,
+            &base,
+            &ours,
+            &theirs,
+            ConflictPolicy::Theirs,
+            &mut report,
+        )
+        .unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(merged, theirs);
+        assert_eq!(merged["length"], added["length"]);
+    }
+
+    #[test]
+    fn test_merge_entry_partial_conflict_sizes() {
+        // ours adds a source and loses the conflict on flags
+        let base = music_track(1, &[10]);
+        let added = music_track(1, &[10, 20]);
+        let ours = edit_track(added.clone(), &[("/flags", json!(1))]);
+        let theirs = edit_track(base.clone(), &[("/flags", json!(2))]);
+
+        let mut report = MergeReport::default();
+        let merged =
+            merge_entry(1, &base, &ours, &theirs, ConflictPolicy::Fail, &mut report).unwrap();
+        assert!(report.has_unresolved());
+        assert_eq!(merged, added);
+
+        let mut report = MergeReport::default();
+        let merged = merge_entry(
+            1,
+            &base,
+            &ours,
+            &theirs,
+            ConflictPolicy::Theirs,
+            &mut report,
+        )
+        .unwrap();
+        assert!(!report.has_unresolved());
+        assert_eq!(merged, edit_track(added, &[("/flags", json!(2))]));
+    }
+}
@@ -0,0 +1,335 @@
+//! Helpers on top of `re_sound::bnk`, shared by commands and build pipelines.
+
+pub mod diff;
+pub mod graph;
+pub mod hirc;
+pub mod merge;
+pub mod query;
+pub mod text;
+pub mod track_sync;
+pub mod validate;
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use eyre::Context;
+use re_sound::bnk::{Bnk, DidxEntry, Section, SectionPayload};
+use serde::Serialize;
+use serde_json::Value;
+
+use self::{hirc::RefKind, track_sync::TrackSyncReport, validate::BankReport};
+
+/// Result of removing a wem from bnk.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaRemoval {
+    pub id: u32,
+    /// Whether the wem was embedded in DIDX/DATA and got removed.
+    pub removed: bool,
+    /// HIRC fields still pointing to the removed wem.
+    pub references: Vec<MediaReference>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaReference {
+    pub hirc_id: u32,
+    pub entry_type: String,
+    pub pointer: String,
+    /// Whether the reference was removed from the entry.
+    pub rewritten: bool,
+}
+
+/// Result of saving a bank, HIRC references are checked before writing.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveReport {
+    /// False if reference errors were found and saving was not forced.
+    pub saved: bool,
+    pub validation: BankReport,
+    pub track_sync: TrackSyncReport,
+}
+
+impl SaveReport {
+    /// Bank not written because of reference errors.
+    pub fn blocked(validation: BankReport) -> Self {
+        log::warn!(
+            "Bnk not written, {} errors found in HIRC references: {}",
+            validation.report.errors,
+            validation.path
+        );
+        Self {
+            saved: false,
+            validation,
+            track_sync: TrackSyncReport::default(),
+        }
+    }
+}
+
+pub fn load_file(path: impl AsRef<Path>) -> eyre::Result<Bnk> {
+    let path = path.as_ref();
+    let file = File::open(path).context(format!("Failed to open bnk: {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let bnk = Bnk::from_reader(&mut reader)
+        .context(format!("Failed to parse bnk: {}", path.display()))?;
+    Ok(bnk)
+}
+
+pub fn save_file(bnk: &mut Bnk, path: impl AsRef<Path>) -> eyre::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::create(path).context(format!("Failed to create bnk: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    bnk.write_to(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Get DIDX entries and DATA payloads, which are kept in the same order.
+pub fn media_sections(bnk: &mut Bnk) -> Option<(&mut Vec<DidxEntry>, &mut Vec<Vec<u8>>)> {
+    let mut didx = None;
+    let mut data = None;
+    for section in bnk.sections.iter_mut() {
+        match &mut section.payload {
+            SectionPayload::Didx { entries } => didx = Some(entries),
+            SectionPayload::Data { data_list } => data = Some(data_list),
+            _ => {}
+        }
+    }
+
+    Some((didx?, data?))
+}
+
+/// Replace the payload of an embedded wem, or append it as new media.
+///
+/// DIDX offsets and lengths are fixed by re-sound on write.
+pub fn replace_media(bnk: &mut Bnk, id: u32, data: Vec<u8>) -> eyre::Result<()> {
+    ensure_media_sections(bnk);
+    let (didx_entries, data_list) = media_sections(bnk).unwrap();
+    if didx_entries.len() != data_list.len() {
+        eyre::bail!(
+            "Number of DIDX entries ({}) and DATA entries ({}) do not match.",
+            didx_entries.len(),
+            data_list.len()
+        );
+    }
+
+    match didx_entries.iter().position(|e| e.id == id) {
+        Some(index) => data_list[index] = data,
+        None => {
+            didx_entries.push(DidxEntry {
+                id,
+                offset: 0,
+                length: 0,
+            });
+            data_list.push(data);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace all embedded wems, in DIDX order.
+pub fn set_media(bnk: &mut Bnk, media: Vec<(u32, Vec<u8>)>) {
+    ensure_media_sections(bnk);
+    let (didx_entries, data_list) = media_sections(bnk).unwrap();
+    didx_entries.clear();
+    data_list.clear();
+    for (id, data) in media {
+        // offsets are fixed by re-sound on write
+        didx_entries.push(DidxEntry {
+            id,
+            offset: 0,
+            length: data.len() as u32,
+        });
+        data_list.push(data);
+    }
+}
+
+/// Remove embedded wems and report HIRC references to them.
+///
+/// With `cleanup`, MusicTrack sources and playlist items using the wems
+/// are removed too. Sound objects can not be rewritten, their references
+/// are only reported.
+pub fn remove_media(bnk: &mut Bnk, ids: &[u32], cleanup: bool) -> eyre::Result<Vec<MediaRemoval>> {
+    let mut removals: Vec<MediaRemoval> = ids
+        .iter()
+        .map(|&id| MediaRemoval {
+            id,
+            removed: false,
+            references: vec![],
+        })
+        .collect();
+
+    if let Some((didx_entries, data_list)) = media_sections(bnk) {
+        // DATA may be stripped when loaded with section filter
+        let has_data = didx_entries.len() == data_list.len();
+        for removal in removals.iter_mut() {
+            if let Some(index) = didx_entries.iter().position(|e| e.id == removal.id) {
+                didx_entries.remove(index);
+                if has_data {
+                    data_list.remove(index);
+                }
+                removal.removed = true;
+            }
+        }
+    }
+
+    hirc::edit_entries(bnk, |entry| {
+        let hirc_id = hirc::entry_id(entry).unwrap_or_default();
+        let entry_type = hirc::entry_type(entry).to_string();
+        let mut changed = false;
+        for removal in removals.iter_mut() {
+            let references: Vec<_> = hirc::references(entry)
+                .into_iter()
+                .filter(|r| r.kind == RefKind::Media && r.id == removal.id)
+                .collect();
+            if references.is_empty() {
+                continue;
+            }
+
+            let rewritten =
+                cleanup && entry_type == "MusicTrack" && remove_track_source(entry, removal.id)?;
+            changed |= rewritten;
+            removal
+                .references
+                .extend(references.into_iter().map(|r| MediaReference {
+                    hirc_id,
+                    entry_type: entry_type.clone(),
+                    pointer: r.pointer,
+                    rewritten,
+                }));
+        }
+        Ok(changed)
+    })?;
+
+    Ok(removals)
+}
+
+/// Remove sources and playlist items of a serialized MusicTrack.
+fn remove_track_source(entry: &mut Value, id: u32) -> eyre::Result<bool> {
+    let Some(values) = entry.get_mut("music_track_initial_values") else {
+        return Ok(false);
+    };
+    let uses_source =
+        |v: &Value, pointer: &str| v.pointer(pointer).and_then(|id| id.as_u64()) == Some(id as u64);
+
+    let mut changed = false;
+    if let Some(Value::Array(sources)) = values.get_mut("sources") {
+        let before = sources.len();
+        sources.retain(|s| !uses_source(s, "/media_information/source_id"));
+        changed |= sources.len() != before;
+    }
+
+    let mut removed_items = vec![];
+    if let Some(Value::Array(playlist)) = values.get_mut("playlist") {
+        for (i, item) in playlist.iter().enumerate() {
+            if uses_source(item, "/source_id") {
+                removed_items.push(i as u64);
+            }
+        }
+        let mut index = 0;
+        playlist.retain(|_| {
+            index += 1;
+            !removed_items.contains(&(index - 1))
+        });
+        changed |= !removed_items.is_empty();
+    }
+
+    // clip automations are bound to playlist item index
+    if !removed_items.is_empty()
+        && let Some(Value::Array(automations)) = values.get_mut("clip_automations")
+    {
+        automations.retain(|a| {
+            let index = a.get("clip_index").and_then(|i| i.as_u64());
+            !index.is_some_and(|i| removed_items.contains(&i))
+        });
+        for automation in automations.iter_mut() {
+            if let Some(index) = automation.get("clip_index").and_then(|i| i.as_u64()) {
+                let shift = removed_items.iter().filter(|&&r| r < index).count() as u64;
+                automation["clip_index"] = (index - shift).into();
+            }
+        }
+    }
+
+    if changed {
+        hirc::update_sizes(entry)?;
+    }
+    Ok(changed)
+}
+
+/// Create empty DIDX and DATA sections if missing, right after BKHD.
+fn ensure_media_sections(bnk: &mut Bnk) {
+    let has_section = |bnk: &Bnk, magic: &[u8; 4]| bnk.sections.iter().any(|s| &s.magic == magic);
+
+    let mut insert_at = bnk
+        .sections
+        .iter()
+        .position(|s| &s.magic == b"BKHD")
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    if !has_section(bnk, b"DIDX") {
+        bnk.sections.insert(
+            insert_at,
+            Section {
+                magic: *b"DIDX",
+                section_length: 0,
+                payload: SectionPayload::Didx { entries: vec![] },
+            },
+        );
+    }
+    if let Some(didx_index) = bnk.sections.iter().position(|s| &s.magic == b"DIDX") {
+        insert_at = didx_index + 1;
+    }
+    if !has_section(bnk, b"DATA") {
+        bnk.sections.insert(
+            insert_at,
+            Section {
+                magic: *b"DATA",
+                section_length: 0,
+                payload: SectionPayload::Data { data_list: vec![] },
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_remove_track_source() {
+        let mut entry = hirc::tests::music_track(1, &[10, 20]);
+        let values = &mut entry["music_track_initial_values"];
+        let mut item = values["playlist"][1].clone();
+        item["play_at"] = json!(1000.0);
+        values["playlist"].as_array_mut().unwrap().push(item);
+        values["clip_automations"] = json!([
+            { "clip_index": 0, "auto_type": 0, "graph_points_count": 0, "graph_points": [] },
+            { "clip_index": 2, "auto_type": 0, "graph_points_count": 0, "graph_points": [] },
+        ]);
+        hirc::update_sizes(&mut entry).unwrap();
+        let length = entry["length"].as_u64().unwrap();
+
+        assert!(remove_track_source(&mut entry, 10).unwrap());
+        let values = &entry["music_track_initial_values"];
+        assert_eq!(values["num_sources"], 1);
+        assert_eq!(values["num_playlist_items"], 2);
+        assert_eq!(values["num_clip_automations"], 1);
+        assert_eq!(values["clip_automations"][0]["clip_index"], 1);
+        assert!(hirc::references(&entry).iter().all(|r| r.id == 20));
+        assert!(entry["length"].as_u64().unwrap() < length);
+        assert_eq!(entry["length"], hirc::entry_length(&entry).unwrap());
+        assert!(!remove_track_source(&mut entry, 10).unwrap());
+    }
+}
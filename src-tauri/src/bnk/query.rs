@@ -0,0 +1,148 @@
+//! Filtered and paged access to HIRC entries.
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::hirc::{self, RefKind};
+use crate::names::NameDb;
+
+/// Page size if no limit provided.
+const DEFAULT_LIMIT: usize = 200;
+
+/// All filters are optional and combined with AND.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HircQuery {
+    /// Entry types, e.g. `Event`, `MusicTrack`.
+    pub types: Option<Vec<String>>,
+    /// Inclusive id range.
+    pub id_min: Option<u32>,
+    pub id_max: Option<u32>,
+    /// Children of the object, by their parent field or the children
+    /// list of the parent.
+    pub parent_id: Option<u32>,
+    /// Case insensitive match on id, entry type or name.
+    pub text: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HircPage {
+    /// Count of all matched entries, regardless of paging.
+    pub total: usize,
+    pub entries: Vec<Value>,
+    /// Names of the entries and the ids they reference.
+    pub names: BTreeMap<u32, String>,
+}
+
+pub fn query(entries: &[Value], query: &HircQuery, names: &NameDb) -> HircPage {
+    let children: Option<HashSet<u32>> = query.parent_id.map(|parent_id| {
+        entries
+            .iter()
+            .filter(|entry| hirc::entry_id(entry) == Some(parent_id))
+            .flat_map(hirc::references)
+            .filter(|r| r.kind == RefKind::Child)
+            .map(|r| r.id)
+            .collect()
+    });
+    let text = query.text.as_ref().map(|t| t.trim().to_lowercase());
+
+    let matches = |entry: &Value| {
+        let id = hirc::entry_id(entry).unwrap_or_default();
+        let entry_type = hirc::entry_type(entry);
+        if let Some(types) = &query.types
+            && !types.iter().any(|t| t == entry_type)
+        {
+            return false;
+        }
+        if query.id_min.is_some_and(|min| id < min) || query.id_max.is_some_and(|max| id > max) {
+            return false;
+        }
+        if let Some(parent_id) = query.parent_id {
+            let is_child = children.as_ref().is_some_and(|c| c.contains(&id))
+                || hirc::references(entry)
+                    .iter()
+                    .any(|r| r.kind == RefKind::Parent && r.id == parent_id);
+            if !is_child {
+                return false;
+            }
+        }
+        if let Some(text) = text.as_deref().filter(|t| !t.is_empty()) {
+            let found = id.to_string().contains(text)
+                || entry_type.to_lowercase().contains(text)
+                || names
+                    .get(id)
+                    .is_some_and(|name| name.to_lowercase().contains(text));
+            if !found {
+                return false;
+            }
+        }
+        true
+    };
+
+    let matched: Vec<&Value> = entries.iter().filter(|entry| matches(entry)).collect();
+    let total = matched.len();
+    let entries: Vec<Value> = matched
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+        .cloned()
+        .collect();
+    HircPage {
+        total,
+        names: names.lookup_values(&entries),
+        entries,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let mut names = NameDb::default();
+        let play_id = names.add("Play_Hit");
+        let entries = vec![
+            json!({ "entry_type": "Event", "id": 10, "action_ids": [20, play_id] }),
+            json!({ "entry_type": "EventAction", "id": 20 }),
+            json!({ "entry_type": "Event", "id": 30, "action_ids": [] }),
+        ];
+
+        let page = query(
+            &entries,
+            &HircQuery {
+                types: Some(vec!["Event".to_string()]),
+                limit: Some(1),
+                ..Default::default()
+            },
+            &names,
+        );
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries, [entries[0].clone()]);
+        assert_eq!(
+            page.names.get(&play_id).map(String::as_str),
+            Some("Play_Hit")
+        );
+
+        let page = query(
+            &entries,
+            &HircQuery {
+                text: Some("action".to_string()),
+                id_min: Some(15),
+                ..Default::default()
+            },
+            &names,
+        );
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0]["id"], 20);
+        assert!(page.names.is_empty());
+    }
+}
@@ -0,0 +1,251 @@
+//! Lossless text form of a bank, for editing and version control.
+//!
+//! The document is the json of [`Bnk`] with a few changes to keep it readable:
+//! section magics are strings, raw byte fields (`data`, `unknown`) are hex
+//! strings, and DATA payloads are stored as wem files next to the document,
+//! referenced by relative paths in `files`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use re_sound::bnk::{Bnk, SectionPayload};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const TEXT_FORMAT: &str = "mhws-sound-modder/bnk";
+const TEXT_VERSION: u32 = 1;
+/// Fields holding raw bytes, converted to hex strings.
+const BYTE_FIELDS: &[&str] = &["data", "unknown"];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document {
+    format: String,
+    version: u32,
+    sections: Vec<Value>,
+}
+
+/// Write bank as a json document, with wem files in `<document stem>.media/`.
+pub fn export(bnk: &Bnk, output_path: impl AsRef<Path>) -> eyre::Result<()> {
+    let output_path = output_path.as_ref();
+    let root = output_path.parent().unwrap_or(Path::new(""));
+    let media_dir_name = format!(
+        "{}.media",
+        output_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+    );
+
+    let wem_ids: Vec<u32> = bnk
+        .sections
+        .iter()
+        .flat_map(|section| match &section.payload {
+            SectionPayload::Didx { entries } => entries.iter().map(|e| e.id).collect(),
+            _ => vec![],
+        })
+        .collect();
+
+    let mut sections = vec![];
+    for section in bnk.sections.iter() {
+        let mut value = match &section.payload {
+            // keep payloads out of the json tree
+            SectionPayload::Data { data_list } => {
+                let media_dir = root.join(&media_dir_name);
+                fs::create_dir_all(&media_dir)?;
+                let mut files = vec![];
+                for (index, data) in data_list.iter().enumerate() {
+                    let name = match wem_ids.get(index) {
+                        Some(id) => format!("{}.wem", id),
+                        None => format!("{}.bin", index),
+                    };
+                    fs::write(media_dir.join(&name), data)?;
+                    files.push(Value::String(format!("{}/{}", media_dir_name, name)));
+                }
+
+                let mut map = Map::new();
+                map.insert("magic".to_string(), Value::Null);
+                map.insert("section_length".to_string(), section.section_length.into());
+                map.insert("type".to_string(), "Data".into());
+                map.insert("files".to_string(), Value::Array(files));
+                Value::Object(map)
+            }
+            _ => {
+                let mut value = serde_json::to_value(section)?;
+                bytes_to_hex(&mut value);
+                value
+            }
+        };
+        value["magic"] = String::from_utf8_lossy(&section.magic).into();
+        sections.push(value);
+    }
+
+    let document = Document {
+        format: TEXT_FORMAT.to_string(),
+        version: TEXT_VERSION,
+        sections,
+    };
+    let content = serde_json::to_string_pretty(&document)?;
+    fs::write(output_path, content).context(format!(
+        "Failed to write document: {}",
+        output_path.display()
+    ))?;
+    Ok(())
+}
+
+/// Rebuild bank from a document written by [`export`].
+pub fn import(path: impl AsRef<Path>) -> eyre::Result<Bnk> {
+    let path = path.as_ref();
+    let root = path.parent().unwrap_or(Path::new(""));
+    let content =
+        fs::read_to_string(path).context(format!("Failed to read document: {}", path.display()))?;
+    let document: Document = serde_json::from_str(&content)
+        .context(format!("Failed to parse document: {}", path.display()))?;
+    if document.format != TEXT_FORMAT {
+        eyre::bail!("Not a bnk document: {}", path.display());
+    }
+    if document.version != TEXT_VERSION {
+        eyre::bail!(
+            "Unsupported document version {}, expected {}",
+            document.version,
+            TEXT_VERSION
+        );
+    }
+
+    // files of each DATA section, read after the structure is parsed
+    let mut media_files: Vec<Vec<PathBuf>> = vec![];
+    let mut sections = vec![];
+    for (index, mut section) in document.sections.into_iter().enumerate() {
+        let Some(magic) = section.get("magic").and_then(|m| m.as_str()) else {
+            eyre::bail!("Missing magic of section {}", index);
+        };
+        let magic: [u8; 4] = magic
+            .as_bytes()
+            .try_into()
+            .map_err(|_| eyre::eyre!("Invalid magic of section {}: {}", index, magic))?;
+        section["magic"] = magic.to_vec().into();
+
+        if section.get("type").and_then(|t| t.as_str()) == Some("Data") {
+            let files = section
+                .as_object_mut()
+                .and_then(|map| map.remove("files"))
+                .unwrap_or_default();
+            let files: Vec<String> = serde_json::from_value(files)
+                .map_err(|e| eyre::eyre!("Invalid files of DATA section: {}", e))?;
+            media_files.push(files.iter().map(|f| root.join(f)).collect());
+            section["data_list"] = Value::Array(vec![]);
+        } else {
+            hex_to_bytes(&mut section)?;
+        }
+        sections.push(section);
+    }
+
+    let mut bnk: Bnk = serde_json::from_value(serde_json::json!({ "sections": sections }))
+        .context("Invalid bnk document")?;
+
+    let mut media_files = media_files.into_iter();
+    for section in bnk.sections.iter_mut() {
+        let SectionPayload::Data { data_list } = &mut section.payload else {
+            continue;
+        };
+        for file in media_files.next().unwrap_or_default() {
+            let data =
+                fs::read(&file).context(format!("Failed to read wem: {}", file.display()))?;
+            data_list.push(data);
+        }
+    }
+
+    Ok(bnk)
+}
+
+fn bytes_to_hex(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if BYTE_FIELDS.contains(&key.as_str())
+                    && let Some(bytes) = as_bytes(child)
+                {
+                    *child = Value::String(bytes.iter().map(|b| format!("{:02x}", b)).collect());
+                } else {
+                    bytes_to_hex(child);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(bytes_to_hex),
+        _ => {}
+    }
+}
+
+fn hex_to_bytes(value: &mut Value) -> eyre::Result<()> {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                match child {
+                    Value::String(hex) if BYTE_FIELDS.contains(&key.as_str()) => {
+                        *child = decode_hex(hex)?.into();
+                    }
+                    _ => hex_to_bytes(child)?,
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                hex_to_bytes(item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Get bytes of an array of numbers in u8 range.
+fn as_bytes(value: &Value) -> Option<Vec<u8>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|v| v.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> eyre::Result<Vec<u8>> {
+    // allow whitespace for hand formatted values
+    let hex: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if !hex.len().is_multiple_of(2) {
+        eyre::bail!("Invalid hex string length: {}", hex.len());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| eyre::eyre!("Invalid hex string: {}", &hex[i..i + 2]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_byte_fields_round_trip() {
+        let original = json!({
+            "type": "Hirc",
+            "entries": [
+                { "entry_type": "Sound", "id": 1, "data": [0, 15, 255] },
+                { "entry_type": "EventAction", "parameters": [1, 2] },
+            ],
+        });
+
+        let mut value = original.clone();
+        bytes_to_hex(&mut value);
+        assert_eq!(value["entries"][0]["data"], "000fff");
+        assert_eq!(value["entries"][1]["parameters"], json!([1, 2]));
+
+        hex_to_bytes(&mut value).unwrap();
+        assert_eq!(value, original);
+    }
+}
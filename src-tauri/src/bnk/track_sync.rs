@@ -0,0 +1,271 @@
+//! Keep MusicTrack source metadata in sync with replaced wems.
+//!
+//! MusicTrack stores the size and duration of its sources, which the game
+//! uses to schedule playback. Stale values cut new audio short or leave
+//! silence, so they are updated from the wem headers on save.
+
+use std::{collections::HashMap, path::Path};
+
+use re_sound::bnk::Bnk;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::hirc;
+use crate::wem::WemInfo;
+
+/// Stream type of sources embedded in the bank.
+const STREAM_TYPE_DATA: u64 = 0;
+/// Durations closer than this are considered equal, in milliseconds.
+const DURATION_EPSILON: f64 = 0.001;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackSyncChange {
+    pub hirc_id: u32,
+    pub source_id: u32,
+    /// JSON pointer of the field inside the entry.
+    pub pointer: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+/// Playlist item whose trims leave nothing of the new wem to play.
+/// Trims are not changed, they have to be fixed by hand.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmptyClip {
+    pub hirc_id: u32,
+    pub source_id: u32,
+    /// JSON pointer of the playlist item inside the entry.
+    pub pointer: String,
+    /// Clip length left by the trims, in milliseconds.
+    pub clip_length: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackSyncReport {
+    pub changes: Vec<TrackSyncChange>,
+    pub empty_clips: Vec<EmptyClip>,
+}
+
+/// Read headers of replaced wem files, files which can not be parsed
+/// are skipped with a warning.
+pub fn read_infos<'a>(files: impl IntoIterator<Item = (u32, &'a Path)>) -> HashMap<u32, WemInfo> {
+    let mut infos = HashMap::new();
+    for (id, path) in files {
+        match WemInfo::from_file(path) {
+            Ok(info) => {
+                infos.insert(id, info);
+            }
+            Err(e) => log::warn!("MusicTrack metadata of {} not synced: {:#}", id, e),
+        }
+    }
+    infos
+}
+
+/// Update MusicTrack sources and playlist items using the replaced wems.
+pub fn sync_tracks(bnk: &mut Bnk, media: &HashMap<u32, WemInfo>) -> eyre::Result<TrackSyncReport> {
+    let mut report = TrackSyncReport::default();
+    if media.is_empty() {
+        return Ok(report);
+    }
+
+    hirc::edit_entries(bnk, |entry| {
+        if hirc::entry_type(entry) != "MusicTrack" {
+            return Ok(false);
+        }
+        let hirc_id = hirc::entry_id(entry).unwrap_or_default();
+        let changes = sync_entry(entry, hirc_id, media, &mut report.empty_clips);
+        let changed = !changes.is_empty();
+        report.changes.extend(changes);
+        Ok(changed)
+    })?;
+
+    for change in report.changes.iter() {
+        log::info!(
+            "MusicTrack {} source {}: {} {} -> {}",
+            change.hirc_id,
+            change.source_id,
+            change.pointer,
+            change.old,
+            change.new,
+        );
+    }
+    for clip in report.empty_clips.iter() {
+        log::warn!(
+            "MusicTrack {} source {}: {} is trimmed to {} ms of the new wem",
+            clip.hirc_id,
+            clip.source_id,
+            clip.pointer,
+            clip.clip_length,
+        );
+    }
+    Ok(report)
+}
+
+fn sync_entry(
+    entry: &mut Value,
+    hirc_id: u32,
+    media: &HashMap<u32, WemInfo>,
+    empty_clips: &mut Vec<EmptyClip>,
+) -> Vec<TrackSyncChange> {
+    let mut changes = vec![];
+    let mut set = |entry: &mut Value, source_id: u32, pointer: String, new: Value| {
+        let Some(field) = entry.pointer_mut(&pointer) else {
+            return;
+        };
+        let same = match (field.as_f64(), new.as_f64()) {
+            (Some(old), Some(new)) => (old - new).abs() < DURATION_EPSILON,
+            _ => *field == new,
+        };
+        if same {
+            return;
+        }
+        let old = std::mem::replace(field, new.clone());
+        changes.push(TrackSyncChange {
+            hirc_id,
+            source_id,
+            pointer,
+            old,
+            new,
+        });
+    };
+    let source_id = |entry: &Value, pointer: &str| {
+        entry
+            .pointer(pointer)
+            .and_then(|id| id.as_u64())
+            .map(|id| id as u32)
+    };
+    let count = |entry: &Value, pointer: &str| {
+        entry
+            .pointer(pointer)
+            .and_then(|v| v.as_array())
+            .map_or(0, |v| v.len())
+    };
+
+    let sources = "/music_track_initial_values/sources";
+    for i in 0..count(entry, sources) {
+        let base = format!("{sources}/{i}");
+        let Some(id) = source_id(entry, &format!("{base}/media_information/source_id")) else {
+            continue;
+        };
+        let Some(info) = media.get(&id) else {
+            continue;
+        };
+        // prefetched and streamed sources only keep part of the data in memory
+        let stream_type = entry
+            .pointer(&format!("{base}/stream_type"))
+            .and_then(|v| v.as_u64());
+        if stream_type == Some(STREAM_TYPE_DATA) {
+            let pointer = format!("{base}/media_information/in_memory_media_size");
+            set(entry, id, pointer, info.file_size.into());
+        }
+    }
+
+    let playlist = "/music_track_initial_values/playlist";
+    for i in 0..count(entry, playlist) {
+        let base = format!("{playlist}/{i}");
+        let Some(id) = source_id(entry, &format!("{base}/source_id")) else {
+            continue;
+        };
+        let Some(info) = media.get(&id) else {
+            continue;
+        };
+        let duration = info.duration_ms;
+        set(entry, id, format!("{base}/src_duration"), duration.into());
+
+        // begin trim is from the start, end trim is a non-positive offset from the end.
+        // clamping trims to a shorter wem would leave an empty clip, report instead
+        let get = |field: &str| {
+            entry
+                .pointer(&format!("{base}/{field}"))
+                .and_then(|v| v.as_f64())
+                .unwrap_or_default()
+        };
+        let clip_length = duration - get("begin_trim_offset") + get("end_trim_offset");
+        if clip_length < DURATION_EPSILON {
+            empty_clips.push(EmptyClip {
+                hirc_id,
+                source_id: id,
+                pointer: base,
+                clip_length: clip_length.max(0.0),
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sync_entry() {
+        let mut entry = json!({
+            "entry_type": "MusicTrack",
+            "id": 1,
+            "music_track_initial_values": {
+                "sources": [{
+                    "stream_type": 0,
+                    "media_information": { "source_id": 100, "in_memory_media_size": 10 },
+                }],
+                "playlist": [{
+                    "source_id": 100,
+                    "begin_trim_offset": 0.0,
+                    "end_trim_offset": -3000.0,
+                    "src_duration": 5000.0,
+                }],
+            },
+        });
+        let info = WemInfo {
+            sample_count: 2000,
+            duration_ms: 2000.0,
+            file_size: 4044,
+            ..Default::default()
+        };
+
+        let mut empty_clips = vec![];
+        let changes = sync_entry(
+            &mut entry,
+            1,
+            &HashMap::from([(100, info)]),
+            &mut empty_clips,
+        );
+        assert_eq!(changes.len(), 2);
+        let values = &entry["music_track_initial_values"];
+        assert_eq!(
+            values["sources"][0]["media_information"]["in_memory_media_size"],
+            4044
+        );
+        assert_eq!(values["playlist"][0]["src_duration"], 2000.0);
+        // trims are kept, the clip is reported as empty
+        assert_eq!(values["playlist"][0]["end_trim_offset"], -3000.0);
+        assert_eq!(empty_clips.len(), 1);
+        assert_eq!(
+            empty_clips[0].pointer,
+            "/music_track_initial_values/playlist/0"
+        );
+        assert_eq!(empty_clips[0].clip_length, 0.0);
+
+        // trims fitting the new wem are not reported
+        entry["music_track_initial_values"]["playlist"][0]["end_trim_offset"] = json!(-1500.0);
+        let mut empty_clips = vec![];
+        let info = WemInfo {
+            duration_ms: 2000.0,
+            file_size: 4044,
+            ..Default::default()
+        };
+        let changes = sync_entry(
+            &mut entry,
+            1,
+            &HashMap::from([(100, info)]),
+            &mut empty_clips,
+        );
+        assert!(changes.is_empty());
+        assert!(empty_clips.is_empty());
+    }
+}
@@ -0,0 +1,367 @@
+//! Reference integrity check of HIRC entries.
+//!
+//! The game silently skips objects with broken references, so they
+//! only show up as missing sounds. Checking before save catches them early.
+
+use std::{collections::HashSet, fs::File, io::Read, path::Path};
+
+use eyre::Context;
+use re_sound::{
+    bnk::{Bnk, SectionPayload},
+    pck::Pck,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use super::hirc::{self, RefKind, Reference};
+
+const PLAY_ACTION: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    /// The reference can not be resolved, the object will not play.
+    Error,
+    /// The reference may live in a bank or package not provided.
+    Warning,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub hirc_id: u32,
+    pub entry_type: String,
+    pub kind: RefKind,
+    pub referenced_id: u32,
+    /// JSON pointer of the field inside the entry.
+    pub pointer: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+/// Report of a bank file checked by [`validate_files`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankReport {
+    pub path: String,
+    #[serde(flatten)]
+    pub report: ValidationReport,
+}
+
+impl ValidationReport {
+    fn push(&mut self, issue: ValidationIssue) {
+        match issue.severity {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+        }
+        self.issues.push(issue);
+    }
+}
+
+/// Ids provided by other banks and packages loaded along with the bank.
+#[derive(Debug, Default)]
+pub struct KnownIds {
+    objects: HashSet<u32>,
+    media: HashSet<u32>,
+    /// Streamed media is only checked if packages are provided.
+    has_packages: bool,
+}
+
+impl KnownIds {
+    /// Collect ids from bnk and pck files, detected by file magic.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> eyre::Result<Self> {
+        let mut known = KnownIds::default();
+        for path in paths {
+            known.add_file(path.as_ref())?;
+        }
+        Ok(known)
+    }
+
+    pub fn add_file(&mut self, path: &Path) -> eyre::Result<()> {
+        let mut magic = [0; 4];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .context(format!("Failed to read reference file: {}", path.display()))?;
+
+        match &magic {
+            b"BKHD" => {
+                let bnk = super::load_file(path)?;
+                self.add_bnk(&bnk)?;
+            }
+            b"AKPK" => {
+                let mut pck = Pck::from_file(&path.to_string_lossy())
+                    .context(format!("Failed to parse pck: {}", path.display()))?;
+                self.media
+                    .extend(pck.header().wem_entries.iter().map(|e| e.id));
+                self.has_packages = true;
+            }
+            _ => eyre::bail!("Unsupported reference file: {}", path.display()),
+        }
+        Ok(())
+    }
+
+    pub fn add_bnk(&mut self, bnk: &Bnk) -> eyre::Result<()> {
+        self.media.extend(media_ids(bnk));
+        for entry in hirc::entries(bnk)? {
+            self.objects.extend(hirc::entry_id(&entry));
+        }
+        Ok(())
+    }
+}
+
+/// Check every id referenced by HIRC entries resolves in the bank itself,
+/// its embedded media, or the `known` ids of other loaded files.
+pub fn validate(bnk: &Bnk, known: &KnownIds) -> eyre::Result<ValidationReport> {
+    let entries = hirc::entries(bnk)?;
+    let objects: HashSet<u32> = entries.iter().filter_map(hirc::entry_id).collect();
+    let media: HashSet<u32> = media_ids(bnk).collect();
+    let has_object = |id: u32| objects.contains(&id) || known.objects.contains(&id);
+    let has_media = |id: u32| media.contains(&id) || known.media.contains(&id);
+
+    let mut report = ValidationReport::default();
+    for entry in entries.iter() {
+        let hirc_id = hirc::entry_id(entry).unwrap_or_default();
+        let entry_type = hirc::entry_type(entry);
+        for reference in hirc::references(entry) {
+            let found = match reference.kind {
+                RefKind::Media => has_media(reference.id),
+                _ => has_object(reference.id),
+            };
+            if found {
+                continue;
+            }
+            let Some((severity, message)) = check_missing(entry, &reference, known) else {
+                continue;
+            };
+            report.push(ValidationIssue {
+                severity,
+                hirc_id,
+                entry_type: entry_type.to_string(),
+                kind: reference.kind,
+                referenced_id: reference.id,
+                pointer: reference.pointer,
+                message,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Check bnk files saved together, e.g. in one export. Ids of all of them
+/// and of `reference_paths` are collected once, so the banks resolve
+/// references into each other without loading every file per bank.
+pub fn validate_files<P: AsRef<Path>>(
+    paths: &[P],
+    reference_paths: &[P],
+) -> eyre::Result<Vec<BankReport>> {
+    let banks = paths
+        .iter()
+        .map(|path| super::load_file(path.as_ref()))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let mut known = KnownIds::from_files(reference_paths)?;
+    for bnk in &banks {
+        known.add_bnk(bnk)?;
+    }
+
+    banks
+        .iter()
+        .zip(paths)
+        .map(|(bnk, path)| {
+            Ok(BankReport {
+                path: path.as_ref().to_string_lossy().to_string(),
+                report: validate(bnk, &known)?,
+            })
+        })
+        .collect()
+}
+
+/// Decide how bad an unresolved reference is.
+fn check_missing(
+    entry: &Value,
+    reference: &Reference,
+    known: &KnownIds,
+) -> Option<(Severity, String)> {
+    let id = reference.id;
+    let issue = match reference.kind {
+        RefKind::Action => (Severity::Error, format!("EventAction {} not found", id)),
+        RefKind::Target => {
+            if !hirc::targets_object(entry) {
+                return None;
+            }
+            let action_type = entry.get("action_type")?;
+            let severity = if action_type.as_u64() == Some(PLAY_ACTION)
+                || action_type.as_str() == Some("Play")
+            {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            (severity, format!("Target object {} not found", id))
+        }
+        RefKind::Media => match is_streamed(entry, id) {
+            Some(false) => (
+                Severity::Error,
+                format!("Embedded media {} not found in DIDX", id),
+            ),
+            // streamed from packages, only check if any provided
+            Some(true) if !known.has_packages => return None,
+            Some(true) => (
+                Severity::Warning,
+                format!("Streamed media {} not found in provided packages", id),
+            ),
+            None => (Severity::Warning, format!("Media {} not found", id)),
+        },
+        RefKind::Child => (Severity::Warning, format!("Child object {} not found", id)),
+        // usually defined in Init or shared banks
+        RefKind::Parent => (Severity::Warning, format!("Parent object {} not found", id)),
+        RefKind::Bus => (Severity::Warning, format!("Bus {} not found", id)),
+    };
+    Some(issue)
+}
+
+/// Whether the media is streamed instead of embedded, if known.
+///
+/// Stream type is 0 for embedded data, 1 for prefetch and 2 for streaming.
+fn is_streamed(entry: &Value, id: u32) -> Option<bool> {
+    let stream_type = match hirc::entry_type(entry) {
+        "Sound" => entry.get("state")?.as_u64()?,
+        "MusicTrack" => entry
+            .pointer("/music_track_initial_values/sources")?
+            .as_array()?
+            .iter()
+            .find(|s| {
+                s.pointer("/media_information/source_id")
+                    .and_then(|v| v.as_u64())
+                    == Some(id as u64)
+            })?
+            .get("stream_type")?
+            .as_u64()?,
+        _ => return None,
+    };
+    Some(stream_type != 0)
+}
+
+fn media_ids(bnk: &Bnk) -> impl Iterator<Item = u32> + '_ {
+    bnk.sections
+        .iter()
+        .flat_map(|section| match &section.payload {
+            SectionPayload::Didx { entries } => entries.iter().map(|e| e.id).collect(),
+            _ => vec![],
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use re_sound::bnk::Section;
+    use serde_json::json;
+
+    use super::*;
+    use crate::bnk::hirc::tests::music_track;
+
+    fn test_bnk(entries: Vec<Value>, media: &[u32]) -> Bnk {
+        let mut bnk = Bnk {
+            sections: vec![Section {
+                magic: *b"HIRC",
+                section_length: 0,
+                payload: SectionPayload::Hirc { entries: vec![] },
+            }],
+        };
+        crate::bnk::set_media(&mut bnk, media.iter().map(|&id| (id, vec![0; 4])).collect());
+        hirc::set_entries(&mut bnk, entries).unwrap();
+        bnk
+    }
+
+    fn action(id: u32, action_type: u64, target: u32) -> Value {
+        json!({
+            "entry_type": "EventAction",
+            "length": 0,
+            "id": id,
+            "scope": 3,
+            "action_type": action_type,
+            "game_object_id": target,
+            "_unk1": 0,
+            "parameter_count": 0,
+            "parameter_types": [],
+            "parameters": [],
+            "_unk2": 0,
+            "data": [],
+        })
+    }
+
+    fn severities(report: &ValidationReport) -> Vec<(Severity, RefKind, u32)> {
+        let mut issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|i| (i.severity, i.kind, i.referenced_id))
+            .collect();
+        issues.sort_by_key(|&(_, _, id)| id);
+        issues
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut track = music_track(5, &[20, 21, 22]);
+        let values = &mut track["music_track_initial_values"];
+        values["sources"][2]["stream_type"] = json!(2);
+        values["node_base_params"]["direct_parent_id"] = json!(30);
+        let bnk = test_bnk(
+            vec![
+                json!({ "entry_type": "Event", "length": 0, "id": 1, "action_ids": [2, 3, 4] }),
+                // play and stop objects of another bank
+                action(2, 4, 10),
+                action(4, 1, 11),
+                track,
+            ],
+            &[20],
+        );
+
+        let report = validate(&bnk, &KnownIds::default()).unwrap();
+        assert_eq!(
+            severities(&report),
+            [
+                (Severity::Error, RefKind::Action, 3),
+                (Severity::Error, RefKind::Target, 10),
+                (Severity::Warning, RefKind::Target, 11),
+                // the source and its playlist item
+                (Severity::Error, RefKind::Media, 21),
+                (Severity::Error, RefKind::Media, 21),
+                (Severity::Warning, RefKind::Parent, 30),
+            ]
+        );
+        assert_eq!((report.errors, report.warnings), (4, 2));
+        assert!(
+            report.issues.iter().any(|i| i.pointer
+                == "/music_track_initial_values/sources/1/media_information/source_id")
+        );
+
+        // objects of the other bank resolve, streamed media is checked
+        // once packages are provided
+        let mut known = KnownIds::default();
+        known
+            .add_bnk(&test_bnk(vec![music_track(10, &[])], &[]))
+            .unwrap();
+        known.has_packages = true;
+        let report = validate(&bnk, &known).unwrap();
+        assert_eq!(
+            severities(&report),
+            [
+                (Severity::Error, RefKind::Action, 3),
+                (Severity::Warning, RefKind::Target, 11),
+                (Severity::Error, RefKind::Media, 21),
+                (Severity::Error, RefKind::Media, 21),
+                (Severity::Warning, RefKind::Media, 22),
+                (Severity::Warning, RefKind::Media, 22),
+                (Severity::Warning, RefKind::Parent, 30),
+            ]
+        );
+    }
+}
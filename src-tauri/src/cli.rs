@@ -0,0 +1,608 @@
+//! Headless command line entry, for scripts and build machines.
+//!
+//! Every subcommand maps to a Tauri command in [`crate::command`],
+//! so both frontends share the same behavior.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use eyre::Context;
+use serde::Deserialize;
+
+use crate::{
+    bnk::{self, merge::ConflictPolicy, validate::KnownIds},
+    command,
+    extract::{self, ExtractOptions},
+    loudness, manifest, names, patch,
+    service::{
+        ConversionPreset, NameService, TranscodeJob, TranscodeOptions, TranscodeService,
+        WemDecoder, WemEncoder,
+    },
+    wem::WemInfo,
+};
+
+const USAGE: &str = "\
+Usage: mhws-sound-modder-cli <command> [options]
+
+Commands:
+  bnk-load <bnk> [-o <json>] [--sections <BKHD,DIDX,...>] [--names <txt>]...
+                                      Dump bnk structure as json, with names of ids found
+                                      in wordlists.
+  bnk-save <json> <bnk> [--data-dir <dir>] [--source <bnk>] [--ref <bnk|pck>]... [--force]
+                                      Write bnk from json. Wem data is taken from dir,
+                                      falling back to the source bnk. Fails without writing
+                                      if HIRC references have errors, unless forced.
+  bnk-extract <bnk> <dir> [--template <name>] [--ref <bnk>]...
+                                      Extract all wem data from bnk. Template placeholders:
+                                      {id}, {name}, {event}, {bank}, {index}.
+  bnk-remove-media <bnk> <id,...> [-o <bnk>] [--cleanup]
+                                      Remove wems and report HIRC references to them.
+                                      Only reports if no output provided.
+  bnk-validate <bnk> [--ref <bnk|pck>]...
+                                      Check HIRC references resolve in the bnk or reference
+                                      files. Fails if any error found.
+  bnk-event <bnk> <event_id> [-o <json>]
+                                      Resolve objects and wem sources an event can play.
+  bnk-diff <old> <new> [-o <json>]    Show changed sections, media and HIRC fields.
+  bnk-merge <base> <ours> <theirs> -o <bnk> [--on-conflict <fail|ours|theirs>]
+                                      Merge changes of two modded bnk on top of base bnk.
+                                      Fails without writing if conflicts are unresolved.
+  bnk-export-text <bnk> <json>        Export bnk as an editable json document,
+                                      wem data goes to <json stem>.media/.
+  bnk-import-text <json> <bnk>        Rebuild bnk from an exported json document.
+  pck-load <pck> [-o <json>]          Dump pck header as json.
+  pck-save <json> <pck> [--data-dir <dir>] [--source <pck>]
+                                      Write pck from header json. Wem data is taken from dir,
+                                      falling back to the source pck.
+  pck-extract <pck> <dir> [--template <name>] [--ref <bnk>]...
+                                      Extract all wem data from pck, naming files with HIRC
+                                      of the reference banks.
+  transcode <input> <output> [--conversion <preset>] [--match <wem>]
+                                      Convert audio by file extensions. Presets: match-source,
+                                      pcm, adpcm, vorbis-high, vorbis-medium, vorbis-low,
+                                      or a conversion ShareSet name of the temp project.
+                                      match-source uses the codec of the --match wem.
+  transcode-batch <jobs.json> [-o <json>]
+                                      Convert a list of {input, output, options} jobs, wavs
+                                      to wem with a single WwiseConsole run. Fails if any
+                                      job failed, after converting the others.
+  loudness <wav>                      Print peak and LUFS of a wav file.
+  wem-info <wem>                      Print codec, channels, duration, loop and markers
+                                      from the wem header.
+  build <manifest>                    Build patched files from a mod manifest (toml or json).
+  patch-create <vanilla> <modded> <patch>
+                                      Create a patch from vanilla to modded bnk or pck.
+  patch-apply <patch> <vanilla> <output>
+                                      Rebuild the modded file from a vanilla copy.
+  names-hash <name>...                Print Wwise ids of names.
+
+Global options:
+  --ffmpeg <path>                     Use the specified ffmpeg executable.
+  --wwise-console <path>              Use the specified WwiseConsole executable.
+  --vgmstream <path>                  Use the specified vgmstream-cli executable.
+  --decoder <auto|native|vgmstream>   Wem decoding backend, native supports PCM and Vorbis.
+  --codebooks <bin>                   Use the specified Vorbis codebooks file.
+  --encoder <auto|wwise|pcm>          Wem encoding backend, pcm needs no WwiseConsole.
+  --names <txt>                       Load a wordlist besides wordlists/ next to the executable.
+  -v, --verbose                       Print debug logs.
+  -h, --help                          Print this help.
+
+Exit codes: 0 on success, 1 on failure, 2 on invalid usage.";
+
+/// Run command line interface with process arguments.
+pub fn run() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    CliLogger::init(args.verbose);
+    match execute(&args) {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::Usage(msg)) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+enum Outcome {
+    Done,
+    Usage(String),
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    command: Option<String>,
+    positional: Vec<String>,
+    output: Option<String>,
+    data_dir: Option<String>,
+    source: Option<String>,
+    sections: Option<String>,
+    references: Vec<String>,
+    wordlists: Vec<String>,
+    template: Option<String>,
+    on_conflict: Option<String>,
+    ffmpeg: Option<String>,
+    wwise_console: Option<String>,
+    vgmstream: Option<String>,
+    decoder: Option<WemDecoder>,
+    codebooks: Option<String>,
+    encoder: Option<WemEncoder>,
+    conversion: Option<ConversionPreset>,
+    source_wem: Option<String>,
+    cleanup: bool,
+    force: bool,
+    verbose: bool,
+    help: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for option {name}"))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => parsed.help = true,
+                "-v" | "--verbose" => parsed.verbose = true,
+                "--cleanup" => parsed.cleanup = true,
+                "--force" => parsed.force = true,
+                "-o" | "--output" => parsed.output = Some(value(&arg)?),
+                "--data-dir" => parsed.data_dir = Some(value(&arg)?),
+                "--source" => parsed.source = Some(value(&arg)?),
+                "--sections" => parsed.sections = Some(value(&arg)?),
+                "--ref" => parsed.references.push(value(&arg)?),
+                "--names" => parsed.wordlists.push(value(&arg)?),
+                "--template" => parsed.template = Some(value(&arg)?),
+                "--on-conflict" => parsed.on_conflict = Some(value(&arg)?),
+                "--ffmpeg" => parsed.ffmpeg = Some(value(&arg)?),
+                "--wwise-console" => parsed.wwise_console = Some(value(&arg)?),
+                "--vgmstream" => parsed.vgmstream = Some(value(&arg)?),
+                "--decoder" => {
+                    parsed.decoder = Some(match value(&arg)?.as_str() {
+                        "auto" => WemDecoder::Auto,
+                        "native" => WemDecoder::Native,
+                        "vgmstream" => WemDecoder::Vgmstream,
+                        other => return Err(format!("Invalid decoder: {other}")),
+                    })
+                }
+                "--codebooks" => parsed.codebooks = Some(value(&arg)?),
+                "--encoder" => {
+                    parsed.encoder = Some(match value(&arg)?.as_str() {
+                        "auto" => WemEncoder::Auto,
+                        "wwise" => WemEncoder::Wwise,
+                        "pcm" => WemEncoder::Pcm,
+                        other => return Err(format!("Invalid encoder: {other}")),
+                    })
+                }
+                "--conversion" => {
+                    parsed.conversion = Some(match value(&arg)?.as_str() {
+                        "match-source" => ConversionPreset::MatchSource,
+                        "pcm" => ConversionPreset::Pcm,
+                        "adpcm" => ConversionPreset::Adpcm,
+                        "vorbis-high" => ConversionPreset::VorbisHigh,
+                        "vorbis-medium" => ConversionPreset::VorbisMedium,
+                        "vorbis-low" => ConversionPreset::VorbisLow,
+                        share_set => ConversionPreset::ShareSet(share_set.to_string()),
+                    })
+                }
+                "--match" => parsed.source_wem = Some(value(&arg)?),
+                other if other.starts_with('-') && other.len() > 1 => {
+                    return Err(format!("Unknown option: {other}"));
+                }
+                _ => {
+                    if parsed.command.is_none() {
+                        parsed.command = Some(arg);
+                    } else {
+                        parsed.positional.push(arg);
+                    }
+                }
+            }
+        }
+        if parsed.command.is_none() && !parsed.help {
+            return Err("No command specified.".to_string());
+        }
+
+        Ok(parsed)
+    }
+
+    /// Get positional arguments, exactly `N` required.
+    fn positional<const N: usize>(&self) -> Option<[&str; N]> {
+        if self.positional.len() != N {
+            return None;
+        }
+        Some(std::array::from_fn(|i| self.positional[i].as_str()))
+    }
+
+    fn transcode_service(&self) -> eyre::Result<TranscodeService> {
+        let service = TranscodeService::new();
+        if let Some(path) = &self.ffmpeg {
+            service.set_ffmpeg_path(path);
+        }
+        if let Some(path) = &self.wwise_console {
+            service.set_wwise_path(path);
+        }
+        if let Some(path) = &self.vgmstream {
+            service.set_vgmstream_path(path);
+        }
+        if let Some(decoder) = self.decoder {
+            service.set_wem_decoder(decoder);
+        }
+        if let Some(path) = &self.codebooks {
+            service.set_codebooks_path(path)?;
+        }
+        if let Some(encoder) = self.encoder {
+            service.set_wem_encoder(encoder);
+        }
+        Ok(service)
+    }
+
+    fn extract_options(&self) -> ExtractOptions {
+        ExtractOptions {
+            template: self.template.clone(),
+            reference_paths: self.references.clone(),
+        }
+    }
+
+    fn name_service(&self) -> eyre::Result<NameService> {
+        let service = NameService::new();
+        for path in self.wordlists.iter() {
+            service.load_file(path)?;
+        }
+        Ok(service)
+    }
+}
+
+fn execute(args: &Args) -> eyre::Result<Outcome> {
+    let command = args.command.as_deref().unwrap_or_default();
+    let usage = || Ok(Outcome::Usage(format!("Invalid arguments for {command}.")));
+
+    match command {
+        "bnk-load" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let filter = args
+                .sections
+                .as_deref()
+                .map(parse_section_filter)
+                .transpose()?;
+            let bnk = command::load_bnk(path, filter)?;
+            let names = args.name_service()?.db().lookup_bnk(&bnk)?;
+            write_json(
+                args.output.as_deref(),
+                &serde_json::json!({ "sections": bnk.sections, "names": names }),
+            )?;
+        }
+        "bnk-save" => {
+            let Some([json_path, output]) = args.positional() else {
+                return usage();
+            };
+            let bnk: re_sound::bnk::Bnk = read_json(json_path)?;
+            let known = KnownIds::from_files(&args.references)?;
+            let report = command::save_bnk(
+                output,
+                bnk,
+                args.data_dir.as_deref(),
+                args.source.as_deref(),
+                &known,
+                args.force,
+            )?;
+            if !report.validation.report.issues.is_empty() {
+                write_json(None, &report.validation)?;
+            }
+            if !report.saved {
+                eyre::bail!(
+                    "{} errors found in HIRC references, bnk not written.",
+                    report.validation.report.errors
+                );
+            }
+        }
+        "bnk-extract" => {
+            let Some([path, target]) = args.positional() else {
+                return usage();
+            };
+            let count = extract::extract_bnk(
+                path,
+                target,
+                &args.name_service()?.db(),
+                &args.extract_options(),
+            )?;
+            log::info!("Extracted {count} wem files.");
+        }
+        "bnk-remove-media" => {
+            let Some([path, ids]) = args.positional() else {
+                return usage();
+            };
+            let ids = parse_ids(ids)?;
+            let mut bnk = bnk::load_file(path)?;
+            let removals = bnk::remove_media(&mut bnk, &ids, args.cleanup)?;
+            if let Some(output) = &args.output {
+                bnk::save_file(&mut bnk, output)?;
+            }
+            write_json(None, &removals)?;
+        }
+        "bnk-validate" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let bnk = bnk::load_file(path)?;
+            let known = KnownIds::from_files(&args.references)?;
+            let report = bnk::validate::validate(&bnk, &known)?;
+            write_json(args.output.as_deref(), &report)?;
+            if report.errors > 0 {
+                eyre::bail!(
+                    "{} errors and {} warnings found.",
+                    report.errors,
+                    report.warnings
+                );
+            }
+        }
+        "bnk-event" => {
+            let Some([path, event_id]) = args.positional() else {
+                return usage();
+            };
+            let Ok(event_id) = event_id.parse::<u32>() else {
+                return Ok(Outcome::Usage(format!("Invalid event id: {event_id}")));
+            };
+            let bnk = bnk::load_file(path)?;
+            let graph = bnk::graph::resolve_event(&bnk, event_id)?;
+            write_json(args.output.as_deref(), &graph)?;
+        }
+        "bnk-diff" => {
+            let Some([old, new]) = args.positional() else {
+                return usage();
+            };
+            let diff = command::bnk_diff(old, new).map_err(eyre::Error::msg)?;
+            write_json(args.output.as_deref(), &diff)?;
+        }
+        "bnk-merge" => {
+            let (Some([base, ours, theirs]), Some(output)) = (args.positional(), &args.output)
+            else {
+                return usage();
+            };
+            let policy = match args.on_conflict.as_deref() {
+                None | Some("fail") => ConflictPolicy::Fail,
+                Some("ours") => ConflictPolicy::Ours,
+                Some("theirs") => ConflictPolicy::Theirs,
+                Some(other) => {
+                    return Ok(Outcome::Usage(format!("Invalid conflict policy: {other}")));
+                }
+            };
+            let report = command::bnk_merge(base, ours, theirs, output, Some(policy))
+                .map_err(eyre::Error::msg)?;
+            write_json(None, &report)?;
+            if report.has_unresolved() {
+                eyre::bail!("Unresolved conflicts found, output not written.");
+            }
+        }
+        "bnk-export-text" => {
+            let Some([path, output]) = args.positional() else {
+                return usage();
+            };
+            command::bnk_export_text(path, output).map_err(eyre::Error::msg)?;
+        }
+        "bnk-import-text" => {
+            let Some([path, output]) = args.positional() else {
+                return usage();
+            };
+            command::bnk_import_text(path, output).map_err(eyre::Error::msg)?;
+        }
+        "pck-load" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let data = command::pck_load_basic_data(path).map_err(eyre::Error::msg)?;
+            write_json(args.output.as_deref(), &data)?;
+        }
+        "pck-save" => {
+            let Some([json_path, output]) = args.positional() else {
+                return usage();
+            };
+            let header = read_json::<PckHeaderInput>(json_path)?.into_header();
+            command::save_pck(
+                header,
+                output,
+                args.data_dir.as_deref(),
+                args.source.as_deref(),
+            )
+            .map_err(eyre::Error::msg)?;
+        }
+        "pck-extract" => {
+            let Some([path, target]) = args.positional() else {
+                return usage();
+            };
+            let count = extract::extract_pck(
+                path,
+                target,
+                &args.name_service()?.db(),
+                &args.extract_options(),
+            )?;
+            log::info!("Extracted {count} wem files.");
+        }
+        "transcode" => {
+            let Some([input, output]) = args.positional() else {
+                return usage();
+            };
+            let options = TranscodeOptions {
+                conversion: args.conversion.clone(),
+                source_wem: args.source_wem.as_ref().map(PathBuf::from),
+            };
+            args.transcode_service()?
+                .auto_transcode(input, output, &options)?;
+        }
+        "transcode-batch" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let jobs: Vec<TranscodeJob> = read_json(path)?;
+            let results = args.transcode_service()?.batch_transcode(&jobs);
+            write_json(args.output.as_deref(), &results)?;
+            let failed = results.iter().filter(|r| r.error.is_some()).count();
+            if failed > 0 {
+                eyre::bail!("{} of {} jobs failed.", failed, results.len());
+            }
+        }
+        "loudness" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let info = loudness::get_loadness_info(path)?;
+            write_json(None, &info)?;
+        }
+        "wem-info" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let info = WemInfo::from_file(path)?;
+            write_json(None, &info)?;
+        }
+        "build" => {
+            let Some([path]) = args.positional() else {
+                return usage();
+            };
+            let report = manifest::build(path, &args.transcode_service()?)?;
+            write_json(args.output.as_deref(), &report)?;
+        }
+        "names-hash" => {
+            if args.positional.is_empty() {
+                return usage();
+            }
+            for name in args.positional.iter() {
+                println!("{}\t{}", names::hash(name), name);
+            }
+        }
+        "patch-create" => {
+            let Some([vanilla, modded, output]) = args.positional() else {
+                return usage();
+            };
+            let summary = patch::create(vanilla, modded, output)?;
+            write_json(None, &summary)?;
+        }
+        "patch-apply" => {
+            let Some([patch_path, vanilla, output]) = args.positional() else {
+                return usage();
+            };
+            let report = patch::apply(patch_path, vanilla, output)?;
+            write_json(None, &report)?;
+        }
+        other => return Ok(Outcome::Usage(format!("Unknown command: {other}"))),
+    }
+
+    Ok(Outcome::Done)
+}
+
+/// Accept both the raw header and the `pck-load` output.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PckHeaderInput {
+    BasicData { header: re_sound::pck::PckHeader },
+    Header(re_sound::pck::PckHeader),
+}
+
+impl PckHeaderInput {
+    fn into_header(self) -> re_sound::pck::PckHeader {
+        match self {
+            PckHeaderInput::BasicData { header } => header,
+            PckHeaderInput::Header(header) => header,
+        }
+    }
+}
+
+/// Parse comma separated ids.
+fn parse_ids(value: &str) -> eyre::Result<Vec<u32>> {
+    value
+        .split(',')
+        .map(|id| {
+            id.trim()
+                .parse()
+                .map_err(|_| eyre::eyre!("Invalid id: {}", id))
+        })
+        .collect()
+}
+
+/// Parse section names like `BKHD,DIDX` into magic numbers.
+fn parse_section_filter(value: &str) -> eyre::Result<Vec<u32>> {
+    value
+        .split(',')
+        .map(|name| {
+            let magic: [u8; 4] = name
+                .trim()
+                .as_bytes()
+                .try_into()
+                .map_err(|_| eyre::eyre!("Invalid section name: {}", name))?;
+            Ok(u32::from_le_bytes(magic))
+        })
+        .collect()
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> eyre::Result<T> {
+    let file = File::open(path).context(format!("Failed to open {}", path))?;
+    let value = serde_json::from_reader(io::BufReader::new(file))
+        .context(format!("Failed to parse json {}", path))?;
+    Ok(value)
+}
+
+/// Write json to file, or stdout if no path provided.
+fn write_json<T: serde::Serialize>(path: Option<&str>, value: &T) -> eyre::Result<()> {
+    match path {
+        Some(path) => {
+            let file = File::create(path).context(format!("Failed to create {}", path))?;
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, value)?;
+            writer.flush()?;
+        }
+        None => {
+            let mut stdout = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, value)?;
+            writeln!(stdout)?;
+        }
+    }
+    Ok(())
+}
+
+/// Logs go to stderr, keeping stdout clean for json output.
+struct CliLogger {
+    verbose: bool,
+}
+
+impl CliLogger {
+    fn init(verbose: bool) {
+        let logger = CliLogger { verbose };
+        if log::set_boxed_logger(Box::new(logger)).is_ok() {
+            log::set_max_level(log::LevelFilter::Debug);
+        }
+    }
+}
+
+impl log::Log for CliLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.verbose || metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -1,18 +1,38 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::{self, File},
-    io::{self, Write},
+    io,
     path::{Path, PathBuf},
 };
 
-use eyre::Context;
 use indexmap::IndexMap;
-use re_sound::bnk::SectionPayload;
+use re_sound::bnk::{DidxEntry, SectionPayload};
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
 use crate::{
+    bnk::{
+        self, MediaRemoval, SaveReport,
+        diff::BnkDiff,
+        graph::EventGraph,
+        merge::{ConflictPolicy, MergeReport},
+        query::{HircPage, HircQuery},
+        track_sync,
+        validate::{BankReport, KnownIds},
+    },
+    extract::{self, ExtractOptions},
     loudness::{self, LoudnessInfo},
-    service::TranscodeService,
+    manifest::{self, BuildReport},
+    names,
+    patch::{self, PatchApplyReport, PatchSummary},
+    pck::{self, WemData},
+    service::{
+        NameService, SessionService, SettingsService, SettingsState, ToolStatus, ToolsStatus,
+        TranscodeJob, TranscodeJobResult, TranscodeOptions, TranscodeService, WemDecoder,
+        WemEncoder,
+    },
+    settings::{Settings, SettingsIssue},
+    wem::WemInfo,
 };
 
 fn map_result<F, V>(f: F) -> std::result::Result<V, String>
@@ -22,122 +42,363 @@ where
     f().map_err(|e| format!("{:#}", e))
 }
 
+#[derive(Debug, Serialize)]
+pub struct BnkLoadResult {
+    #[serde(flatten)]
+    bnk: re_sound::bnk::Bnk,
+    /// Names of the ids found in loaded wordlists.
+    names: BTreeMap<u32, String>,
+}
+
 #[tauri::command]
 pub fn bnk_load_file(
+    names: State<NameService>,
     path: &str,
     section_filter: Option<Vec<u32>>,
-) -> Result<re_sound::bnk::Bnk, String> {
+) -> Result<BnkLoadResult, String> {
     map_result(|| {
-        let file = File::open(path)?;
-        let mut reader = std::io::BufReader::new(file);
-        let mut bnk = re_sound::bnk::Bnk::from_reader(&mut reader)?;
-        // use filter to remove sections
-        if let Some(filter) = section_filter {
-            let mut remove_indexes = vec![];
-            for (i, section) in bnk.sections.iter_mut().enumerate() {
-                let sec_magic_number = u32::from_le_bytes(section.magic);
-                if filter.iter().all(|&f| sec_magic_number != f) {
-                    // Data: only clear content, keep section header
-                    if let SectionPayload::Data { data_list } = &mut section.payload {
-                        data_list.clear();
-                    } else {
-                        remove_indexes.push(i);
-                    }
+        let bnk = load_bnk(path, section_filter)?;
+        let names = names.db().lookup_bnk(&bnk)?;
+        Ok(BnkLoadResult { bnk, names })
+    })
+}
+
+/// Load bnk, keeping only sections with magic in `section_filter`.
+/// DATA section is kept with empty content.
+pub fn load_bnk(path: &str, section_filter: Option<Vec<u32>>) -> eyre::Result<re_sound::bnk::Bnk> {
+    let file = File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+    let mut bnk = re_sound::bnk::Bnk::from_reader(&mut reader)?;
+    // use filter to remove sections
+    if let Some(filter) = section_filter {
+        let mut remove_indexes = vec![];
+        for (i, section) in bnk.sections.iter_mut().enumerate() {
+            let sec_magic_number = u32::from_le_bytes(section.magic);
+            if filter.iter().all(|&f| sec_magic_number != f) {
+                // Data: only clear content, keep section header
+                if let SectionPayload::Data { data_list } = &mut section.payload {
+                    data_list.clear();
+                } else {
+                    remove_indexes.push(i);
                 }
             }
+        }
 
-            // remove sections
-            for i in remove_indexes.iter().rev() {
-                bnk.sections.remove(*i);
-            }
+        // remove sections
+        for i in remove_indexes.iter().rev() {
+            bnk.sections.remove(*i);
         }
+    }
 
-        Ok(bnk)
-    })
+    Ok(bnk)
 }
 
+/// Save bnk at `source_path` to `path`, with the wem files in `data_dir`
+/// replaced or added. Edits of opened banks are saved with `bnk_session_save`.
+///
+/// See `save_bnk` for the checks before writing.
 #[tauri::command]
 pub fn bnk_save_file(
     path: &str,
-    mut bnk: re_sound::bnk::Bnk,
+    source_path: &str,
     data_dir: Option<&str>,
-) -> Result<(), String> {
+    reference_paths: Vec<String>,
+    force: bool,
+) -> Result<SaveReport, String> {
     map_result(|| {
-        // load override sound data
-        if let Some(data_dir) = data_dir {
-            let dir_path = Path::new(data_dir);
-            if !dir_path.is_dir() {
-                eyre::bail!(
-                    "Override data path not found or not a directory: {}",
-                    data_dir
-                );
-            }
-
-            // 收集所有 wem 文件
-            let mut wem_files = vec![];
-            for entry in fs::read_dir(dir_path)? {
-                let entry = entry?;
-                let path = entry.path();
-                if !path.is_file() || path.extension().unwrap_or_default() != "wem" {
-                    continue;
-                }
-                wem_files.push(path);
-            }
+        let known = KnownIds::from_files(&reference_paths)?;
+        save_bnk(
+            path,
+            bnk::load_file(source_path)?,
+            data_dir,
+            None,
+            &known,
+            force,
+        )
+    })
+}
 
-            // 更新 bnk 数据
-            update_bnk_data(&mut bnk, &wem_files)?;
-            log::info!("Bnk data updated.")
+/// Save bnk to path.
+///
+/// `data_dir` only needs to contain the replaced or added wem files,
+/// all other DATA payloads are taken from the original bnk at `source_path`.
+/// MusicTrack sources using the replaced wems are updated to match them.
+///
+/// HIRC references are checked before writing, ids in `known` resolve
+/// besides the bank's own. With errors the bank is only written if `force` is set.
+pub fn save_bnk(
+    path: &str,
+    mut bnk: re_sound::bnk::Bnk,
+    data_dir: Option<&str>,
+    source_path: Option<&str>,
+    known: &KnownIds,
+    force: bool,
+) -> eyre::Result<SaveReport> {
+    // 收集替换的 wem 文件
+    let mut wem_files = IndexMap::new();
+    if let Some(data_dir) = data_dir {
+        let dir_path = Path::new(data_dir);
+        if !dir_path.is_dir() {
+            eyre::bail!(
+                "Override data path not found or not a directory: {}",
+                data_dir
+            );
         }
+        wem_files = extract::collect_wem_files(dir_path)?;
+    }
 
-        let file = File::create(path)?;
-        let mut writer = std::io::BufWriter::new(file);
-        bnk.write_to(&mut writer)?;
+    // 更新 bnk 数据
+    if data_dir.is_some() || source_path.is_some() {
+        let source = source_path.map(bnk::load_file).transpose()?;
+        update_bnk_data(&mut bnk, &wem_files, source)?;
+        log::info!("Bnk data updated.")
+    }
 
-        Ok(())
+    let validation = BankReport {
+        path: path.to_string(),
+        report: bnk::validate::validate(&bnk, known)?,
+    };
+    if validation.report.errors > 0 && !force {
+        return Ok(SaveReport::blocked(validation));
+    }
+
+    // MusicTrack keeps size and duration of its sources
+    let infos = track_sync::read_infos(wem_files.iter().map(|(id, path)| (*id, path.as_path())));
+    let track_sync = track_sync::sync_tracks(&mut bnk, &infos)?;
+
+    let file = File::create(path)?;
+    let mut writer = std::io::BufWriter::new(file);
+    bnk.write_to(&mut writer)?;
+
+    Ok(SaveReport {
+        saved: true,
+        validation,
+        track_sync,
     })
 }
 
-/// Extract all Wem data from specified Bnk file to target_path.
+/// Check HIRC references of saved bnk files.
+/// `reference_paths` are other bnk or pck files loaded by the game along with them.
 #[tauri::command]
-pub fn bnk_extract_data(path: &str, target_path: &str) -> Result<(), String> {
+pub fn bnk_validate(
+    paths: Vec<String>,
+    reference_paths: Vec<String>,
+) -> Result<Vec<BankReport>, String> {
+    map_result(|| bnk::validate::validate_files(&paths, &reference_paths))
+}
+
+/// Resolve the tree of objects and wem sources an event in an opened bnk can play.
+#[tauri::command]
+pub fn bnk_resolve_event(
+    sessions: State<SessionService>,
+    handle: u32,
+    event_id: u32,
+) -> Result<EventGraph, String> {
     map_result(|| {
-        let file = std::fs::File::open(path)?;
-        let mut reader = std::io::BufReader::new(file);
-        let bnk = re_sound::bnk::Bnk::from_reader(&mut reader)?;
+        sessions.with_bnk(handle, |session| {
+            bnk::graph::resolve_event(&session.bnk, event_id)
+        })
+    })
+}
+
+/// Structured diff of two bnk files, e.g. vanilla and modded.
+#[tauri::command]
+pub fn bnk_diff(old_path: &str, new_path: &str) -> Result<BnkDiff, String> {
+    map_result(|| {
+        let old = bnk::load_file(old_path)?;
+        let new = bnk::load_file(new_path)?;
+        bnk::diff::diff(&old, &new)
+    })
+}
 
-        let target_path = Path::new(target_path);
-        if !target_path.exists() {
-            std::fs::create_dir_all(target_path)?;
+/// Three-way merge of bnk files modified from the same base bnk.
+/// Output is only written if all conflicts are resolved.
+#[tauri::command]
+pub fn bnk_merge(
+    base_path: &str,
+    ours_path: &str,
+    theirs_path: &str,
+    output_path: &str,
+    on_conflict: Option<ConflictPolicy>,
+) -> Result<MergeReport, String> {
+    map_result(|| {
+        let mut base = bnk::load_file(base_path)?;
+        let ours = bnk::load_file(ours_path)?;
+        let theirs = bnk::load_file(theirs_path)?;
+        let report = bnk::merge::merge(&mut base, &ours, &theirs, on_conflict.unwrap_or_default())?;
+        if report.has_unresolved() {
+            log::warn!("Merge has unresolved conflicts, output not written.");
+        } else {
+            bnk::save_file(&mut base, output_path)?;
         }
+        Ok(report)
+    })
+}
 
-        // get all data
-        let mut wem_ids = vec![];
-        let mut data = None;
-        for section in bnk.sections.iter() {
-            match &section.payload {
-                SectionPayload::Didx { entries } => {
-                    wem_ids.extend(entries.iter().map(|e| e.id));
+/// Export bnk as an editable json document, wem data is written
+/// as files next to it.
+#[tauri::command]
+pub fn bnk_export_text(path: &str, output_path: &str) -> Result<(), String> {
+    map_result(|| {
+        let bnk = bnk::load_file(path)?;
+        bnk::text::export(&bnk, output_path)
+    })
+}
+
+/// Rebuild bnk from a json document written by `bnk_export_text`.
+#[tauri::command]
+pub fn bnk_import_text(path: &str, output_path: &str) -> Result<(), String> {
+    map_result(|| {
+        let mut bnk = bnk::text::import(path)?;
+        bnk::save_file(&mut bnk, output_path)
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BnkSessionInfo {
+    handle: u32,
+    /// Section magics in file order.
+    sections: Vec<String>,
+    hirc_count: usize,
+    /// Embedded wem ids in DIDX order.
+    media: Vec<u32>,
+}
+
+/// Load bnk into backend memory, entries are then fetched with `bnk_query_hirc`.
+#[tauri::command]
+pub fn bnk_session_open(
+    sessions: State<SessionService>,
+    path: &str,
+) -> Result<BnkSessionInfo, String> {
+    map_result(|| {
+        let handle = sessions.open_bnk(path)?;
+        sessions.with_bnk(handle, |session| {
+            let mut info = BnkSessionInfo {
+                handle,
+                sections: vec![],
+                hirc_count: 0,
+                media: vec![],
+            };
+            for section in session.bnk.sections.iter() {
+                info.sections
+                    .push(String::from_utf8_lossy(&section.magic).to_string());
+                match &section.payload {
+                    SectionPayload::Hirc { entries } => info.hirc_count += entries.len(),
+                    SectionPayload::Didx { entries } => {
+                        info.media.extend(entries.iter().map(|e| e.id))
+                    }
+                    _ => {}
                 }
-                SectionPayload::Data { data_list } => data = Some(data_list),
-                _ => {}
             }
-        }
-        let Some(data) = data else {
-            log::info!("No data found in Bnk file. This Bnk may not contain actual sound data.");
-            // save none files
-            return Ok(());
-        };
-        if wem_ids.len() != data.len() {
-            eyre::bail!("Number of Wem IDs and data entries do not match.");
-        }
+            Ok(info)
+        })
+    })
+}
 
-        // save all data
-        for (id, data) in wem_ids.iter().zip(data.iter()) {
-            let file_path = target_path.join(format!("{}.wem", id));
-            let mut file = File::create(&file_path)?;
-            file.write_all(data)?;
-        }
+#[tauri::command]
+pub fn bnk_session_close(sessions: State<SessionService>, handle: u32) -> Result<(), String> {
+    if !sessions.close_bnk(handle) {
+        return Err(format!("Bnk session not found: {}", handle));
+    }
+    Ok(())
+}
+
+/// Fetch HIRC entries of an opened bnk by filters, with offset and limit paging.
+#[tauri::command]
+pub fn bnk_query_hirc(
+    sessions: State<SessionService>,
+    names: State<NameService>,
+    handle: u32,
+    query: HircQuery,
+) -> Result<HircPage, String> {
+    map_result(|| {
+        sessions.with_bnk(handle, |session| {
+            Ok(bnk::query::query(session.entries()?, &query, &names.db()))
+        })
+    })
+}
+
+/// Set a field of HIRC entry in an opened bnk by JSON pointer,
+/// returns the updated entry.
+#[tauri::command]
+pub fn bnk_session_set_field(
+    sessions: State<SessionService>,
+    handle: u32,
+    id: u32,
+    pointer: &str,
+    value: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    map_result(|| sessions.with_bnk(handle, |session| session.set_field(id, pointer, value)))
+}
+
+/// Replace the HIRC entry with the same id in an opened bnk.
+#[tauri::command]
+pub fn bnk_session_replace_entry(
+    sessions: State<SessionService>,
+    handle: u32,
+    entry: serde_json::Value,
+) -> Result<serde_json::Value, String> {
+    map_result(|| sessions.with_bnk(handle, |session| session.replace_entry(entry)))
+}
+
+/// Replace or add embedded wem of an opened bnk from file.
+#[tauri::command]
+pub fn bnk_session_replace_media(
+    sessions: State<SessionService>,
+    handle: u32,
+    id: u32,
+    path: &str,
+) -> Result<(), String> {
+    map_result(|| sessions.with_bnk(handle, |session| session.replace_media(id, path)))
+}
+
+/// Remove embedded wems of an opened bnk, and report HIRC references to them.
+/// With `cleanup`, references in MusicTrack are removed too.
+#[tauri::command]
+pub fn bnk_session_remove_media(
+    sessions: State<SessionService>,
+    handle: u32,
+    ids: Vec<u32>,
+    cleanup: bool,
+) -> Result<Vec<MediaRemoval>, String> {
+    map_result(|| sessions.with_bnk(handle, |session| session.remove_media(&ids, cleanup)))
+}
+
+/// Write an opened bnk, to its source path if no path provided.
+/// MusicTrack sources using the replaced wems are updated to match them.
+///
+/// HIRC references are checked first, resolving ids in `reference_paths` too.
+/// With errors the bank is only written if `force` is set.
+#[tauri::command]
+pub fn bnk_session_save(
+    sessions: State<SessionService>,
+    handle: u32,
+    path: Option<&str>,
+    reference_paths: Vec<String>,
+    force: bool,
+) -> Result<SaveReport, String> {
+    map_result(|| {
+        let known = KnownIds::from_files(&reference_paths)?;
+        sessions.with_bnk(handle, |session| session.save(path, &known, force))
+    })
+}
 
+/// Extract all Wem data from specified Bnk file to target_path.
+///
+/// Files are named `{id}.wem` unless a naming template is provided in `options`,
+/// or in the export settings if `options` is not provided.
+#[tauri::command]
+pub fn bnk_extract_data(
+    names: State<NameService>,
+    settings: State<SettingsService>,
+    path: &str,
+    target_path: &str,
+    options: Option<ExtractOptions>,
+) -> Result<(), String> {
+    map_result(|| {
+        let options = options.unwrap_or_else(|| settings.get().export);
+        extract::extract_bnk(path, target_path, &names.db(), &options)?;
         Ok(())
     })
 }
@@ -162,109 +423,194 @@ pub fn pck_load_basic_data(path: &str) -> Result<PckBasicData, String> {
     })
 }
 
+/// Extract all Wem data from specified Pck file to target_path.
+///
+/// Files are named `{id}.wem` unless a naming template is provided in `options`,
+/// or in the export settings if `options` is not provided.
 #[tauri::command]
-pub fn pck_extract_data(path: &str, target_path: &str) -> Result<(), String> {
+pub fn pck_extract_data(
+    names: State<NameService>,
+    settings: State<SettingsService>,
+    path: &str,
+    target_path: &str,
+    options: Option<ExtractOptions>,
+) -> Result<(), String> {
     map_result(|| {
-        let target_path = Path::new(target_path);
-        let mut pck = re_sound::pck::Pck::from_file(path)?;
+        let options = options.unwrap_or_else(|| settings.get().export);
+        extract::extract_pck(path, target_path, &names.db(), &options)?;
+        Ok(())
+    })
+}
 
-        let target_path = Path::new(target_path);
-        if !target_path.exists() {
-            std::fs::create_dir_all(target_path)?;
-        }
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PckSessionInfo {
+    handle: u32,
+    header: re_sound::pck::PckHeader,
+    has_data: bool,
+}
 
-        for i in 0..pck.header().wem_entries.len() {
-            let entry = &pck.header().wem_entries[i];
-            let file_name = format!("{}.wem", entry.id);
-            let file_path = target_path.join(file_name);
-            let mut file = File::create(&file_path)
-                .context("Failed to create wem output file")
-                .context(format!("Path: {}", file_path.display()))?;
+/// Open pck in backend memory, edits are applied on save.
+#[tauri::command]
+pub fn pck_session_open(
+    sessions: State<SessionService>,
+    path: &str,
+) -> Result<PckSessionInfo, String> {
+    map_result(|| {
+        let handle = sessions.open_pck(path)?;
+        sessions.with_pck(handle, |session| {
+            Ok(PckSessionInfo {
+                handle,
+                header: session.header.clone(),
+                has_data: session.has_data(),
+            })
+        })
+    })
+}
 
-            let mut wem_reader = pck.wem_reader(i).unwrap();
-            io::copy(&mut wem_reader, &mut file).context("Failed to write wem data to file")?;
-        }
+#[tauri::command]
+pub fn pck_session_close(sessions: State<SessionService>, handle: u32) -> Result<(), String> {
+    if !sessions.close_pck(handle) {
+        return Err(format!("Pck session not found: {}", handle));
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Replace wem of an opened pck from file, adding the entry if missing.
+/// Language is sfx if not provided. Returns the updated header.
+#[tauri::command]
+pub fn pck_session_replace_wem(
+    sessions: State<SessionService>,
+    handle: u32,
+    id: u32,
+    language: Option<&str>,
+    path: &str,
+) -> Result<re_sound::pck::PckHeader, String> {
+    map_result(|| {
+        sessions.with_pck(handle, |session| {
+            session.replace_wem(id, language, path)?;
+            Ok(session.header.clone())
+        })
+    })
+}
+
+/// Remove wem entries of an opened pck, in all languages if not provided.
+/// Returns the updated header.
+#[tauri::command]
+pub fn pck_session_remove_wem(
+    sessions: State<SessionService>,
+    handle: u32,
+    id: u32,
+    language: Option<&str>,
+) -> Result<re_sound::pck::PckHeader, String> {
+    map_result(|| {
+        sessions.with_pck(handle, |session| {
+            session.remove_wem(id, language)?;
+            Ok(session.header.clone())
+        })
+    })
+}
+
+/// Write an opened pck to output_path, which must differ from its source.
+#[tauri::command]
+pub fn pck_session_save(
+    sessions: State<SessionService>,
+    handle: u32,
+    output_path: &str,
+) -> Result<(), String> {
+    map_result(|| {
+        sessions.with_pck(handle, |session| {
+            if is_same_file(&session.path, output_path) {
+                eyre::bail!("Output path must be different from the source pck.");
+            }
+            let result = session.save(output_path);
+            if result.is_err() {
+                // clean up if failed
+                fs::remove_file(output_path).ok();
+            }
+            result
+        })
     })
 }
 
+/// Save pck at `source_path` to `output_path`, with the wem files in `data_path`
+/// replaced. Edits of opened packages are saved with `pck_session_save`.
 #[tauri::command]
 pub fn pck_save_file(
+    output_path: &str,
+    source_path: &str,
+    data_path: Option<&str>,
+) -> Result<(), String> {
+    let mut source = map_result(|| Ok(re_sound::pck::Pck::from_file(source_path)?))?;
+    let header = source.header().clone();
+    if !source.has_data() {
+        // header only, no wem data to replace
+        return save_pck(header, output_path, None, None);
+    }
+    save_pck(header, output_path, data_path, Some(source_path))
+}
+
+/// Save pck to output_path.
+///
+/// `data_path` only needs to contain the replaced wem files,
+/// other entries are streamed from the original pck at `source_path`.
+pub fn save_pck(
     mut header: re_sound::pck::PckHeader,
     output_path: &str,
     data_path: Option<&str>,
+    source_path: Option<&str>,
 ) -> Result<(), String> {
+    if let Some(source_path) = source_path
+        && is_same_file(source_path, output_path)
+    {
+        return Err("Output path must be different from the source pck.".to_string());
+    }
+
     let result = map_result(|| {
-        // If data_path is provided, use it to update header.
-        let mut wem_metadata_map = IndexMap::new();
+        if data_path.is_none() && source_path.is_none() {
+            // No data, write header only.
+            let file = File::create(output_path)?;
+            let mut writer = io::BufWriter::new(file);
+            header.write_to(&mut writer)?;
+            return Ok(());
+        }
+
+        // collect replaced wem files
+        let mut wem_files = IndexMap::new();
         if let Some(data_path) = data_path {
             let data_path = Path::new(data_path);
             if !data_path.exists() {
                 eyre::bail!("Source Wem dir provided but not found.");
             }
-
-            // collect wem files
-            struct WemMetadata {
-                id: u32,
-                file_size: u32,
-                file_path: Option<String>,
-                data: Option<Vec<u8>>,
-            }
-            for entry in fs::read_dir(data_path)? {
-                let entry = entry?;
-                let path = entry.path();
-                if !path.is_file() || path.extension().unwrap_or_default() != "wem" {
-                    continue;
-                }
-
-                // 解析wem文件名
-                let file_stem = path.file_stem().unwrap().to_string_lossy();
-                let id: u32 = file_stem.parse()?;
-                wem_metadata_map.insert(
-                    id,
-                    WemMetadata {
-                        id,
-                        file_size: path.metadata()?.len() as u32,
-                        file_path: Some(path.to_string_lossy().to_string()),
-                        data: None,
-                    },
-                );
-            }
-
-            // update header with new wem entries
-            let mut offset = header.get_wem_offset_start();
-            for entry in header.wem_entries.iter_mut() {
-                let metadata = wem_metadata_map.get(&entry.id).ok_or_else(|| {
-                    eyre::eyre!("Wem id not found in source wem dir: {}", entry.id)
-                })?;
-                entry.offset = offset;
-                entry.length = metadata.file_size;
-                offset += metadata.file_size;
-            }
+            wem_files = extract::collect_wem_files(data_path)?;
         }
 
-        let file = File::create(output_path)?;
-        let mut writer = io::BufWriter::new(file);
-        // write header
-        header.write_to(&mut writer)?;
-
-        // write wem data
-        if data_path.is_some() {
-            for metadata in wem_metadata_map.values() {
-                if let Some(data) = &metadata.data {
-                    writer.write_all(data)?;
-                } else if let Some(file_path) = &metadata.file_path {
-                    let mut input_file = File::open(file_path)?;
-                    io::copy(&mut input_file, &mut writer)?;
-                } else {
-                    // No data, skip
-                    log::info!("No data for wem file: {}", metadata.id);
-                }
+        // untouched entries are streamed from source pck
+        let mut source = source_path.map(re_sound::pck::Pck::from_file).transpose()?;
+        let source_entries = source
+            .as_mut()
+            .map(|pck| pck.header().wem_entries.clone())
+            .unwrap_or_default();
+
+        let mut wem_data = Vec::with_capacity(header.wem_entries.len());
+        for entry in header.wem_entries.iter() {
+            if let Some(path) = wem_files.get(&entry.id) {
+                wem_data.push(WemData::File(path.clone()));
+                continue;
             }
+            let index = source_entries
+                .iter()
+                .position(|e| e.id == entry.id && e.language_id == entry.language_id)
+                .ok_or_else(|| {
+                    eyre::eyre!(
+                        "Wem id not found in source wem dir or source pck: {}",
+                        entry.id
+                    )
+                })?;
+            wem_data.push(WemData::Source(index));
         }
 
-        Ok(())
+        pck::write_file(&mut header, output_path, &wem_data, source.as_mut())
     });
     if result.is_err() {
         // clean up if failed
@@ -274,6 +620,13 @@ pub fn pck_save_file(
     result
 }
 
+fn is_same_file(a: impl AsRef<Path>, b: impl AsRef<Path>) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 #[tauri::command]
 pub fn get_exe_path() -> Result<String, String> {
     map_result(|| {
@@ -287,6 +640,71 @@ pub fn env_get_var(name: &str) -> Option<String> {
     std::env::var(name).ok()
 }
 
+/// Settings with the problems found in them.
+#[tauri::command]
+pub fn settings_get(settings: State<SettingsService>) -> SettingsState {
+    settings.state()
+}
+
+/// Replace and save settings, and apply them to the running services.
+///
+/// Settings with problems are still saved, the problems are returned in
+/// `issues` together with the ones found applying them.
+#[tauri::command]
+pub fn settings_update(
+    settings: State<SettingsService>,
+    transcode: State<TranscodeService>,
+    names: State<NameService>,
+    new_settings: Settings,
+) -> Result<SettingsState, String> {
+    map_result(|| {
+        let old = settings.get();
+        let mut state = settings.update(|settings| *settings = new_settings)?;
+        let new = &state.settings;
+
+        let (tools, old_tools) = (&new.tools, &old.tools);
+        // cleared paths are detected again, as on startup
+        if tools != old_tools {
+            transcode.set_tool_paths(tools);
+        }
+        transcode.set_wem_decoder(new.transcode.decoder);
+        transcode.set_wem_encoder(new.transcode.encoder);
+        transcode.set_conversion(new.transcode.conversion.clone());
+
+        // only reload files changed, loading is slow for large wordlists
+        let mut errors = vec![];
+        if tools.codebooks != old_tools.codebooks
+            && let Err(e) = transcode.reset_codebooks(tools.codebooks.as_deref())
+        {
+            errors.push(("tools.codebooks".to_string(), e));
+        }
+        let removed = old
+            .wordlists
+            .iter()
+            .any(|path| !new.wordlists.contains(path));
+        if removed {
+            // names can not be unloaded, start over from the default wordlists,
+            // dropping names loaded outside settings too
+            names.reset();
+        }
+        for (i, path) in new.wordlists.iter().enumerate() {
+            if (removed || !old.wordlists.contains(path))
+                && let Err(e) = names.load_file(path)
+            {
+                errors.push((format!("wordlists.{}", i), e));
+            }
+        }
+        for (field, e) in errors {
+            if !state.issues.iter().any(|issue| issue.field == field) {
+                state
+                    .issues
+                    .push(SettingsIssue::new(field, format!("{e:#}")));
+            }
+        }
+        Ok(state)
+    })
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TranscodePaths {
@@ -295,45 +713,127 @@ pub struct TranscodePaths {
     pub vgmstream: Option<String>,
 }
 
+/// Set tool paths and save them to settings.
 #[tauri::command]
 pub fn transcode_set_paths(
     service: State<TranscodeService>,
+    settings: State<SettingsService>,
     paths: TranscodePaths,
 ) -> Result<(), String> {
-    if let Some(path) = paths.ffmpeg {
+    if let Some(path) = &paths.ffmpeg {
         service.set_ffmpeg_path(path);
     }
-    if let Some(path) = paths.wwise_console {
+    if let Some(path) = &paths.wwise_console {
         service.set_wwise_path(path);
     }
-    if let Some(path) = paths.vgmstream {
-        service.set_vgmstream_path(&path);
+    if let Some(path) = &paths.vgmstream {
+        service.set_vgmstream_path(path);
     }
 
-    Ok(())
+    map_result(|| {
+        settings.update(|settings| {
+            let tools = &mut settings.tools;
+            tools.ffmpeg = paths.ffmpeg.or(tools.ffmpeg.take());
+            tools.wwise_console = paths.wwise_console.or(tools.wwise_console.take());
+            tools.vgmstream = paths.vgmstream.or(tools.vgmstream.take());
+        })?;
+        Ok(())
+    })
 }
 
+#[tauri::command]
+pub fn transcode_set_wem_decoder(
+    service: State<TranscodeService>,
+    settings: State<SettingsService>,
+    decoder: WemDecoder,
+    codebooks_path: Option<String>,
+) -> Result<(), String> {
+    map_result(|| {
+        service.set_wem_decoder(decoder);
+        if let Some(path) = &codebooks_path {
+            service.set_codebooks_path(path)?;
+        }
+        settings.update(|settings| {
+            settings.transcode.decoder = decoder;
+            if codebooks_path.is_some() {
+                settings.tools.codebooks = codebooks_path;
+            }
+        })?;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn transcode_set_wem_encoder(
+    service: State<TranscodeService>,
+    settings: State<SettingsService>,
+    encoder: WemEncoder,
+) -> Result<(), String> {
+    service.set_wem_encoder(encoder);
+    map_result(|| {
+        settings.update(|settings| settings.transcode.encoder = encoder)?;
+        Ok(())
+    })
+}
+
+/// Detect tool paths, replacing configured ones only if found.
+/// Paths of working tools are saved to settings.
 #[tauri::command]
 pub fn transcode_auto_detect_paths(
-    _service: State<TranscodeService>,
-) -> Result<TranscodePaths, String> {
-    Err("Not implemented yet".to_string())
+    service: State<TranscodeService>,
+    settings: State<SettingsService>,
+) -> ToolsStatus {
+    let status = service.auto_detect();
+    let working = |tool: &ToolStatus| tool.path.clone().filter(|_| tool.ok);
+    let result = settings.update(|settings| {
+        let tools = &mut settings.tools;
+        tools.ffmpeg = working(&status.ffmpeg).or(tools.ffmpeg.take());
+        tools.wwise_console = working(&status.wwise_console).or(tools.wwise_console.take());
+        tools.vgmstream = working(&status.vgmstream).or(tools.vgmstream.take());
+    });
+    if let Err(e) = result {
+        log::error!("{e:#}");
+    }
+    status
 }
 
-/// Check shell path settings can work.
-/// Returns: Some(path) if it works, None if it fails or not set.
+/// Check the configured tools can run, with their versions or the
+/// reason they fail.
 #[tauri::command]
-pub fn transcode_check(_service: State<TranscodeService>) -> Result<TranscodePaths, String> {
-    Err("Not implemented yet".to_string())
+pub fn transcode_check(service: State<TranscodeService>) -> ToolsStatus {
+    service.check()
 }
 
+/// Convert audio by file extensions.
+///
+/// Wav is converted to wem with `options.conversion`, or the default
+/// conversion of settings if not set.
 #[tauri::command]
 pub fn transcode_auto_transcode(
     service: State<TranscodeService>,
     input: &str,
     output: &str,
+    options: Option<TranscodeOptions>,
 ) -> Result<(), String> {
-    map_result(|| service.auto_transcode(input, output))
+    map_result(|| service.auto_transcode(input, output, &options.unwrap_or_default()))
+}
+
+/// Convert files in a batch, wavs to wem with a single WwiseConsole run.
+/// Failures are reported per job, in the order of jobs.
+#[tauri::command]
+pub fn transcode_batch(
+    service: State<TranscodeService>,
+    jobs: Vec<TranscodeJob>,
+) -> Vec<TranscodeJobResult> {
+    service.batch_transcode(&jobs)
+}
+
+/// Names of the conversion ShareSets in the Wwise temp project.
+#[tauri::command]
+pub fn transcode_conversion_share_sets(
+    service: State<TranscodeService>,
+) -> Result<Vec<String>, String> {
+    map_result(|| service.conversion_share_sets())
 }
 
 #[tauri::command]
@@ -341,66 +841,115 @@ pub async fn loudness_get_info(path: &str) -> Result<LoudnessInfo, String> {
     map_result(|| Ok(loudness::get_loadness_info(path)?))
 }
 
-fn update_bnk_data(bnk: &mut re_sound::bnk::Bnk, wem_files: &[PathBuf]) -> eyre::Result<()> {
-    // Locate DATA and DIDX sections
-    let (data_section, didx_section) =
-        bnk.sections
-            .iter_mut()
-            .fold((None, None), |(data, didx), section| {
-                match &mut section.payload {
-                    SectionPayload::Data { .. } => (Some(section), didx),
-                    SectionPayload::Didx { .. } => (data, Some(section)),
-                    _ => (data, didx),
-                }
-            });
+/// Read wem header without decoding audio.
+#[tauri::command]
+pub fn wem_get_info(path: &str) -> Result<WemInfo, String> {
+    map_result(|| WemInfo::from_file(path))
+}
+
+/// Build all targets described by a mod manifest file.
+#[tauri::command]
+pub fn manifest_build(service: State<TranscodeService>, path: &str) -> Result<BuildReport, String> {
+    map_result(|| manifest::build(path, &service))
+}
 
-    let (Some(data_section), Some(didx_section)) = (data_section, didx_section) else {
+/// Rebuild DATA payloads in DIDX order.
+///
+/// For each DIDX entry the override wem file is used if provided,
+/// otherwise the original payload from `source` bnk, or from `bnk` itself
+/// if its DATA section is still complete.
+fn update_bnk_data(
+    bnk: &mut re_sound::bnk::Bnk,
+    wem_files: &IndexMap<u32, PathBuf>,
+    source: Option<re_sound::bnk::Bnk>,
+) -> eyre::Result<()> {
+    // 收集原始数据并按 ID 索引
+    let mut original_data = HashMap::new();
+    if let Some(mut source) = source
+        && let Some((entries, data_list)) = bnk::media_sections(&mut source)
+    {
+        original_data.extend(entries.iter().map(|e| e.id).zip(data_list.drain(..)));
+    }
+
+    let Some((didx_entries, data_list)) = bnk::media_sections(bnk) else {
         log::warn!("No DATA or DIDX section found in Bnk file. Ignore update.");
         return Ok(());
     };
-    let SectionPayload::Data { data_list } = &mut data_section.payload else {
-        unreachable!();
-    };
-    let SectionPayload::Didx {
-        entries: didx_entries,
-    } = &mut didx_section.payload
-    else {
-        unreachable!();
-    };
-
-    // 收集所有 wem 文件并按 ID 索引
-    let mut wem_files_map = IndexMap::new();
-    for path in wem_files {
-        let id = path
-            .file_stem()
-            .unwrap()
-            .to_string_lossy()
-            .parse::<u32>()
-            .map_err(|_| eyre::eyre!("Invalid wem file name: {}", path.display()))?;
-        wem_files_map.insert(id, path);
+    if didx_entries.len() == data_list.len() {
+        original_data.extend(didx_entries.iter().map(|e| e.id).zip(data_list.drain(..)));
     }
-
-    // 检查是否所有 didx 条目都有对应的 wem 文件
-    for entry in didx_entries.iter() {
-        if !wem_files_map.contains_key(&entry.id) {
-            eyre::bail!("Missing wem file for id {} in override directory", entry.id);
+    // wems not in DIDX yet are added as new media
+    for id in wem_files.keys() {
+        if !didx_entries.iter().any(|e| e.id == *id) {
+            didx_entries.push(DidxEntry {
+                id: *id,
+                offset: 0,
+                length: 0,
+            });
         }
     }
 
-    // 按照 didx 顺序创建有序的文件列表
-    let mut wem_files_ordered = Vec::new();
-    for entry in didx_entries.iter() {
-        wem_files_ordered.push(wem_files_map.get(&entry.id).ok_or_else(|| {
-            eyre::eyre!("Missing wem file for id {} in override directory", entry.id)
-        })?);
-    }
-
     // 按照 didx 顺序更新数据
-    for path in wem_files_ordered {
-        let data = fs::read(path)?;
-        data_list.push(data);
-        // didx section will fixed automatically by re-sound
+    let mut new_data_list = Vec::with_capacity(didx_entries.len());
+    for entry in didx_entries.iter() {
+        let data = if let Some(path) = wem_files.get(&entry.id) {
+            fs::read(path)?
+        } else if let Some(data) = original_data.remove(&entry.id) {
+            data
+        } else {
+            eyre::bail!(
+                "Missing wem data for id {}, not found in override directory or source bnk",
+                entry.id
+            );
+        };
+        new_data_list.push(data);
     }
+    // didx section will fixed automatically by re-sound
+    *data_list = new_data_list;
 
     Ok(())
 }
+
+/// Create a patch with the changes from a vanilla bnk or pck to a modded one.
+#[tauri::command]
+pub fn patch_create(
+    vanilla_path: &str,
+    modded_path: &str,
+    output_path: &str,
+) -> Result<PatchSummary, String> {
+    map_result(|| patch::create(vanilla_path, modded_path, output_path))
+}
+
+/// Rebuild a modded file by applying patch to the vanilla file.
+#[tauri::command]
+pub fn patch_apply(
+    patch_path: &str,
+    vanilla_path: &str,
+    output_path: &str,
+) -> Result<PatchApplyReport, String> {
+    map_result(|| patch::apply(patch_path, vanilla_path, output_path))
+}
+
+/// Load wordlists of Wwise object names, returns the count of names read.
+#[tauri::command]
+pub fn names_load_files(names: State<NameService>, paths: Vec<String>) -> Result<usize, String> {
+    map_result(|| {
+        let mut count = 0;
+        for path in paths {
+            count += names.load_file(path)?;
+        }
+        Ok(count)
+    })
+}
+
+/// Wwise id of a name.
+#[tauri::command]
+pub fn names_hash(name: &str) -> u32 {
+    names::hash(name)
+}
+
+/// Names of the ids found in loaded wordlists.
+#[tauri::command]
+pub fn names_lookup(names: State<NameService>, ids: Vec<u32>) -> BTreeMap<u32, String> {
+    names.db().lookup(ids)
+}
@@ -0,0 +1,373 @@
+//! Wem extraction with file names from a template.
+//!
+//! Placeholders: `{id}`, `{name}`, `{event}`, `{bank}` and `{index}`.
+//! The wem id of every named file is recorded in a sidecar file in the
+//! output directory, so renamed files still resolve to their ids on import.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use indexmap::IndexMap;
+use re_sound::bnk::{Bnk, SectionPayload};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bnk::{
+        self, graph,
+        hirc::{self, RefKind},
+    },
+    names::NameDb,
+};
+
+/// Maps file names to wem ids, in the directory of extracted files.
+pub const SIDECAR_FILE: &str = "wem_ids.json";
+const SIDECAR_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractOptions {
+    /// File name template without extension, `{id}` if not provided.
+    pub template: Option<String>,
+    /// Other bnk files with HIRC objects playing the wems,
+    /// e.g. banks of a streamed pck.
+    #[serde(default)]
+    pub reference_paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Sidecar {
+    version: u32,
+    files: BTreeMap<String, u32>,
+}
+
+/// Extract all wem data of bnk to `target_dir`, returns the count of files written.
+pub fn extract_bnk(
+    path: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    names: &NameDb,
+    options: &ExtractOptions,
+) -> eyre::Result<usize> {
+    let path = path.as_ref();
+    let bnk = bnk::load_file(path)?;
+
+    // get all data
+    let mut wem_ids = vec![];
+    let mut data = None;
+    for section in bnk.sections.iter() {
+        match &section.payload {
+            SectionPayload::Didx { entries } => {
+                wem_ids.extend(entries.iter().map(|e| e.id));
+            }
+            SectionPayload::Data { data_list } => data = Some(data_list),
+            _ => {}
+        }
+    }
+    let Some(data) = data else {
+        log::info!("No data found in Bnk file. This Bnk may not contain actual sound data.");
+        return Ok(0);
+    };
+    if wem_ids.len() != data.len() {
+        eyre::bail!("Number of Wem IDs and data entries do not match.");
+    }
+
+    let mut writer = NamedWriter::new(target_dir, path, names, options, Some(&bnk))?;
+    for (index, (id, data)) in wem_ids.iter().zip(data.iter()).enumerate() {
+        let file_path = writer.next_path(*id, index);
+        fs::write(&file_path, data)
+            .context(format!("Failed to write wem: {}", file_path.display()))?;
+    }
+    writer.finish()
+}
+
+/// Extract all wem data of pck to `target_dir`, returns the count of files written.
+pub fn extract_pck(
+    path: impl AsRef<Path>,
+    target_dir: impl AsRef<Path>,
+    names: &NameDb,
+    options: &ExtractOptions,
+) -> eyre::Result<usize> {
+    let path = path.as_ref();
+    let mut pck = re_sound::pck::Pck::from_file(&path.to_string_lossy())
+        .context(format!("Failed to parse pck: {}", path.display()))?;
+
+    let mut writer = NamedWriter::new(target_dir, path, names, options, None)?;
+    for i in 0..pck.header().wem_entries.len() {
+        let id = pck.header().wem_entries[i].id;
+        let file_path = writer.next_path(id, i);
+        let mut file = File::create(&file_path)
+            .context("Failed to create wem output file")
+            .context(format!("Path: {}", file_path.display()))?;
+
+        let mut wem_reader = pck
+            .wem_reader(i)
+            .ok_or_else(|| eyre::eyre!("Wem data not found for id {}", id))?;
+        io::copy(&mut wem_reader, &mut file).context("Failed to write wem data to file")?;
+    }
+    writer.finish()
+}
+
+/// Collect wem files in `dir` by id.
+///
+/// Ids are taken from the sidecar file if present, otherwise from file names.
+pub fn collect_wem_files(dir: impl AsRef<Path>) -> eyre::Result<IndexMap<u32, PathBuf>> {
+    let dir = dir.as_ref();
+    let sidecar = read_sidecar(dir)?.map(|s| s.files).unwrap_or_default();
+
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().unwrap_or_default() == "wem" {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut files = IndexMap::new();
+    for path in paths {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let id = match sidecar.get(file_name.as_ref()) {
+            Some(id) => *id,
+            None => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .parse::<u32>()
+                .map_err(|_| eyre::eyre!("Invalid wem file name: {}", path.display()))?,
+        };
+        files.insert(id, path);
+    }
+    Ok(files)
+}
+
+fn read_sidecar(dir: &Path) -> eyre::Result<Option<Sidecar>> {
+    let path = dir.join(SIDECAR_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)?;
+    let sidecar: Sidecar = serde_json::from_str(&content)
+        .context(format!("Invalid sidecar file: {}", path.display()))?;
+    if sidecar.version != SIDECAR_VERSION {
+        eyre::bail!(
+            "Unsupported sidecar version {}, expected {}",
+            sidecar.version,
+            SIDECAR_VERSION
+        );
+    }
+    Ok(Some(sidecar))
+}
+
+/// Picks file names of extracted wems, and records them in the sidecar.
+struct NamedWriter<'a> {
+    dir: PathBuf,
+    template: Option<&'a str>,
+    names: &'a NameDb,
+    bank: String,
+    /// HIRC object playing each wem.
+    owners: HashMap<u32, u32>,
+    /// Events able to play each wem.
+    events: HashMap<u32, Vec<u32>>,
+    used: HashSet<String>,
+    files: BTreeMap<String, u32>,
+    count: usize,
+}
+
+impl<'a> NamedWriter<'a> {
+    fn new(
+        dir: impl AsRef<Path>,
+        source_path: &Path,
+        names: &'a NameDb,
+        options: &'a ExtractOptions,
+        source_bnk: Option<&Bnk>,
+    ) -> eyre::Result<Self> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let source_name = source_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let mut writer = NamedWriter {
+            dir: dir.to_path_buf(),
+            template: options.template.as_deref(),
+            names,
+            // strip all extensions, e.g. `.sbnk.1.X64`
+            bank: source_name
+                .split('.')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            owners: HashMap::new(),
+            events: HashMap::new(),
+            used: HashSet::new(),
+            files: BTreeMap::new(),
+            count: 0,
+        };
+        // only HIRC is needed if naming by template
+        if writer.template.is_some() {
+            if let Some(bnk) = source_bnk {
+                writer.add_bnk(bnk)?;
+            }
+            for path in options.reference_paths.iter() {
+                writer.add_bnk(&bnk::load_file(path)?)?;
+            }
+        }
+        Ok(writer)
+    }
+
+    fn add_bnk(&mut self, bnk: &Bnk) -> eyre::Result<()> {
+        for entry in hirc::entries(bnk)? {
+            let Some(owner) = hirc::entry_id(&entry) else {
+                continue;
+            };
+            for reference in hirc::references(&entry) {
+                if reference.kind == RefKind::Media {
+                    self.owners.entry(reference.id).or_insert(owner);
+                }
+            }
+        }
+        for (media, events) in graph::media_events(bnk)? {
+            self.events.entry(media).or_default().extend(events);
+        }
+        Ok(())
+    }
+
+    fn next_path(&mut self, id: u32, index: usize) -> PathBuf {
+        self.count += 1;
+        let Some(template) = self.template else {
+            return self.dir.join(format!("{}.wem", id));
+        };
+
+        let name = self
+            .names
+            .get(id)
+            .or_else(|| self.names.get(*self.owners.get(&id)?))
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| id.to_string());
+        let event = self.events.get(&id).map(|events| {
+            // prefer events with known names
+            events
+                .iter()
+                .find_map(|e| self.names.get(*e).map(|s| s.to_string()))
+                .unwrap_or_else(|| events[0].to_string())
+        });
+        let vars = [
+            ("id", id.to_string()),
+            ("name", name),
+            ("event", event.unwrap_or_else(|| "none".to_string())),
+            ("bank", self.bank.clone()),
+            ("index", index.to_string()),
+        ];
+
+        let stem = sanitize(&render(template, &vars));
+        let mut file_name = format!("{}.wem", stem);
+        let mut suffix = index;
+        while !self.used.insert(file_name.clone()) {
+            file_name = format!("{}_{}.wem", stem, suffix);
+            suffix += 1;
+        }
+        self.files.insert(file_name.clone(), id);
+        self.dir.join(file_name)
+    }
+
+    /// Write sidecar if files are named by template, returns the count of files.
+    fn finish(self) -> eyre::Result<usize> {
+        if self.template.is_none() {
+            return Ok(self.count);
+        }
+
+        // keep files from previous extractions to the same directory
+        let mut files = read_sidecar(&self.dir)
+            .ok()
+            .flatten()
+            .map(|s| s.files)
+            .unwrap_or_default();
+        files.extend(self.files);
+
+        let sidecar = Sidecar {
+            version: SIDECAR_VERSION,
+            files,
+        };
+        let path = self.dir.join(SIDECAR_FILE);
+        let mut file =
+            File::create(&path).context(format!("Failed to write sidecar: {}", path.display()))?;
+        file.write_all(serde_json::to_string_pretty(&sidecar)?.as_bytes())?;
+        Ok(self.count)
+    }
+}
+
+fn render(template: &str, vars: &[(&str, String)]) -> String {
+    let mut result = template.to_string();
+    for (key, value) in vars {
+        result = result.replace(&format!("{{{}}}", key), value);
+    }
+    result
+}
+
+/// Replace characters not allowed in file names.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "_".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let vars = [
+            ("id", "123".to_string()),
+            ("name", "Play_Hit".to_string()),
+            ("event", "none".to_string()),
+        ];
+        assert_eq!(render("{name}_{id}", &vars), "Play_Hit_123");
+        assert_eq!(render("{event}/{bank}", &vars), "none/{bank}");
+        assert_eq!(sanitize("none/{bank}"), "none_{bank}");
+        assert_eq!(sanitize("a:b?."), "a_b_");
+    }
+
+    #[test]
+    fn test_next_path_collision() {
+        let names = NameDb::default();
+        let mut writer = NamedWriter {
+            dir: PathBuf::new(),
+            template: Some("{bank}"),
+            names: &names,
+            bank: "bgm".to_string(),
+            owners: HashMap::new(),
+            events: HashMap::new(),
+            used: HashSet::new(),
+            files: BTreeMap::new(),
+            count: 0,
+        };
+        let paths: Vec<_> = [(1, 1), (2, 1), (3, 1), (4, 2)]
+            .into_iter()
+            .map(|(id, index)| writer.next_path(id, index))
+            .collect();
+        assert_eq!(
+            paths,
+            ["bgm.wem", "bgm_1.wem", "bgm_2.wem", "bgm_3.wem"].map(PathBuf::from)
+        );
+        assert_eq!(writer.files.len(), 4);
+    }
+}
@@ -1,15 +1,24 @@
+mod bnk;
+mod cli;
 mod command;
 mod event;
+mod extract;
 mod logger;
 mod loudness;
+mod manifest;
+mod names;
+mod patch;
+mod pck;
 mod service;
+mod settings;
 mod subprocess;
+mod wem;
 
 use std::sync::OnceLock;
 
 use tauri::{AppHandle, Manager as _};
 
-use crate::service::TranscodeService;
+use crate::service::{NameService, SessionService, SettingsService, TranscodeService};
 
 static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
 
@@ -30,9 +39,19 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(TranscodeService::new())
+        .manage(SessionService::new())
         .setup(|app| {
             let _ = APP_HANDLE.set(app.handle().clone());
+
+            let config_dir = app.path().app_config_dir()?;
+            let exe_dir = std::env::current_exe()
+                .ok()
+                .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()));
+            let settings = SettingsService::load(config_dir, exe_dir.as_deref());
+            app.manage(TranscodeService::from_settings(&settings.get()));
+            app.manage(NameService::from_settings(&settings.get()));
+            app.manage(settings);
+
             let main_window = app.get_webview_window("main").unwrap();
             main_window
                 .set_title(&format!(
@@ -46,17 +65,54 @@ pub fn run() {
             command::bnk_load_file,
             command::bnk_save_file,
             command::bnk_extract_data,
+            command::bnk_validate,
+            command::bnk_resolve_event,
+            command::bnk_diff,
+            command::bnk_merge,
+            command::bnk_export_text,
+            command::bnk_import_text,
+            command::bnk_session_open,
+            command::bnk_session_close,
+            command::bnk_query_hirc,
+            command::bnk_session_set_field,
+            command::bnk_session_replace_entry,
+            command::bnk_session_replace_media,
+            command::bnk_session_remove_media,
+            command::bnk_session_save,
             command::pck_load_basic_data,
             command::pck_extract_data,
             command::pck_save_file,
+            command::pck_session_open,
+            command::pck_session_close,
+            command::pck_session_replace_wem,
+            command::pck_session_remove_wem,
+            command::pck_session_save,
             command::get_exe_path,
             command::env_get_var,
+            command::settings_get,
+            command::settings_update,
             command::transcode_set_paths,
+            command::transcode_set_wem_decoder,
+            command::transcode_set_wem_encoder,
             command::transcode_auto_detect_paths,
             command::transcode_check,
             command::transcode_auto_transcode,
+            command::transcode_batch,
+            command::transcode_conversion_share_sets,
             command::loudness_get_info,
+            command::wem_get_info,
+            command::manifest_build,
+            command::patch_create,
+            command::patch_apply,
+            command::names_load_files,
+            command::names_hash,
+            command::names_lookup,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Entry of the headless command line binary.
+pub fn run_cli() -> std::process::ExitCode {
+    cli::run()
+}
@@ -0,0 +1,269 @@
+//! Declarative mod build manifest.
+//!
+//! A manifest describes how to produce patched banks from vanilla files,
+//! so a mod can be rebuilt reproducibly and reviewed as text.
+//!
+//! ```toml
+//! version = 1
+//!
+//! [[bank]]
+//! source = "vanilla/Wp00_Cmn_m.sbnk.1.X64"
+//! output = "out/Wp00_Cmn_m.sbnk.1.X64"
+//!
+//! [[bank.media]]
+//! id = 123456
+//! file = "audio/hit.wav"
+//! conversion = "adpcm"
+//!
+//! [[bank.hirc]]
+//! id = 654321
+//! pointer = "/music_track_initial_values/playlist/0/play_at"
+//! value = -1200.0
+//! ```
+//!
+//! Relative paths are resolved against the manifest directory.
+//! Json manifests with the same structure are also accepted.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bnk::{
+        self, hirc,
+        track_sync::{self, TrackSyncReport},
+    },
+    patch,
+    service::{ConversionPreset, TranscodeOptions, TranscodeService},
+    wem::WemInfo,
+};
+
+const MANIFEST_VERSION: u32 = 1;
+const DEFAULT_CACHE_DIR: &str = ".build";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub version: u32,
+    /// Directory for transcoded intermediate files.
+    pub cache_dir: Option<PathBuf>,
+    #[serde(default, rename = "bank")]
+    pub banks: Vec<BankTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BankTarget {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    #[serde(default)]
+    pub media: Vec<MediaReplacement>,
+    #[serde(default)]
+    pub hirc: Vec<HircEdit>,
+}
+
+/// Replace or add an embedded wem. Non-wem files are transcoded first.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MediaReplacement {
+    pub id: u32,
+    pub file: PathBuf,
+    /// Conversion of non-wem files, the default conversion if not set.
+    /// `matchSource` uses the codec of the replaced wem in the source bank.
+    pub conversion: Option<ConversionPreset>,
+}
+
+/// Set a field of a HIRC entry, addressed by JSON pointer
+/// into the entry as shown by `bnk_load_file`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HircEdit {
+    pub id: u32,
+    pub pointer: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildReport {
+    pub banks: Vec<BankBuildReport>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BankBuildReport {
+    pub output: String,
+    pub media_replaced: usize,
+    pub hirc_edited: usize,
+    pub track_sync: TrackSyncReport,
+}
+
+impl Manifest {
+    pub fn from_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read manifest: {}", path.display()))?;
+        let manifest: Manifest = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => toml::from_str(&content)?,
+        };
+        if manifest.version != MANIFEST_VERSION {
+            eyre::bail!(
+                "Unsupported manifest version {}, expected {}",
+                manifest.version,
+                MANIFEST_VERSION
+            );
+        }
+
+        Ok(manifest)
+    }
+}
+
+/// Build all targets of the manifest at `path`.
+pub fn build(path: impl AsRef<Path>, transcoder: &TranscodeService) -> eyre::Result<BuildReport> {
+    let path = path.as_ref();
+    let manifest = Manifest::from_file(path)?;
+    let root = path.parent().unwrap_or(Path::new(""));
+    let cache_dir = root.join(
+        manifest
+            .cache_dir
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_CACHE_DIR)),
+    );
+
+    let mut report = BuildReport::default();
+    for (index, target) in manifest.banks.iter().enumerate() {
+        let bank_report = build_bank(target, root, &cache_dir.join(index.to_string()), transcoder)
+            .context(format!("Failed to build bank: {}", target.output.display()))?;
+        report.banks.push(bank_report);
+    }
+
+    Ok(report)
+}
+
+fn build_bank(
+    target: &BankTarget,
+    root: &Path,
+    cache_dir: &Path,
+    transcoder: &TranscodeService,
+) -> eyre::Result<BankBuildReport> {
+    let output = root.join(&target.output);
+    log::info!("Building {}", output.display());
+
+    let mut bnk = bnk::load_file(root.join(&target.source))?;
+
+    let mut wem_paths = vec![];
+    for media in target.media.iter() {
+        let conversion = media
+            .conversion
+            .clone()
+            .unwrap_or_else(|| transcoder.conversion())
+            .resolve(media_info(&mut bnk, media.id).as_ref());
+        let wem_path = resolve_wem(
+            &root.join(&media.file),
+            media.id,
+            &conversion,
+            cache_dir,
+            transcoder,
+        )?;
+        let data =
+            fs::read(&wem_path).context(format!("Failed to read wem: {}", wem_path.display()))?;
+        bnk::replace_media(&mut bnk, media.id, data)?;
+        wem_paths.push((media.id, wem_path));
+    }
+    // before HIRC edits, explicit values in manifest win
+    let infos = track_sync::read_infos(wem_paths.iter().map(|(id, path)| (*id, path.as_path())));
+    let track_sync = track_sync::sync_tracks(&mut bnk, &infos)?;
+
+    let mut edits: HashMap<u32, Vec<&HircEdit>> = HashMap::new();
+    for edit in target.hirc.iter() {
+        edits.entry(edit.id).or_default().push(edit);
+    }
+    let mut edited = 0;
+    hirc::edit_entries(&mut bnk, |entry| {
+        let Some(entry_edits) = hirc::entry_id(entry).and_then(|id| edits.remove(&id)) else {
+            return Ok(false);
+        };
+        for edit in entry_edits {
+            hirc::set_field(entry, &edit.pointer, edit.value.clone())?;
+            edited += 1;
+        }
+        Ok(true)
+    })?;
+    if let Some(id) = edits.keys().next() {
+        eyre::bail!("HIRC entry {} not found in {}", id, target.source.display());
+    }
+
+    bnk::save_file(&mut bnk, &output)?;
+
+    Ok(BankBuildReport {
+        output: output.to_string_lossy().to_string(),
+        media_replaced: target.media.len(),
+        hirc_edited: edited,
+        track_sync,
+    })
+}
+
+/// Get a wem file for the media source, transcoding into cache if needed.
+/// Cached results are reused while the source content, conversion and
+/// encoder backend are unchanged, recorded in a key file next to them.
+fn resolve_wem(
+    source: &Path,
+    id: u32,
+    conversion: &ConversionPreset,
+    cache_dir: &Path,
+    transcoder: &TranscodeService,
+) -> eyre::Result<PathBuf> {
+    if !source.is_file() {
+        eyre::bail!("Media file not found: {}", source.display());
+    }
+    if source.extension().is_some_and(|ext| ext == "wem") {
+        return Ok(source.to_path_buf());
+    }
+
+    let cached = cache_dir.join(format!("{}.wem", id));
+    let key_path = cache_dir.join(format!("{}.wem.key", id));
+    let key = format!(
+        "source={}\nsha256={}\nconversion={:?}\nencoder={}\n",
+        source.display(),
+        patch::hash_file(source)?,
+        conversion,
+        transcoder.encoder_backend()
+    );
+    let cached_key = fs::read_to_string(&key_path).ok();
+    if cached.is_file() && cached_key.as_deref() == Some(key.as_str()) {
+        log::debug!("Using cached wem: {}", cached.display());
+        return Ok(cached);
+    }
+
+    fs::create_dir_all(cache_dir)?;
+    // copy source into cache, so intermediate files never land next to it
+    let staged = cache_dir.join(format!(
+        "{}.{}",
+        id,
+        source.extension().unwrap_or_default().to_string_lossy()
+    ));
+    fs::copy(source, &staged)?;
+    let options = TranscodeOptions {
+        conversion: Some(conversion.clone()),
+        source_wem: None,
+    };
+    // drop the key first, so a failed transcode is not taken as cached
+    let _ = fs::remove_file(&key_path);
+    transcoder.auto_transcode(&staged, &cached, &options)?;
+    fs::write(&key_path, key)?;
+
+    Ok(cached)
+}
+
+/// Header of an embedded wem.
+fn media_info(bnk: &mut re_sound::bnk::Bnk, id: u32) -> Option<WemInfo> {
+    let (entries, data_list) = bnk::media_sections(bnk)?;
+    let index = entries.iter().position(|entry| entry.id == id)?;
+    WemInfo::from_bytes(data_list.get(index)?).ok()
+}
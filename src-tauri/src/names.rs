@@ -0,0 +1,128 @@
+//! Reverse lookup of Wwise ids from object names.
+//!
+//! Wwise ids are FNV-1 32-bit hashes of lowercased object names, so names
+//! from wordlists (`wwnames.txt` style, one name per line) can be hashed
+//! and matched against ids found in banks.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use eyre::Context;
+use re_sound::bnk::{Bnk, SectionPayload};
+use serde_json::Value;
+
+const FNV_OFFSET: u32 = 2166136261;
+const FNV_PRIME: u32 = 16777619;
+
+/// Wwise id of an object name.
+pub fn hash(name: &str) -> u32 {
+    name.bytes().fold(FNV_OFFSET, |hash, b| {
+        hash.wrapping_mul(FNV_PRIME) ^ b.to_ascii_lowercase() as u32
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct NameDb {
+    names: HashMap<u32, String>,
+}
+
+impl NameDb {
+    /// Add a name, returns its id. The first name added wins on collisions.
+    pub fn add(&mut self, name: &str) -> u32 {
+        let id = hash(name);
+        self.names.entry(id).or_insert_with(|| name.to_string());
+        id
+    }
+
+    /// Load a wordlist, returns the count of names read.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> eyre::Result<usize> {
+        let path = path.as_ref();
+        let bytes =
+            fs::read(path).context(format!("Failed to read wordlist: {}", path.display()))?;
+        let content = String::from_utf8_lossy(&bytes);
+
+        let mut count = 0;
+        for line in content.lines() {
+            let name = line.trim();
+            if name.is_empty() || name.starts_with('#') {
+                continue;
+            }
+            self.add(name);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(|s| s.as_str())
+    }
+
+    /// Names of the ids found.
+    pub fn lookup(&self, ids: impl IntoIterator<Item = u32>) -> BTreeMap<u32, String> {
+        ids.into_iter()
+            .filter_map(|id| Some((id, self.get(id)?.to_string())))
+            .collect()
+    }
+
+    /// Names of bank, HIRC objects and every id they reference,
+    /// e.g. events, buses, state groups and states.
+    pub fn lookup_bnk(&self, bnk: &Bnk) -> eyre::Result<BTreeMap<u32, String>> {
+        let mut values = vec![];
+        for section in bnk.sections.iter() {
+            // wem payloads hold no names
+            if matches!(section.payload, SectionPayload::Data { .. }) {
+                continue;
+            }
+            values.push(serde_json::to_value(section)?);
+        }
+        Ok(self.lookup_values(&values))
+    }
+
+    /// Names of serialized objects and every id they reference.
+    pub fn lookup_values(&self, values: &[Value]) -> BTreeMap<u32, String> {
+        let mut ids = vec![];
+        for value in values {
+            collect_ids(value, false, &mut ids);
+        }
+        self.lookup(ids)
+    }
+}
+
+/// Collect numbers of fields named like `id`, `*_id` or `*_ids`.
+fn collect_ids(value: &Value, is_id: bool, ids: &mut Vec<u32>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter() {
+                let is_id = key == "id" || key.ends_with("_id") || key.ends_with("_ids");
+                collect_ids(child, is_id, ids);
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_ids(item, is_id, ids)),
+        Value::Number(n) if is_id => {
+            if let Some(id) = n.as_u64().and_then(|id| u32::try_from(id).ok())
+                && id != 0
+            {
+                ids.push(id);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(""), FNV_OFFSET);
+        assert_eq!(hash("Init"), 1355168291);
+        assert_eq!(hash("Master Audio Bus"), 3803692087);
+        assert_eq!(hash("master audio bus"), hash("MASTER AUDIO BUS"));
+    }
+}
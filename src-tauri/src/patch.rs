@@ -0,0 +1,655 @@
+//! Distributable patches against vanilla bnk and pck files.
+//!
+//! A patch only carries what a mod changed and is applied to the user's
+//! own vanilla copy, so game files never need to be redistributed.
+//!
+//! Layout: magic `MHWSPTCH`, u32 LE version, u32 LE manifest length,
+//! json [`PatchManifest`], then the blobs referenced by the manifest.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Cursor, Read, Write},
+    path::Path,
+};
+
+use eyre::Context;
+use indexmap::IndexMap;
+use re_sound::{
+    bnk::{Bnk, SectionPayload},
+    pck::{Pck, PckHeader},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bnk::{
+        self,
+        diff::{self, FieldChange},
+        hirc,
+    },
+    pck::{self, WemData},
+};
+
+const PATCH_MAGIC: &[u8; 8] = b"MHWSPTCH";
+const PATCH_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchManifest {
+    /// Sha256 of the vanilla file the patch applies to.
+    pub source_hash: String,
+    /// Sha256 of the modded file the patch was created from.
+    pub result_hash: String,
+    pub content: PatchContent,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PatchContent {
+    Bnk(BnkPatch),
+    Pck(PckPatch),
+}
+
+/// Range in the blob area after the manifest.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Blob {
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BnkPatch {
+    /// Full payload of changed sections other than DIDX, DATA and HIRC.
+    pub sections: Vec<SectionPatch>,
+    pub media: Vec<MediaPatch>,
+    /// Wem ids in DIDX order of the modded bank.
+    pub media_order: Vec<u32>,
+    pub hirc: Vec<HircPatch>,
+    /// Entry ids in HIRC order of the modded bank.
+    pub hirc_order: Vec<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SectionPatch {
+    pub magic: String,
+    pub payload: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum MediaPatch {
+    /// Replace or add a wem.
+    Set {
+        id: u32,
+        blob: Blob,
+    },
+    Remove {
+        id: u32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum HircPatch {
+    Edit { id: u32, fields: Vec<FieldChange> },
+    Add { id: u32, entry: Value },
+    Remove { id: u32 },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PckPatch {
+    /// Header of the modded package.
+    pub header: PckHeader,
+    /// Changed or added wems, others are copied from vanilla.
+    pub wems: Vec<WemPatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WemPatch {
+    pub id: u32,
+    pub language_id: u32,
+    pub blob: Blob,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchSummary {
+    pub kind: String,
+    pub source_hash: String,
+    pub result_hash: String,
+    /// Number of changed media or wem entries.
+    pub media_changed: usize,
+    pub hirc_changed: usize,
+    pub blob_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatchApplyReport {
+    pub expected_hash: String,
+    pub result_hash: String,
+    /// Whether the output is identical to the modded file.
+    /// A mismatch usually means a different writer version, not a broken file.
+    pub matched: bool,
+}
+
+/// Create a patch from a vanilla and a modded file of the same kind.
+pub fn create(
+    vanilla_path: impl AsRef<Path>,
+    modded_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+) -> eyre::Result<PatchSummary> {
+    let vanilla_path = vanilla_path.as_ref();
+    let modded_path = modded_path.as_ref();
+    let source_hash = hash_file(vanilla_path)?;
+    let result_hash = hash_file(modded_path)?;
+
+    let mut blobs = BlobWriter::default();
+    let content = match &read_magic(vanilla_path)? {
+        b"BKHD" => {
+            let vanilla = bnk::load_file(vanilla_path)?;
+            let modded = bnk::load_file(modded_path)?;
+            PatchContent::Bnk(create_bnk(&vanilla, &modded, &mut blobs)?)
+        }
+        b"AKPK" => {
+            let mut vanilla = open_pck(vanilla_path)?;
+            let mut modded = open_pck(modded_path)?;
+            PatchContent::Pck(create_pck(&mut vanilla, &mut modded, &mut blobs)?)
+        }
+        _ => eyre::bail!("Unsupported file type: {}", vanilla_path.display()),
+    };
+
+    let (kind, media_changed, hirc_changed) = match &content {
+        PatchContent::Bnk(patch) => ("bnk", patch.media.len(), patch.hirc.len()),
+        PatchContent::Pck(patch) => ("pck", patch.wems.len(), 0),
+    };
+    let manifest = PatchManifest {
+        source_hash,
+        result_hash,
+        content,
+    };
+    let json = serde_json::to_vec(&manifest)?;
+
+    let output_path = output_path.as_ref();
+    let file = File::create(output_path)
+        .context(format!("Failed to create patch: {}", output_path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(PATCH_MAGIC)?;
+    writer.write_all(&PATCH_VERSION.to_le_bytes())?;
+    writer.write_all(&(json.len() as u32).to_le_bytes())?;
+    writer.write_all(&json)?;
+    writer.write_all(&blobs.data)?;
+    writer.flush()?;
+
+    Ok(PatchSummary {
+        kind: kind.to_string(),
+        source_hash: manifest.source_hash,
+        result_hash: manifest.result_hash,
+        media_changed,
+        hirc_changed,
+        blob_size: blobs.data.len() as u64,
+    })
+}
+
+/// Rebuild the modded file from a vanilla copy.
+///
+/// Fails if the vanilla file is not the one the patch was created from.
+pub fn apply(
+    patch_path: impl AsRef<Path>,
+    vanilla_path: impl AsRef<Path>,
+    output_path: impl AsRef<Path>,
+) -> eyre::Result<PatchApplyReport> {
+    let vanilla_path = vanilla_path.as_ref();
+    let output_path = output_path.as_ref();
+    if let (Ok(a), Ok(b)) = (
+        fs::canonicalize(vanilla_path),
+        fs::canonicalize(output_path),
+    ) && a == b
+    {
+        eyre::bail!("Output path must be different from the vanilla file.");
+    }
+
+    let patch = PatchFile::read(patch_path.as_ref())?;
+    let manifest = &patch.manifest;
+    let vanilla_hash = hash_file(vanilla_path)?;
+    if vanilla_hash != manifest.source_hash {
+        eyre::bail!(
+            "Vanilla file does not match the patch, expected sha256 {}, got {}",
+            manifest.source_hash,
+            vanilla_hash
+        );
+    }
+
+    match &manifest.content {
+        PatchContent::Bnk(bnk_patch) => {
+            let mut bnk = bnk::load_file(vanilla_path)?;
+            apply_bnk(&mut bnk, bnk_patch, &patch)?;
+            bnk::save_file(&mut bnk, output_path)?;
+        }
+        PatchContent::Pck(pck_patch) => {
+            let mut vanilla = open_pck(vanilla_path)?;
+            apply_pck(&mut vanilla, pck_patch, &patch, output_path)?;
+        }
+    }
+
+    let result_hash = hash_file(output_path)?;
+    let matched = result_hash == manifest.result_hash;
+    if !matched {
+        log::warn!(
+            "Patched file differs from the original mod, expected sha256 {}, got {}",
+            manifest.result_hash,
+            result_hash
+        );
+    }
+
+    Ok(PatchApplyReport {
+        expected_hash: manifest.result_hash.clone(),
+        result_hash,
+        matched,
+    })
+}
+
+fn create_bnk(vanilla: &Bnk, modded: &Bnk, blobs: &mut BlobWriter) -> eyre::Result<BnkPatch> {
+    let mut patch = BnkPatch::default();
+
+    // sections
+    let other_sections = |bnk: &Bnk| -> eyre::Result<IndexMap<[u8; 4], Value>> {
+        let mut map = IndexMap::new();
+        for section in bnk.sections.iter() {
+            if !is_media_or_hirc(&section.payload) {
+                map.insert(section.magic, serde_json::to_value(&section.payload)?);
+            }
+        }
+        Ok(map)
+    };
+    let vanilla_sections = other_sections(vanilla)?;
+    let modded_sections = other_sections(modded)?;
+    if !vanilla_sections.keys().eq(modded_sections.keys()) {
+        eyre::bail!("Adding or removing sections other than DIDX, DATA and HIRC is not supported.");
+    }
+    for (magic, payload) in modded_sections {
+        if vanilla_sections.get(&magic) != Some(&payload) {
+            patch.sections.push(SectionPatch {
+                magic: String::from_utf8_lossy(&magic).to_string(),
+                payload,
+            });
+        }
+    }
+
+    // media
+    let vanilla_media = diff::media_map(vanilla);
+    let modded_media = diff::media_map(modded);
+    for (&id, media) in modded_media.iter() {
+        let Some(data) = media.data else {
+            eyre::bail!("DATA section is required to create patch.");
+        };
+        if vanilla_media.get(&id).and_then(|m| m.data) != Some(data) {
+            patch.media.push(MediaPatch::Set {
+                id,
+                blob: blobs.push(data),
+            });
+        }
+    }
+    for &id in vanilla_media.keys() {
+        if !modded_media.contains_key(&id) {
+            patch.media.push(MediaPatch::Remove { id });
+        }
+    }
+    patch.media_order = modded_media.keys().copied().collect();
+
+    // hirc
+    let vanilla_entries = diff::hirc_map(vanilla)?;
+    let modded_entries = diff::hirc_map(modded)?;
+    for (&id, entry) in modded_entries.iter() {
+        match vanilla_entries.get(&id) {
+            None => patch.hirc.push(HircPatch::Add {
+                id,
+                entry: entry.clone(),
+            }),
+            Some(vanilla_entry) if vanilla_entry != entry => patch.hirc.push(HircPatch::Edit {
+                id,
+                fields: diff::diff_values(vanilla_entry, entry),
+            }),
+            Some(_) => {}
+        }
+    }
+    for &id in vanilla_entries.keys() {
+        if !modded_entries.contains_key(&id) {
+            patch.hirc.push(HircPatch::Remove { id });
+        }
+    }
+    patch.hirc_order = modded_entries.keys().copied().collect();
+
+    Ok(patch)
+}
+
+fn apply_bnk(bnk: &mut Bnk, patch: &BnkPatch, file: &PatchFile) -> eyre::Result<()> {
+    for section_patch in patch.sections.iter() {
+        let Some(section) = bnk
+            .sections
+            .iter_mut()
+            .find(|s| s.magic == section_patch.magic.as_bytes())
+        else {
+            eyre::bail!("Section {} not found in vanilla bnk.", section_patch.magic);
+        };
+        section.payload = serde_json::from_value(section_patch.payload.clone())?;
+    }
+
+    let vanilla_media = diff::media_map(bnk);
+    if !patch.media.is_empty() || !vanilla_media.keys().eq(patch.media_order.iter()) {
+        let mut media = IndexMap::new();
+        for (&id, m) in vanilla_media.iter() {
+            let Some(data) = m.data else {
+                eyre::bail!("DATA section is required to apply patch.");
+            };
+            media.insert(id, data.to_vec());
+        }
+        for media_patch in patch.media.iter() {
+            match media_patch {
+                MediaPatch::Set { id, blob } => {
+                    media.insert(*id, file.blob(blob)?.to_vec());
+                }
+                MediaPatch::Remove { id } => {
+                    media.shift_remove(id);
+                }
+            }
+        }
+        let media = patch
+            .media_order
+            .iter()
+            .map(|id| {
+                let data = media
+                    .shift_remove(id)
+                    .ok_or_else(|| eyre::eyre!("Wem {} missing in patched bnk", id))?;
+                Ok((*id, data))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        bnk::set_media(bnk, media);
+    }
+
+    if !patch.hirc.is_empty() {
+        let mut entries = diff::hirc_map(bnk)?;
+        for hirc_patch in patch.hirc.iter() {
+            match hirc_patch {
+                HircPatch::Edit { id, fields } => {
+                    let entry = entries
+                        .get_mut(id)
+                        .ok_or_else(|| eyre::eyre!("HIRC entry {} not found in vanilla bnk", id))?;
+                    for change in fields {
+                        diff::apply_change(entry, change);
+                    }
+                }
+                HircPatch::Add { id, entry } => {
+                    entries.insert(*id, entry.clone());
+                }
+                HircPatch::Remove { id } => {
+                    entries.shift_remove(id);
+                }
+            }
+        }
+        let entries = patch
+            .hirc_order
+            .iter()
+            .map(|id| {
+                entries
+                    .shift_remove(id)
+                    .ok_or_else(|| eyre::eyre!("HIRC entry {} missing in patched bnk", id))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        hirc::set_entries(bnk, entries)?;
+    }
+
+    Ok(())
+}
+
+fn create_pck(
+    vanilla: &mut Pck,
+    modded: &mut Pck,
+    blobs: &mut BlobWriter,
+) -> eyre::Result<PckPatch> {
+    let vanilla_entries = vanilla.header().wem_entries.clone();
+    let header = modded.header().clone();
+
+    let mut wems = vec![];
+    for (index, entry) in header.wem_entries.iter().enumerate() {
+        let data = read_wem(modded, index)?;
+        let vanilla_index = vanilla_entries
+            .iter()
+            .position(|e| e.id == entry.id && e.language_id == entry.language_id);
+        let unchanged = match vanilla_index {
+            Some(vanilla_index) => read_wem(vanilla, vanilla_index)? == data,
+            None => false,
+        };
+        if !unchanged {
+            wems.push(WemPatch {
+                id: entry.id,
+                language_id: entry.language_id,
+                blob: blobs.push(&data),
+            });
+        }
+    }
+
+    Ok(PckPatch { header, wems })
+}
+
+fn apply_pck(
+    vanilla: &mut Pck,
+    patch: &PckPatch,
+    file: &PatchFile,
+    output_path: &Path,
+) -> eyre::Result<()> {
+    let vanilla_entries = vanilla.header().wem_entries.clone();
+    let mut header = patch.header.clone();
+
+    let mut data = Vec::with_capacity(header.wem_entries.len());
+    for entry in header.wem_entries.iter() {
+        let patched = patch
+            .wems
+            .iter()
+            .find(|w| w.id == entry.id && w.language_id == entry.language_id);
+        if let Some(wem) = patched {
+            data.push(WemData::Memory(file.blob(&wem.blob)?.to_vec()));
+            continue;
+        }
+        let Some(index) = vanilla_entries
+            .iter()
+            .position(|e| e.id == entry.id && e.language_id == entry.language_id)
+        else {
+            eyre::bail!(
+                "Wem {} (language {}) not found in vanilla pck",
+                entry.id,
+                entry.language_id
+            );
+        };
+        data.push(WemData::Source(index));
+    }
+
+    pck::write_file(&mut header, output_path, &data, Some(vanilla))
+}
+
+/// Patch file loaded in memory.
+struct PatchFile {
+    manifest: PatchManifest,
+    data: Vec<u8>,
+    /// Offset of the blob area in `data`.
+    blobs_start: usize,
+}
+
+impl PatchFile {
+    fn read(path: &Path) -> eyre::Result<Self> {
+        let data = fs::read(path).context(format!("Failed to read patch: {}", path.display()))?;
+        let mut reader = Cursor::new(data.as_slice());
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != PATCH_MAGIC {
+            eyre::bail!("Not a patch file: {}", path.display());
+        }
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        let version = u32::from_le_bytes(buf);
+        if version != PATCH_VERSION {
+            eyre::bail!(
+                "Unsupported patch version {}, expected {}",
+                version,
+                PATCH_VERSION
+            );
+        }
+        reader.read_exact(&mut buf)?;
+        let json_length = u32::from_le_bytes(buf) as usize;
+        let json_start = reader.position() as usize;
+        let json_end = json_start + json_length;
+        let Some(json) = data.get(json_start..json_end) else {
+            eyre::bail!("Patch file is truncated: {}", path.display());
+        };
+        let manifest = serde_json::from_slice(json).context("Failed to parse patch manifest")?;
+
+        Ok(PatchFile {
+            manifest,
+            data,
+            blobs_start: json_end,
+        })
+    }
+
+    fn blob(&self, blob: &Blob) -> eyre::Result<&[u8]> {
+        let start = self.blobs_start.checked_add(blob.offset as usize);
+        let end = start.and_then(|start| start.checked_add(blob.length as usize));
+        start
+            .zip(end)
+            .and_then(|(start, end)| self.data.get(start..end))
+            .ok_or_else(|| eyre::eyre!("Blob out of range in patch file"))
+    }
+}
+
+#[derive(Default)]
+struct BlobWriter {
+    data: Vec<u8>,
+}
+
+impl BlobWriter {
+    fn push(&mut self, data: &[u8]) -> Blob {
+        let blob = Blob {
+            offset: self.data.len() as u64,
+            length: data.len() as u64,
+        };
+        self.data.extend_from_slice(data);
+        blob
+    }
+}
+
+fn is_media_or_hirc(payload: &SectionPayload) -> bool {
+    matches!(
+        payload,
+        SectionPayload::Didx { .. } | SectionPayload::Data { .. } | SectionPayload::Hirc { .. }
+    )
+}
+
+pub fn hash_file(path: &Path) -> eyre::Result<String> {
+    let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut BufReader::new(file), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn read_magic(path: &Path) -> eyre::Result<[u8; 4]> {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .context(format!("Failed to read {}", path.display()))?;
+    Ok(magic)
+}
+
+fn open_pck(path: &Path) -> eyre::Result<Pck> {
+    Pck::from_file(&path.to_string_lossy())
+        .context(format!("Failed to parse pck: {}", path.display()))
+}
+
+fn read_wem(pck: &mut Pck, index: usize) -> eyre::Result<Vec<u8>> {
+    let mut reader = pck
+        .wem_reader(index)
+        .ok_or_else(|| eyre::eyre!("Wem index out of range in pck: {}", index))?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use re_sound::bnk::Section;
+    use serde_json::json;
+
+    use super::*;
+    use crate::bnk::hirc::tests::music_track;
+
+    fn test_bnk(entries: Vec<Value>, media: Vec<(u32, Vec<u8>)>) -> Bnk {
+        let mut bnk = Bnk {
+            sections: vec![
+                Section {
+                    magic: *b"BKHD",
+                    section_length: 0,
+                    payload: SectionPayload::Bkhd {
+                        version: 145,
+                        id: 1,
+                        unknown: vec![0; 12],
+                    },
+                },
+                Section {
+                    magic: *b"HIRC",
+                    section_length: 0,
+                    payload: SectionPayload::Hirc { entries: vec![] },
+                },
+            ],
+        };
+        bnk::set_media(&mut bnk, media);
+        hirc::set_entries(&mut bnk, entries).unwrap();
+        bnk
+    }
+
+    #[test]
+    fn test_bnk_patch_round_trip() {
+        let dir = std::env::temp_dir().join(format!("mhws-patch-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| -> PathBuf { dir.join(name) };
+
+        let mut vanilla = test_bnk(
+            vec![music_track(1, &[10]), music_track(2, &[20])],
+            vec![(10, vec![1; 64]), (20, vec![2; 64])],
+        );
+        bnk::save_file(&mut vanilla, path("vanilla.bnk")).unwrap();
+        // replace a wem, add another, edit a track and add a track
+        let mut edited = music_track(1, &[10, 30]);
+        hirc::set_field(
+            &mut edited,
+            "/music_track_initial_values/playlist/1/play_at",
+            json!(1000.0),
+        )
+        .unwrap();
+        let mut modded = test_bnk(
+            vec![edited, music_track(2, &[20]), music_track(3, &[30])],
+            vec![(10, vec![3; 80]), (20, vec![2; 64]), (30, vec![4; 16])],
+        );
+        bnk::save_file(&mut modded, path("modded.bnk")).unwrap();
+
+        let summary = create(path("vanilla.bnk"), path("modded.bnk"), path("mod.patch")).unwrap();
+        assert_eq!(summary.media_changed, 2);
+        assert_eq!(summary.hirc_changed, 2);
+        assert_eq!(summary.blob_size, 96);
+
+        let report = apply(path("mod.patch"), path("vanilla.bnk"), path("out.bnk")).unwrap();
+        assert!(report.matched);
+        assert_eq!(
+            fs::read(path("out.bnk")).unwrap(),
+            fs::read(path("modded.bnk")).unwrap()
+        );
+        // wrong vanilla file
+        assert!(apply(path("mod.patch"), path("modded.bnk"), path("out2.bnk")).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
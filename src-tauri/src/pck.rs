@@ -0,0 +1,272 @@
+//! Helpers on top of `re_sound::pck`.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use re_sound::pck::{Pck, PckHeader, PckWemEntry};
+
+/// Size of a wem entry in the header lookup table.
+const WEM_ENTRY_SIZE: u32 = 20;
+/// Language of non-localized sounds.
+const SFX_LANGUAGE: &str = "sfx";
+
+/// Where the data of a wem entry comes from when writing a package.
+pub enum WemData {
+    /// Read from a wem file on disk.
+    File(PathBuf),
+    /// Streamed from the entry at index of the source package.
+    Source(usize),
+    /// Wem data already in memory.
+    Memory(Vec<u8>),
+}
+
+/// Write package with wem data in header entry order.
+///
+/// Offsets and lengths of header entries are recomputed from `data`.
+pub fn write_file(
+    header: &mut PckHeader,
+    output_path: impl AsRef<Path>,
+    data: &[WemData],
+    mut source: Option<&mut Pck>,
+) -> eyre::Result<()> {
+    let output_path = output_path.as_ref();
+    if data.len() != header.wem_entries.len() {
+        eyre::bail!(
+            "Number of wem entries ({}) and data ({}) do not match.",
+            header.wem_entries.len(),
+            data.len()
+        );
+    }
+
+    // update header with new wem lengths, aligned to entry block size
+    let source_lengths = match source.as_mut() {
+        Some(pck) => pck.header().wem_entries.iter().map(|e| e.length).collect(),
+        None => vec![],
+    };
+    let mut offset = header.get_wem_offset_start();
+    let mut paddings = Vec::with_capacity(data.len());
+    for (entry, data) in header.wem_entries.iter_mut().zip(data.iter()) {
+        let length = match data {
+            WemData::File(path) => path
+                .metadata()
+                .context(format!("Failed to read wem file: {}", path.display()))?
+                .len() as u32,
+            WemData::Memory(data) => data.len() as u32,
+            WemData::Source(index) => *source_lengths
+                .get(*index)
+                .ok_or_else(|| eyre::eyre!("Wem index out of range in source pck: {}", index))?,
+        };
+        let block_size = entry.one.max(1);
+        let padding = offset.next_multiple_of(block_size) - offset;
+        offset += padding;
+        paddings.push(padding);
+
+        // offset is counted in blocks
+        entry.offset = offset / block_size;
+        entry.length = length;
+        offset += length;
+    }
+
+    let file = File::create(output_path)?;
+    let mut writer = BufWriter::new(file);
+    header.write_to(&mut writer)?;
+
+    for (data, padding) in data.iter().zip(paddings) {
+        writer.write_all(&vec![0; padding as usize])?;
+        match data {
+            WemData::File(path) => {
+                let mut input_file = File::open(path)?;
+                io::copy(&mut input_file, &mut writer)?;
+            }
+            WemData::Memory(data) => writer.write_all(data)?,
+            WemData::Source(index) => {
+                let Some(pck) = source.as_mut() else {
+                    eyre::bail!("Source pck is required to copy original wem data.");
+                };
+                let mut wem_reader = pck.wem_reader(*index).ok_or_else(|| {
+                    eyre::eyre!("Wem index out of range in source pck: {}", index)
+                })?;
+                io::copy(&mut wem_reader, &mut writer)
+                    .context("Failed to copy wem data from source pck")?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Resolve language id by name in the package string table,
+/// or the sfx language if no name provided.
+pub fn language_id(header: &PckHeader, language: Option<&str>) -> eyre::Result<u32> {
+    let name = language.unwrap_or(SFX_LANGUAGE);
+    if let Some(string) = header
+        .string_table
+        .iter()
+        .find(|s| s.value.eq_ignore_ascii_case(name))
+    {
+        return Ok(string.index);
+    }
+    if language.is_none() {
+        return Ok(0);
+    }
+
+    let available = header
+        .string_table
+        .iter()
+        .map(|s| s.value.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    eyre::bail!(
+        "Language '{}' not found in pck, available: {}",
+        name,
+        available
+    )
+}
+
+/// Insert a new wem entry, keeping the lookup table sorted by id.
+///
+/// Offset and length are filled when the package is written.
+pub fn add_wem_entry(header: &mut PckHeader, id: u32, language_id: u32) -> eyre::Result<()> {
+    if header
+        .wem_entries
+        .iter()
+        .any(|e| e.id == id && e.language_id == language_id)
+    {
+        eyre::bail!(
+            "Wem {} already exists in pck for language {}",
+            id,
+            language_id
+        );
+    }
+
+    // use the same block size as existing entries
+    let block_size = header.wem_entries.first().map(|e| e.one).unwrap_or(1);
+    let index = header
+        .wem_entries
+        .partition_point(|e| (e.id, e.language_id) < (id, language_id));
+    header.wem_entries.insert(
+        index,
+        PckWemEntry {
+            id,
+            one: block_size,
+            length: 0,
+            offset: 0,
+            language_id,
+        },
+    );
+    header.header_length += WEM_ENTRY_SIZE;
+
+    Ok(())
+}
+
+/// Remove wem entries by id, in all languages if `language_id` not provided.
+/// Returns the number of removed entries.
+pub fn remove_wem_entry(header: &mut PckHeader, id: u32, language_id: Option<u32>) -> usize {
+    let count = header.wem_entries.len();
+    header
+        .wem_entries
+        .retain(|e| e.id != id || language_id.is_some_and(|lang| e.language_id != lang));
+    let removed = count - header.wem_entries.len();
+    header.header_length -= WEM_ENTRY_SIZE * removed as u32;
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Read};
+
+    use super::*;
+
+    /// Header without languages, banks and externals.
+    fn empty_header() -> PckHeader {
+        PckHeader {
+            // version, 4 table sizes, then 4 tables holding only their count
+            header_length: 4 + 16 + 4 + 4 + 4 + 4,
+            unk2: 1,
+            string_table: vec![],
+            bnk_table_data: vec![0; 4],
+            wem_entries: vec![],
+            unk_struct_data: vec![0; 4],
+        }
+    }
+
+    fn entry(id: u32, one: u32) -> PckWemEntry {
+        PckWemEntry {
+            id,
+            one,
+            length: 0,
+            offset: 0,
+            language_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_write_file_block_size() {
+        let mut header = empty_header();
+        header.wem_entries = vec![entry(1, 16), entry(2, 16), entry(3, 16)];
+        header.header_length += WEM_ENTRY_SIZE * 3;
+        let wems = [vec![1; 5], vec![2; 16], vec![3; 3]];
+        let data: Vec<_> = wems.iter().cloned().map(WemData::Memory).collect();
+
+        let path = std::env::temp_dir().join(format!("mhws-pck-test-{}.pck", std::process::id()));
+        write_file(&mut header, &path, &data, None).unwrap();
+
+        // data starts at 8 + 96 = 104, each wem aligned to 16 bytes
+        let offsets: Vec<_> = header.wem_entries.iter().map(|e| e.offset).collect();
+        let lengths: Vec<_> = header.wem_entries.iter().map(|e| e.length).collect();
+        assert_eq!(offsets, [7, 8, 9]);
+        assert_eq!(lengths, [5, 16, 3]);
+        let file = fs::read(&path).unwrap();
+        assert_eq!(file.len(), 9 * 16 + 3);
+        assert_eq!(&file[112..117], wems[0]);
+        assert!(file[117..128].iter().all(|&b| b == 0));
+
+        let mut pck = Pck::from_file(&path.to_string_lossy()).unwrap();
+        let entries = pck.header().wem_entries.clone();
+        assert_eq!(pck.header().header_length, header.header_length);
+        for (i, (read, written)) in entries.iter().zip(header.wem_entries.iter()).enumerate() {
+            assert_eq!(
+                (read.id, read.one, read.offset, read.length),
+                (written.id, written.one, written.offset, written.length)
+            );
+            let mut wem = vec![];
+            pck.wem_reader(i).unwrap().read_to_end(&mut wem).unwrap();
+            assert_eq!(wem, wems[i]);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_add_remove_wem_entry() {
+        let mut header = empty_header();
+        header.wem_entries = vec![entry(20, 16)];
+        header.header_length += WEM_ENTRY_SIZE;
+        let base_length = header.header_length;
+
+        add_wem_entry(&mut header, 30, 0).unwrap();
+        add_wem_entry(&mut header, 10, 0).unwrap();
+        add_wem_entry(&mut header, 20, 1).unwrap();
+        assert!(add_wem_entry(&mut header, 20, 0).is_err());
+        let keys: Vec<_> = header
+            .wem_entries
+            .iter()
+            .map(|e| (e.id, e.language_id))
+            .collect();
+        assert_eq!(keys, [(10, 0), (20, 0), (20, 1), (30, 0)]);
+        assert!(header.wem_entries.iter().all(|e| e.one == 16));
+        assert_eq!(header.header_length, base_length + WEM_ENTRY_SIZE * 3);
+
+        assert_eq!(remove_wem_entry(&mut header, 20, Some(1)), 1);
+        assert_eq!(remove_wem_entry(&mut header, 99, None), 0);
+        assert_eq!(remove_wem_entry(&mut header, 30, None), 1);
+        assert_eq!(header.wem_entries.len(), 2);
+        assert_eq!(header.header_length, base_length + WEM_ENTRY_SIZE);
+    }
+}
@@ -1,3 +1,9 @@
+mod names;
+mod session;
+mod settings;
 mod transcode;
 
+pub use names::*;
+pub use session::*;
+pub use settings::*;
 pub use transcode::*;
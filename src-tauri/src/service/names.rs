@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::{names::NameDb, settings::Settings};
+
+/// Directory of wordlists loaded on startup, next to the executable.
+const WORDLISTS_DIR: &str = "wordlists";
+
+pub struct NameService {
+    db: Mutex<NameDb>,
+}
+
+impl NameService {
+    pub fn new() -> Self {
+        let service = Self {
+            db: Mutex::new(NameDb::default()),
+        };
+        service.reset();
+        service
+    }
+
+    /// Service with default wordlists and the ones in settings.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let service = Self::new();
+        for path in &settings.wordlists {
+            if let Err(e) = service.load_file(path) {
+                log::warn!("{e:#}");
+            }
+        }
+        service
+    }
+
+    /// Load a wordlist, returns the count of names read.
+    pub fn load_file(&self, path: impl AsRef<Path>) -> eyre::Result<usize> {
+        let path = path.as_ref();
+        let count = self.db.lock().load_file(path)?;
+        log::info!("Loaded {} names from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// Drop all names and load the default wordlists again.
+    pub fn reset(&self) {
+        *self.db.lock() = NameDb::default();
+        match default_wordlists() {
+            Ok(paths) => {
+                for path in paths {
+                    if let Err(e) = self.load_file(&path) {
+                        log::warn!("{e:#}");
+                    }
+                }
+            }
+            Err(e) => log::debug!("No default wordlists loaded: {e}"),
+        }
+    }
+
+    pub fn db(&self) -> MutexGuard<'_, NameDb> {
+        self.db.lock()
+    }
+}
+
+/// Text files in `wordlists/` next to the executable.
+fn default_wordlists() -> eyre::Result<Vec<PathBuf>> {
+    let exe = std::env::current_exe()?;
+    let dir = exe
+        .parent()
+        .ok_or_else(|| eyre::eyre!("Executable has no parent directory"))?
+        .join(WORDLISTS_DIR);
+
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().unwrap_or_default() == "txt" {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
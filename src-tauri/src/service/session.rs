@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use eyre::Context;
+use parking_lot::Mutex;
+use re_sound::{
+    bnk::{Bnk, SectionPayload},
+    pck::{Pck, PckHeader},
+};
+use serde_json::Value;
+
+use crate::{
+    bnk::{
+        self, MediaRemoval, SaveReport, hirc, track_sync,
+        validate::{self, BankReport, KnownIds},
+    },
+    pck::{self, WemData},
+    wem::WemInfo,
+};
+
+/// A bank loaded once and kept in memory, addressed by handle.
+pub struct BnkSession {
+    pub path: PathBuf,
+    pub bnk: Bnk,
+    /// Serialized HIRC entries, built on first access.
+    entries: Option<Vec<Value>>,
+    /// Headers of wems replaced since last save.
+    replaced: HashMap<u32, WemInfo>,
+}
+
+impl BnkSession {
+    pub fn entries(&mut self) -> eyre::Result<&[Value]> {
+        if self.entries.is_none() {
+            self.entries = Some(hirc::entries(&self.bnk)?);
+        }
+        Ok(self.entries.as_deref().unwrap_or_default())
+    }
+
+    /// Set a field of HIRC entry by JSON pointer, returns the updated entry.
+    pub fn set_field(&mut self, id: u32, pointer: &str, value: Value) -> eyre::Result<Value> {
+        self.edit_entry(id, |entry| hirc::set_field(entry, pointer, value))
+    }
+
+    /// Replace the HIRC entry with the same id, counts and length are
+    /// recomputed from its arrays.
+    pub fn replace_entry(&mut self, entry: Value) -> eyre::Result<Value> {
+        let id = hirc::entry_id(&entry).ok_or_else(|| eyre::eyre!("HIRC entry without id"))?;
+        self.edit_entry(id, |value| {
+            *value = entry;
+            Ok(())
+        })
+    }
+
+    /// Replace or add embedded wem from file.
+    pub fn replace_media(&mut self, id: u32, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let data = fs::read(path).context(format!("Failed to read wem: {}", path.display()))?;
+        match WemInfo::from_bytes(&data) {
+            Ok(info) => {
+                self.replaced.insert(id, info);
+            }
+            Err(e) => log::warn!("MusicTrack metadata of {} will not be synced: {:#}", id, e),
+        }
+        bnk::replace_media(&mut self.bnk, id, data)
+    }
+
+    pub fn remove_media(&mut self, ids: &[u32], cleanup: bool) -> eyre::Result<Vec<MediaRemoval>> {
+        let removals = bnk::remove_media(&mut self.bnk, ids, cleanup)?;
+        if cleanup {
+            // MusicTrack entries may be rewritten
+            self.entries = None;
+        }
+        Ok(removals)
+    }
+
+    /// Write the in-memory bank, to its source path if no path provided.
+    ///
+    /// HIRC references are checked first, ids in `known` resolve besides the
+    /// bank's own. With errors the bank is only written if `force` is set.
+    /// MusicTrack sources using the replaced wems are updated before writing.
+    pub fn save(
+        &mut self,
+        path: Option<&str>,
+        known: &KnownIds,
+        force: bool,
+    ) -> eyre::Result<SaveReport> {
+        let path = path.map(PathBuf::from).unwrap_or_else(|| self.path.clone());
+        let validation = BankReport {
+            path: path.to_string_lossy().to_string(),
+            report: validate::validate(&self.bnk, known)?,
+        };
+        if validation.report.errors > 0 && !force {
+            return Ok(SaveReport::blocked(validation));
+        }
+
+        let track_sync = track_sync::sync_tracks(&mut self.bnk, &self.replaced)?;
+        if !track_sync.changes.is_empty() {
+            self.entries = None;
+        }
+        self.replaced.clear();
+
+        // write next to the target then rename, a failed write keeps the original
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        if let Err(e) = bnk::save_file(&mut self.bnk, &temp) {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        fs::rename(&temp, &path).context(format!("Failed to write bnk: {}", path.display()))?;
+        Ok(SaveReport {
+            saved: true,
+            validation,
+            track_sync,
+        })
+    }
+
+    fn edit_entry<F>(&mut self, id: u32, edit: F) -> eyre::Result<Value>
+    where
+        F: FnOnce(&mut Value) -> eyre::Result<()>,
+    {
+        let entries = self.entries()?;
+        let index = entries
+            .iter()
+            .position(|e| hirc::entry_id(e) == Some(id))
+            .ok_or_else(|| eyre::eyre!("HIRC entry not found: {}", id))?;
+        let mut value = entries[index].clone();
+        edit(&mut value)?;
+        if hirc::entry_id(&value) != Some(id) {
+            eyre::bail!("HIRC entry id can not be changed: {}", id);
+        }
+        // replaced entries may come with stale counts and length
+        hirc::update_sizes(&mut value)?;
+
+        let Some(entry) = self
+            .bnk
+            .sections
+            .iter_mut()
+            .filter_map(|s| match &mut s.payload {
+                SectionPayload::Hirc { entries } => Some(entries),
+                _ => None,
+            })
+            .flatten()
+            .nth(index)
+        else {
+            eyre::bail!("HIRC entry not found: {}", id);
+        };
+        *entry = serde_json::from_value(value.clone())
+            .map_err(|e| eyre::eyre!("Invalid HIRC entry {} after edit: {}", id, e))?;
+        if let Some(entries) = self.entries.as_mut() {
+            entries[index] = value.clone();
+        }
+        Ok(value)
+    }
+}
+
+/// A package kept open, wem data is streamed from it on save.
+pub struct PckSession {
+    pub path: PathBuf,
+    pck: Pck,
+    /// Edited header, the source header is kept in `pck`.
+    pub header: PckHeader,
+    /// Replaced wem files by id and language id.
+    overrides: HashMap<(u32, u32), PathBuf>,
+}
+
+impl PckSession {
+    pub fn has_data(&mut self) -> bool {
+        self.pck.has_data()
+    }
+
+    /// Replace wem data from file, adding the entry if missing.
+    pub fn replace_wem(
+        &mut self,
+        id: u32,
+        language: Option<&str>,
+        path: impl AsRef<Path>,
+    ) -> eyre::Result<()> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            eyre::bail!("Wem file not found: {}", path.display());
+        }
+        let language_id = pck::language_id(&self.header, language)?;
+        if !self
+            .header
+            .wem_entries
+            .iter()
+            .any(|e| e.id == id && e.language_id == language_id)
+        {
+            pck::add_wem_entry(&mut self.header, id, language_id)?;
+        }
+        self.overrides.insert((id, language_id), path.to_path_buf());
+        Ok(())
+    }
+
+    /// Remove wem entries, in all languages if no language provided.
+    /// Returns the number of removed entries.
+    pub fn remove_wem(&mut self, id: u32, language: Option<&str>) -> eyre::Result<usize> {
+        let language_id = language
+            .map(|_| pck::language_id(&self.header, language))
+            .transpose()?;
+        self.overrides
+            .retain(|&(wem_id, lang), _| wem_id != id || language_id.is_some_and(|l| l != lang));
+        Ok(pck::remove_wem_entry(&mut self.header, id, language_id))
+    }
+
+    /// Write package, untouched wems are streamed from the source package.
+    pub fn save(&mut self, output_path: &str) -> eyre::Result<()> {
+        let mut header = self.header.clone();
+        if !self.pck.has_data() {
+            // no data, write header only
+            let mut writer = BufWriter::new(File::create(output_path)?);
+            return Ok(header.write_to(&mut writer)?);
+        }
+
+        let source_entries = self.pck.header().wem_entries.clone();
+        let mut wem_data = Vec::with_capacity(self.header.wem_entries.len());
+        for entry in self.header.wem_entries.iter() {
+            if let Some(path) = self.overrides.get(&(entry.id, entry.language_id)) {
+                wem_data.push(WemData::File(path.clone()));
+                continue;
+            }
+            let index = source_entries
+                .iter()
+                .position(|e| e.id == entry.id && e.language_id == entry.language_id)
+                .ok_or_else(|| eyre::eyre!("Wem data not found for id {}", entry.id))?;
+            wem_data.push(WemData::Source(index));
+        }
+
+        pck::write_file(&mut header, output_path, &wem_data, Some(&mut self.pck))
+    }
+}
+
+pub struct SessionService {
+    next_handle: AtomicU32,
+    bnks: Mutex<HashMap<u32, BnkSession>>,
+    pcks: Mutex<HashMap<u32, PckSession>>,
+}
+
+impl SessionService {
+    pub fn new() -> Self {
+        Self {
+            next_handle: AtomicU32::new(1),
+            bnks: Mutex::new(HashMap::new()),
+            pcks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load bnk into a new session, returns its handle.
+    pub fn open_bnk(&self, path: impl AsRef<Path>) -> eyre::Result<u32> {
+        let path = path.as_ref();
+        let bnk = bnk::load_file(path)?;
+        let handle = self.next_handle();
+        self.bnks.lock().insert(
+            handle,
+            BnkSession {
+                path: path.to_path_buf(),
+                bnk,
+                entries: None,
+                replaced: HashMap::new(),
+            },
+        );
+        log::debug!("Bnk session {} opened: {}", handle, path.display());
+        Ok(handle)
+    }
+
+    /// Returns whether the session existed.
+    pub fn close_bnk(&self, handle: u32) -> bool {
+        self.bnks.lock().remove(&handle).is_some()
+    }
+
+    pub fn with_bnk<F, R>(&self, handle: u32, f: F) -> eyre::Result<R>
+    where
+        F: FnOnce(&mut BnkSession) -> eyre::Result<R>,
+    {
+        let mut bnks = self.bnks.lock();
+        let session = bnks
+            .get_mut(&handle)
+            .ok_or_else(|| eyre::eyre!("Bnk session not found: {}", handle))?;
+        f(session)
+    }
+
+    /// Open pck into a new session, returns its handle.
+    pub fn open_pck(&self, path: impl AsRef<Path>) -> eyre::Result<u32> {
+        let path = path.as_ref();
+        let mut pck = Pck::from_file(&path.to_string_lossy())
+            .context(format!("Failed to parse pck: {}", path.display()))?;
+        let header = pck.header().clone();
+        let handle = self.next_handle();
+        self.pcks.lock().insert(
+            handle,
+            PckSession {
+                path: path.to_path_buf(),
+                pck,
+                header,
+                overrides: HashMap::new(),
+            },
+        );
+        log::debug!("Pck session {} opened: {}", handle, path.display());
+        Ok(handle)
+    }
+
+    /// Returns whether the session existed.
+    pub fn close_pck(&self, handle: u32) -> bool {
+        self.pcks.lock().remove(&handle).is_some()
+    }
+
+    pub fn with_pck<F, R>(&self, handle: u32, f: F) -> eyre::Result<R>
+    where
+        F: FnOnce(&mut PckSession) -> eyre::Result<R>,
+    {
+        let mut pcks = self.pcks.lock();
+        let session = pcks
+            .get_mut(&handle)
+            .ok_or_else(|| eyre::eyre!("Pck session not found: {}", handle))?;
+        f(session)
+    }
+
+    fn next_handle(&self) -> u32 {
+        self.next_handle.fetch_add(1, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::settings::{self, Settings, SettingsIssue};
+
+/// Settings with the problems found in them.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsState {
+    pub settings: Settings,
+    pub issues: Vec<SettingsIssue>,
+    /// Path of the settings file.
+    pub path: String,
+}
+
+pub struct SettingsService {
+    dir: PathBuf,
+    state: Mutex<(Settings, Vec<SettingsIssue>)>,
+}
+
+impl SettingsService {
+    /// Load settings from the config dir, migrating older files.
+    pub fn load(dir: impl AsRef<Path>, legacy_dir: Option<&Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let loaded = settings::load(&dir, legacy_dir);
+        for issue in &loaded.issues {
+            log::warn!("Settings {}: {}", issue.field, issue.message);
+        }
+        if loaded.migrated
+            && let Err(e) = settings::save(&loaded.settings, &dir)
+        {
+            log::error!("{e:#}");
+        }
+        Self {
+            dir,
+            state: Mutex::new((loaded.settings, loaded.issues)),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.state.lock().0.clone()
+    }
+
+    pub fn state(&self) -> SettingsState {
+        let state = self.state.lock();
+        SettingsState {
+            settings: state.0.clone(),
+            issues: state.1.clone(),
+            path: self
+                .dir
+                .join(settings::SETTINGS_FILE)
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// Change settings and write them. Settings with issues are still saved,
+    /// the issues are returned for the user to fix.
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) -> eyre::Result<SettingsState> {
+        {
+            let mut state = self.state.lock();
+            let mut settings = state.0.clone();
+            f(&mut settings);
+            settings::save(&settings, &self.dir)?;
+            state.1 = settings::validate(&settings);
+            state.0 = settings;
+        }
+        Ok(self.state())
+    }
+}
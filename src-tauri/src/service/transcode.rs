@@ -1,42 +1,249 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use eyre::Context;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 
-use crate::subprocess::{FFmpegCli, VgmstreamCli, WwiseConsole, WwiseSource};
+use crate::{
+    settings::{Settings, ToolPaths},
+    subprocess::{
+        DEFAULT_CONVERSION, FFmpegCli, VgmstreamCli, WwiseConsole, WwiseError, WwiseProject,
+        WwiseSource,
+    },
+    wem::{
+        CODEC_IMA_ADPCM, CODEC_PCM, CODEC_PCM_EXTENSIBLE, CODEC_VORBIS, WemInfo, decode,
+        encode::{self, EncodeOptions, WemFormat},
+        vorbis::{CODEBOOKS_FILE, CodebookLibrary},
+    },
+};
+
+/// Backend used to decode wem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WemDecoder {
+    /// Native decoding if the codec is supported, vgmstream otherwise.
+    #[default]
+    Auto,
+    Native,
+    Vgmstream,
+}
+
+/// Backend used to encode wem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WemEncoder {
+    /// WwiseConsole if available, native PCM otherwise.
+    #[default]
+    Auto,
+    Wwise,
+    /// Native PCM, lossless.
+    Pcm,
+}
+
+/// Wwise conversion of wav to wem.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConversionPreset {
+    /// Same codec as the wem being replaced, Vorbis Quality High if unknown.
+    #[default]
+    MatchSource,
+    /// Lossless, large and cheap to decode, e.g. short UI sounds.
+    Pcm,
+    /// Needs WwiseConsole, encoded to native PCM without it.
+    Adpcm,
+    VorbisHigh,
+    VorbisMedium,
+    VorbisLow,
+    /// A conversion ShareSet of the temp project, by name.
+    ShareSet(String),
+}
+
+impl ConversionPreset {
+    /// Preset for the wem being replaced if this is [`Self::MatchSource`].
+    pub fn resolve(&self, source: Option<&WemInfo>) -> Self {
+        if *self != Self::MatchSource {
+            return self.clone();
+        }
+        let Some(info) = source else {
+            return Self::VorbisHigh;
+        };
+        match info.codec {
+            CODEC_PCM | CODEC_PCM_EXTENSIBLE => Self::Pcm,
+            CODEC_IMA_ADPCM => Self::Adpcm,
+            // quality is not stored in the wem and the bitrate of a quality
+            // varies with the content, keep the default
+            _ => Self::VorbisHigh,
+        }
+    }
+
+    /// ShareSet names to look for in the project, in order.
+    /// Factory ShareSet names differ between Wwise versions.
+    fn share_set_names(&self) -> Vec<&str> {
+        match self {
+            Self::MatchSource | Self::VorbisHigh => vec![DEFAULT_CONVERSION],
+            Self::Pcm => vec!["PCM As Input", "PCM"],
+            Self::Adpcm => vec!["ADPCM As Input", "ADPCM"],
+            Self::VorbisMedium => vec!["Vorbis Quality Medium"],
+            Self::VorbisLow => vec!["Vorbis Quality Low"],
+            Self::ShareSet(name) => vec![name],
+        }
+    }
+
+    /// Format of the native encoder giving the same codec.
+    fn native_format(&self) -> Option<WemFormat> {
+        match self {
+            Self::Pcm => Some(WemFormat::Pcm),
+            _ => None,
+        }
+    }
+}
+
+/// A conversion of a batch.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeJob {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    #[serde(default)]
+    pub options: TranscodeOptions,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeJobResult {
+    pub input: String,
+    pub output: String,
+    /// Why the job failed, `None` on success.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeOptions {
+    /// Conversion of wav to wem, the default preset if not set.
+    pub conversion: Option<ConversionPreset>,
+    /// Wem being replaced, its codec is used by [`ConversionPreset::MatchSource`].
+    pub source_wem: Option<PathBuf>,
+}
+
+/// Whether an external tool works, and why not.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolStatus {
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl ToolStatus {
+    fn check<E: std::fmt::Display>(
+        path: Option<&Path>,
+        version: impl FnOnce(&Path) -> Result<Option<String>, E>,
+    ) -> Self {
+        let Some(path) = path else {
+            return Self {
+                error: Some("Path not set.".to_string()),
+                ..Default::default()
+            };
+        };
+        let mut status = Self {
+            path: Some(path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        match version(path) {
+            Ok(version) => {
+                status.ok = true;
+                status.version = version;
+            }
+            Err(e) => status.error = Some(e.to_string()),
+        }
+        status
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolsStatus {
+    pub ffmpeg: ToolStatus,
+    pub wwise_console: ToolStatus,
+    pub vgmstream: ToolStatus,
+}
 
 pub struct TranscodeService {
     ffmpeg: Mutex<FFmpegCli>,
     wwise: Mutex<WwiseConsole>,
     vgmstream: Mutex<VgmstreamCli>,
+    wem_decoder: Mutex<WemDecoder>,
+    wem_encoder: Mutex<WemEncoder>,
+    /// Default conversion of wav to wem.
+    conversion: Mutex<ConversionPreset>,
+    /// Codebooks for native Vorbis decoding.
+    codebooks: Mutex<Option<Arc<CodebookLibrary>>>,
 }
 
 impl TranscodeService {
     pub fn new() -> Self {
-        // auto detect subprocess paths
+        Self::from_settings(&Settings::default())
+    }
+
+    /// Service with tool paths and backends from settings, paths not set
+    /// are detected.
+    pub fn from_settings(settings: &Settings) -> Self {
+        let service = Self {
+            ffmpeg: Mutex::new(FFmpegCli::default()),
+            wwise: Mutex::new(WwiseConsole::default()),
+            vgmstream: Mutex::new(VgmstreamCli::default()),
+            wem_decoder: Mutex::new(settings.transcode.decoder),
+            wem_encoder: Mutex::new(settings.transcode.encoder),
+            conversion: Mutex::new(settings.transcode.conversion.clone()),
+            codebooks: Mutex::new(None),
+        };
+        service.set_tool_paths(&settings.tools);
+        if let Err(e) = service.reset_codebooks(settings.tools.codebooks.as_deref()) {
+            log::warn!("{e:#}");
+        }
+        service
+    }
+
+    /// Use the tool paths of settings. Tools without a path are detected,
+    /// and left unset if not found.
+    pub fn set_tool_paths(&self, tools: &ToolPaths) {
         let mut ffmpeg = FFmpegCli::default();
-        match FFmpegCli::auto_detect() {
-            Ok(path) => ffmpeg.set_path(path),
-            Err(e) => log::error!("{e}\nYou may not able to transcode audio files."),
+        match &tools.ffmpeg {
+            Some(path) => ffmpeg.set_path(path),
+            None => match FFmpegCli::auto_detect() {
+                Ok(path) => ffmpeg.set_path(path),
+                Err(e) => log::error!("{e}\nYou may not able to transcode audio files."),
+            },
         }
+        *self.ffmpeg.lock() = ffmpeg;
+
         let mut wwise = WwiseConsole::default();
-        match WwiseConsole::auto_detect() {
-            Ok(path) => wwise.set_path(path),
-            Err(e) => log::error!("{e}\nYou may not able to transcode audio to Wem."),
-        }
-        let mut vgmstream = VgmstreamCli::default();
-        match VgmstreamCli::auto_detect() {
-            Ok(path) => vgmstream.set_path(&path),
-            Err(e) => log::error!(
-                "{e}\nYou may not able to transcode Wem to audio, and playback audio files."
-            ),
+        match &tools.wwise_console {
+            Some(path) => wwise.set_path(path),
+            None => match WwiseConsole::auto_detect() {
+                Ok(path) => wwise.set_path(path),
+                Err(e) => log::error!("{e}\nYou may not able to transcode audio to Wem."),
+            },
         }
+        *self.wwise.lock() = wwise;
 
-        Self {
-            ffmpeg: Mutex::new(ffmpeg),
-            wwise: Mutex::new(wwise),
-            vgmstream: Mutex::new(vgmstream),
+        let mut vgmstream = VgmstreamCli::default();
+        match &tools.vgmstream {
+            Some(path) => vgmstream.set_path(path),
+            None => match VgmstreamCli::auto_detect() {
+                Ok(path) => vgmstream.set_path(&path),
+                Err(e) => log::error!(
+                    "{e}\nYou may not able to transcode Wem to audio, and playback audio files."
+                ),
+            },
         }
+        *self.vgmstream.lock() = vgmstream;
     }
 
     pub fn set_ffmpeg_path(&self, path: impl AsRef<Path>) {
@@ -51,10 +258,187 @@ impl TranscodeService {
         self.vgmstream.lock().set_path(path);
     }
 
+    /// Run the configured tools and report their versions.
+    pub fn check(&self) -> ToolsStatus {
+        // tools may take a while to start, do not block conversions meanwhile
+        let ffmpeg = self.ffmpeg.lock().path().map(Path::to_path_buf);
+        let wwise = self.wwise.lock().path().map(Path::to_path_buf);
+        let vgmstream = self.vgmstream.lock().path().map(PathBuf::from);
+        ToolsStatus {
+            ffmpeg: ToolStatus::check(ffmpeg.as_deref(), |path| FFmpegCli::version(path)),
+            wwise_console: ToolStatus::check(wwise.as_deref(), |path| WwiseConsole::version(path)),
+            vgmstream: ToolStatus::check(vgmstream.as_deref(), |path| VgmstreamCli::version(path)),
+        }
+    }
+
+    /// Detect tool paths again and check them. Configured paths are kept
+    /// for tools not found.
+    pub fn auto_detect(&self) -> ToolsStatus {
+        let ffmpeg = FFmpegCli::auto_detect().map(|path| self.set_ffmpeg_path(path));
+        let wwise = WwiseConsole::auto_detect().map(|path| self.set_wwise_path(path));
+        let vgmstream = VgmstreamCli::auto_detect().map(|path| self.set_vgmstream_path(&path));
+
+        let mut status = self.check();
+        // explain why detection failed if the configured path does not work either
+        let detect_error = |status: &mut ToolStatus, error: Option<String>| {
+            if let (false, Some(error)) = (status.ok, error) {
+                status.error = Some(match status.error.take() {
+                    Some(check_error) if status.path.is_some() => {
+                        format!("{error} Configured path failed: {check_error}")
+                    }
+                    _ => error,
+                });
+            }
+        };
+        detect_error(&mut status.ffmpeg, ffmpeg.err().map(|e| e.to_string()));
+        detect_error(
+            &mut status.wwise_console,
+            wwise.err().map(|e| e.to_string()),
+        );
+        detect_error(
+            &mut status.vgmstream,
+            vgmstream.err().map(|e| e.to_string()),
+        );
+        status
+    }
+
+    pub fn set_wem_decoder(&self, decoder: WemDecoder) {
+        *self.wem_decoder.lock() = decoder;
+    }
+
+    pub fn set_wem_encoder(&self, encoder: WemEncoder) {
+        *self.wem_encoder.lock() = encoder;
+    }
+
+    pub fn set_conversion(&self, conversion: ConversionPreset) {
+        *self.conversion.lock() = conversion;
+    }
+
+    /// Name of the backend encoding wav to wem, changes when the encoded
+    /// output of the same conversion would.
+    pub fn encoder_backend(&self) -> String {
+        match *self.wem_encoder.lock() {
+            WemEncoder::Auto if self.wwise.lock().path().is_some() => "Wwise".to_string(),
+            WemEncoder::Auto => "Native".to_string(),
+            encoder => format!("{:?}", encoder),
+        }
+    }
+
+    pub fn conversion(&self) -> ConversionPreset {
+        self.conversion.lock().clone()
+    }
+
+    /// Names of the conversion ShareSets in the temp project template,
+    /// which is created if not exists.
+    pub fn conversion_share_sets(&self) -> eyre::Result<Vec<String>> {
+        let project = self.wwise.lock().template_project()?;
+        Ok(project.conversion_share_sets()?)
+    }
+
+    pub fn set_codebooks_path(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let codebooks = CodebookLibrary::from_file(path)?;
+        *self.codebooks.lock() = Some(Arc::new(codebooks));
+        Ok(())
+    }
+
+    /// Load codebooks from path, or the default file next to the executable
+    /// if `None`. Native Vorbis decoding is not available if none is loaded.
+    pub fn reset_codebooks(&self, path: Option<&str>) -> eyre::Result<()> {
+        *self.codebooks.lock() = None;
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_codebooks() {
+                Ok(path) => path,
+                Err(e) => {
+                    log::debug!("{e}\nNative Vorbis decoding is not available.");
+                    return Ok(());
+                }
+            },
+        };
+        self.set_codebooks_path(path)
+    }
+
+    /// Convert audio by file extensions.
     pub fn auto_transcode(
         &self,
         input: impl AsRef<Path>,
         output: impl AsRef<Path>,
+        options: &TranscodeOptions,
+    ) -> eyre::Result<()> {
+        self.transcode(input, output, &self.resolve_conversion(options))
+    }
+
+    /// Convert files in a batch, all wavs to wem with WwiseConsole in a
+    /// single invocation. Results are in the order of jobs.
+    pub fn batch_transcode(&self, jobs: &[TranscodeJob]) -> Vec<TranscodeJobResult> {
+        let mut results = Vec::with_capacity(jobs.len());
+        let mut wwise_jobs = vec![];
+        for (index, job) in jobs.iter().enumerate() {
+            let conversion = self.resolve_conversion(&job.options);
+            match self.prepare_batch_job(job, &conversion) {
+                Ok(Some(wwise_job)) => {
+                    wwise_jobs.push((index, wwise_job));
+                    results.push(Ok(()));
+                }
+                Ok(None) => results.push(Ok(())),
+                Err(e) => results.push(Err(e)),
+            }
+        }
+
+        if !wwise_jobs.is_empty() {
+            log::info!(
+                "converting {} wav files with WwiseConsole",
+                wwise_jobs.len()
+            );
+            let (indexes, wwise_jobs): (Vec<_>, Vec<_>) = wwise_jobs.into_iter().unzip();
+            match self.wwise_convert(&wwise_jobs) {
+                Ok(wwise_results) => {
+                    for (index, result) in indexes.into_iter().zip(wwise_results) {
+                        results[index] = result;
+                    }
+                }
+                Err(e) => {
+                    for index in indexes {
+                        results[index] = Err(eyre::eyre!("{e:#}"));
+                    }
+                }
+            }
+        }
+
+        jobs.iter()
+            .zip(results)
+            .map(|(job, result)| TranscodeJobResult {
+                input: job.input.to_string_lossy().to_string(),
+                output: job.output.to_string_lossy().to_string(),
+                error: result.err().map(|e| format!("{e:#}")),
+            })
+            .collect()
+    }
+
+    /// Conversion of options, resolved for the wem being replaced.
+    fn resolve_conversion(&self, options: &TranscodeOptions) -> ConversionPreset {
+        let conversion = options
+            .conversion
+            .clone()
+            .unwrap_or_else(|| self.conversion());
+        let source = match (&conversion, &options.source_wem) {
+            (ConversionPreset::MatchSource, Some(path)) => match WemInfo::from_file(path) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    log::warn!("{e:#}\nCannot match the codec of replaced wem.");
+                    None
+                }
+            },
+            _ => None,
+        };
+        conversion.resolve(source.as_ref())
+    }
+
+    fn transcode(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        conversion: &ConversionPreset,
     ) -> eyre::Result<()> {
         let input = input.as_ref();
         let output = output.as_ref();
@@ -85,21 +469,21 @@ impl TranscodeService {
                 .wem2wav(input, output)
                 .context("convering .wem to .wav")?,
             ("wav", "wem") => self
-                .wav2wem(input, output)
+                .wav2wem(input, output, conversion)
                 .context("convering .wav to .wem")?,
             (_, "wem") => {
                 let wav_path = output.with_extension("wav");
                 // audio -> .wav
-                self.auto_transcode(input, &wav_path)?;
+                self.transcode(input, &wav_path, conversion)?;
                 // .wav -> .wem
-                self.auto_transcode(&wav_path, output)?;
+                self.transcode(&wav_path, output, conversion)?;
             }
             ("wem", _) => {
                 let wav_path = output.with_extension("wav");
                 // .wem -> .wav
-                self.auto_transcode(input, &wav_path)?;
+                self.transcode(input, &wav_path, conversion)?;
                 // .wav -> audio
-                self.auto_transcode(&wav_path, output)?;
+                self.transcode(&wav_path, output, conversion)?;
             }
             (other_i, other_o) => {
                 // just ffmpeg magic
@@ -113,52 +497,302 @@ impl TranscodeService {
         Ok(())
     }
 
-    fn wav2wem(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> eyre::Result<()> {
+    fn wav2wem(
+        &self,
+        input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        conversion: &ConversionPreset,
+    ) -> eyre::Result<()> {
         let input = input.as_ref();
         let output = output.as_ref();
 
-        let wwise = self.wwise.lock();
-        let project = wwise.acquire_temp_project()?;
-        let mut wsource =
-            WwiseSource::new(input.parent().unwrap_or(Path::new("")).to_str().unwrap());
-        wsource.add_source(input.to_str().unwrap());
-        project.convert_external_source(
-            &wsource,
-            output.parent().unwrap_or(Path::new("")).to_str().unwrap(),
-        )?;
-
-        // check output file
-        let output_name = output
-            .with_extension("wem")
-            .file_name()
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        let expect_output_path = output
-            .parent()
-            .unwrap_or(Path::new(""))
-            .join("Windows")
-            .join(output_name);
-        if !expect_output_path.exists() {
-            return Err(eyre::eyre!(
-                "Output file not found in expected path: {}",
-                expect_output_path.display()
-            ));
-        }
-
-        // rename output file
-        std::fs::rename(expect_output_path, output)?;
+        if let Some(format) = self.native_format(conversion) {
+            let options = EncodeOptions {
+                format,
+                loop_points: None,
+            };
+            return encode::wav_to_wem(input, output, &options);
+        }
 
-        Ok(())
+        let job = WwiseJob {
+            wav: input.to_path_buf(),
+            output: output.to_path_buf(),
+            conversion: conversion.clone(),
+        };
+        self.wwise_convert(std::slice::from_ref(&job))?.remove(0)
+    }
+
+    /// Format to encode natively, `None` to use WwiseConsole.
+    fn native_format(&self, conversion: &ConversionPreset) -> Option<WemFormat> {
+        match *self.wem_encoder.lock() {
+            WemEncoder::Wwise => None,
+            WemEncoder::Pcm => Some(WemFormat::Pcm),
+            WemEncoder::Auto if self.wwise.lock().path().is_none() => {
+                Some(conversion.native_format().unwrap_or_else(|| {
+                    log::warn!("WwiseConsole not available, encoding to PCM wem.");
+                    WemFormat::Pcm
+                }))
+            }
+            WemEncoder::Auto => None,
+        }
+    }
+
+    /// Convert the input of a batch job until it is a wav for WwiseConsole,
+    /// or convert it completely if WwiseConsole is not needed.
+    fn prepare_batch_job(
+        &self,
+        job: &TranscodeJob,
+        conversion: &ConversionPreset,
+    ) -> eyre::Result<Option<WwiseJob>> {
+        let has_ext = |path: &Path, ext: &str| path.extension().is_some_and(|e| e == ext);
+        if !has_ext(&job.output, "wem") || has_ext(&job.input, "wem") {
+            self.transcode(&job.input, &job.output, conversion)?;
+            return Ok(None);
+        }
+
+        let wav = if has_ext(&job.input, "wav") {
+            job.input.clone()
+        } else {
+            let wav = job.output.with_extension("wav");
+            self.transcode(&job.input, &wav, conversion)?;
+            wav
+        };
+        if let Some(format) = self.native_format(conversion) {
+            let options = EncodeOptions {
+                format,
+                loop_points: None,
+            };
+            encode::wav_to_wem(&wav, &job.output, &options)?;
+            return Ok(None);
+        }
+
+        Ok(Some(WwiseJob {
+            wav,
+            output: job.output.clone(),
+            conversion: conversion.clone(),
+        }))
+    }
+
+    /// Convert wavs with a single WwiseConsole invocation.
+    ///
+    /// Fails if the conversion cannot start, otherwise returns the result
+    /// of each job in order.
+    fn wwise_convert(&self, jobs: &[WwiseJob]) -> eyre::Result<Vec<eyre::Result<()>>> {
+        // the console is only locked to copy the project, jobs convert in parallel
+        let project = self.wwise.lock().acquire_temp_project()?;
+        let available = match project.conversion_share_sets() {
+            Ok(available) => Some(available),
+            Err(e) => {
+                // let WwiseConsole report it if a name is wrong
+                log::debug!("Failed to read conversion ShareSets: {e}");
+                None
+            }
+        };
+
+        // stage wavs under unique names in the job directory, so outputs map
+        // back to their jobs even if inputs in different directories have the same name
+        let staging = project.dir();
+        let mut wsource = WwiseSource::new(staging.to_str().unwrap());
+        let mut results = Vec::with_capacity(jobs.len());
+        for (index, job) in jobs.iter().enumerate() {
+            let staged = staging.join(format!("{}.wav", staged_name(index)));
+            let result = find_share_set(available.as_deref(), &project, &job.conversion).and_then(
+                |share_set| {
+                    fs::copy(&job.wav, &staged)
+                        .context(format!("Failed to read wav: {}", job.wav.display()))?;
+                    wsource.add_source(staged.to_str().unwrap(), share_set);
+                    Ok(())
+                },
+            );
+            results.push(result);
+        }
+        if results.iter().all(Result::is_err) {
+            return Ok(results);
+        }
+
+        // the command fails if any source fails, so check outputs one by one
+        let command_error = project
+            .convert_external_source(&wsource, staging.to_str().unwrap())
+            .err();
+        for (index, (job, result)) in jobs.iter().zip(results.iter_mut()).enumerate() {
+            if result.is_err() {
+                continue;
+            }
+            let name = staged_name(index);
+            let converted = staging.join("Windows").join(format!("{}.wem", name));
+            *result = if converted.is_file() {
+                move_file(&converted, &job.output)
+            } else {
+                Err(match &command_error {
+                    Some(e) => eyre::eyre!("{}", source_error(e, &name, &job.wav)),
+                    None => {
+                        eyre::eyre!("Output of {} not found after conversion", job.wav.display())
+                    }
+                })
+            };
+        }
+
+        Ok(results)
     }
 
     fn wem2wav(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> eyre::Result<()> {
         let input = input.as_ref();
         let output = output.as_ref();
 
+        let decoder = *self.wem_decoder.lock();
+        let native = match decoder {
+            WemDecoder::Native => true,
+            WemDecoder::Vgmstream => false,
+            WemDecoder::Auto => WemInfo::from_file(input).is_ok_and(|info| {
+                decode::is_supported(info.codec)
+                    && (info.codec != CODEC_VORBIS || self.codebooks.lock().is_some())
+            }),
+        };
+        if native {
+            let codebooks = self.codebooks.lock().clone();
+            match decode::wem_to_wav(input, output, codebooks.as_deref()) {
+                Ok(()) => return Ok(()),
+                Err(e) if decoder == WemDecoder::Auto => {
+                    log::warn!("{e:#}\nFalling back to vgmstream.");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
         let vgmstream = self.vgmstream.lock();
         vgmstream.wem_to_wav(input, output)?;
 
         Ok(())
     }
 }
+
+/// Name of the ShareSet of conversion in the project, the first candidate
+/// if ShareSets of the project are unknown.
+fn find_share_set(
+    available: Option<&[String]>,
+    project: &WwiseProject,
+    conversion: &ConversionPreset,
+) -> eyre::Result<String> {
+    let names = conversion.share_set_names();
+    let Some(available) = available else {
+        return Ok(names[0].to_string());
+    };
+    match names
+        .iter()
+        .find(|name| available.iter().any(|a| a == *name))
+    {
+        Some(name) => Ok(name.to_string()),
+        None => eyre::bail!(
+            "Conversion ShareSet '{}' not found in {}. Available: {}",
+            names.join("' or '"),
+            project.project_path().display(),
+            available.join(", ")
+        ),
+    }
+}
+
+/// Wav of a batch job for WwiseConsole.
+struct WwiseJob {
+    wav: PathBuf,
+    output: PathBuf,
+    conversion: ConversionPreset,
+}
+
+/// Name of a staged source, unique in the batch and never a substring of
+/// another, to find its lines in WwiseConsole output.
+fn staged_name(index: usize) -> String {
+    format!("source_{:05}", index)
+}
+
+/// Lines of WwiseConsole output about a staged source, or the whole error.
+fn source_error(error: &WwiseError, staged_name: &str, wav: &Path) -> String {
+    if let WwiseError::CommandFailed { stdout, stderr, .. } = error {
+        let lines: Vec<&str> = stdout
+            .lines()
+            .chain(stderr.lines())
+            .filter(|line| line.contains(staged_name))
+            .collect();
+        if !lines.is_empty() {
+            return format!(
+                "WwiseConsole failed to convert {}:\n{}",
+                wav.display(),
+                lines.join("\n")
+            );
+        }
+    }
+    error.to_string()
+}
+
+/// Rename, or copy if the target is on another volume.
+fn move_file(from: &Path, to: &Path) -> eyre::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).context(format!("Failed to write: {}", to.display()))?;
+        let _ = fs::remove_file(from);
+    }
+    Ok(())
+}
+
+/// Codebooks file next to the executable, or in working directory.
+fn default_codebooks() -> eyre::Result<PathBuf> {
+    let exe = std::env::current_exe()?;
+    let mut try_paths = vec![];
+    if let Some(dir) = exe.parent() {
+        try_paths.push(dir.join(CODEBOOKS_FILE));
+    }
+    try_paths.push(std::env::current_dir()?.join(CODEBOOKS_FILE));
+
+    try_paths
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| eyre::eyre!("{} not found.", CODEBOOKS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_conversion() {
+        let info = |codec, avg_bytes_per_sec| WemInfo {
+            codec,
+            channels: 2,
+            avg_bytes_per_sec,
+            ..Default::default()
+        };
+        let preset = ConversionPreset::MatchSource;
+        assert_eq!(preset.resolve(None), ConversionPreset::VorbisHigh);
+        assert_eq!(
+            preset.resolve(Some(&info(CODEC_PCM_EXTENSIBLE, 192000))),
+            ConversionPreset::Pcm
+        );
+        assert_eq!(
+            preset.resolve(Some(&info(CODEC_IMA_ADPCM, 54000))),
+            ConversionPreset::Adpcm
+        );
+        assert_eq!(
+            preset.resolve(Some(&info(CODEC_VORBIS, 16000))),
+            ConversionPreset::VorbisHigh
+        );
+        let share_set = ConversionPreset::ShareSet("Music".to_string());
+        assert_eq!(share_set.resolve(Some(&info(CODEC_PCM, 192000))), share_set);
+    }
+
+    #[test]
+    fn test_source_error() {
+        let error = WwiseError::CommandFailed {
+            code: Some(2),
+            stdout: "Converting C:\\temp\\source_00001.wav\n\
+                     Error: C:\\temp\\source_00001.wav: unsupported format\n\
+                     Converting C:\\temp\\source_00010.wav"
+                .to_string(),
+            stderr: String::new(),
+        };
+        let message = source_error(&error, &staged_name(1), Path::new("hit.wav"));
+        assert_eq!(message.lines().count(), 3);
+        assert!(message.contains("unsupported format"));
+        assert!(!message.contains("source_00010"));
+        // no lines about the source
+        let message = source_error(&error, &staged_name(2), Path::new("hit.wav"));
+        assert!(message.starts_with("Command failed"));
+    }
+}
@@ -0,0 +1,346 @@
+//! Versioned settings file in the app config directory.
+//!
+//! Older files are migrated step by step to [`SETTINGS_VERSION`] on load,
+//! and written back in the current version.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    extract::ExtractOptions,
+    service::{ConversionPreset, WemDecoder, WemEncoder},
+};
+
+pub const SETTINGS_VERSION: u32 = 1;
+pub const SETTINGS_FILE: &str = "settings.json";
+/// Config written by the frontend before settings moved to the backend,
+/// next to the executable. Migrated as version 0.
+pub const LEGACY_CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub version: u32,
+    pub tools: ToolPaths,
+    /// Backends and default conversion of the transcode service.
+    pub transcode: TranscodeSettings,
+    /// Default options of extract commands.
+    pub export: ExtractOptions,
+    /// Wordlists loaded on startup, besides `wordlists/` next to the executable.
+    pub wordlists: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            tools: ToolPaths::default(),
+            transcode: TranscodeSettings::default(),
+            export: ExtractOptions::default(),
+            wordlists: vec![],
+        }
+    }
+}
+
+/// Paths of external tools, detected on startup if not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToolPaths {
+    pub ffmpeg: Option<String>,
+    pub wwise_console: Option<String>,
+    pub vgmstream: Option<String>,
+    /// Vorbis codebooks for native decoding.
+    pub codebooks: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranscodeSettings {
+    pub decoder: WemDecoder,
+    pub encoder: WemEncoder,
+    /// Conversion of wav to wem if not chosen per source.
+    pub conversion: ConversionPreset,
+}
+
+/// A problem found in settings, shown to the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsIssue {
+    /// Path of the field, e.g. `tools.ffmpeg`, empty for the whole file.
+    pub field: String,
+    pub message: String,
+}
+
+impl SettingsIssue {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadedSettings {
+    pub settings: Settings,
+    pub issues: Vec<SettingsIssue>,
+    /// Settings not read from the current file version, and should be saved.
+    pub migrated: bool,
+}
+
+/// Load settings from `dir`, or the legacy config in `legacy_dir` if there
+/// are none yet. Defaults are used if the file cannot be read, the broken
+/// file is kept as a backup.
+pub fn load(dir: &Path, legacy_dir: Option<&Path>) -> LoadedSettings {
+    let path = dir.join(SETTINGS_FILE);
+    let source = if path.is_file() {
+        Some(path.clone())
+    } else {
+        legacy_dir
+            .map(|dir| dir.join(LEGACY_CONFIG_FILE))
+            .filter(|path| path.is_file())
+    };
+    let Some(source) = source else {
+        return LoadedSettings {
+            settings: Settings::default(),
+            issues: vec![],
+            migrated: true,
+        };
+    };
+
+    let result = fs::read_to_string(&source)
+        .context(format!("Failed to read settings: {}", source.display()))
+        .and_then(|text| {
+            let value: Value = serde_json::from_str(&text)
+                .context(format!("Invalid settings file: {}", source.display()))?;
+            let from_version = file_version(&value)?;
+            Ok((migrate(value)?, from_version))
+        });
+    match result {
+        Ok((settings, from_version)) => {
+            if from_version < SETTINGS_VERSION {
+                log::info!(
+                    "Migrated settings from version {} of {}",
+                    from_version,
+                    source.display()
+                );
+            }
+            let issues = validate(&settings);
+            LoadedSettings {
+                settings,
+                issues,
+                migrated: source != path || from_version < SETTINGS_VERSION,
+            }
+        }
+        Err(e) => {
+            let mut message = format!("{e:#}. Default settings are used.");
+            // keep the file from being overwritten by defaults
+            if source == path {
+                let backup = backup_path(&path);
+                match fs::copy(&path, &backup) {
+                    Ok(_) => message.push_str(&format!(" Backup at {}", backup.display())),
+                    Err(e) => log::warn!("Failed to back up settings: {e}"),
+                }
+            }
+            log::error!("{message}");
+            LoadedSettings {
+                settings: Settings::default(),
+                issues: vec![SettingsIssue::new("", message)],
+                migrated: false,
+            }
+        }
+    }
+}
+
+/// Write settings to `dir` in the current version.
+pub fn save(settings: &Settings, dir: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(dir).context(format!("Failed to create config dir: {}", dir.display()))?;
+    let path = dir.join(SETTINGS_FILE);
+    let settings = Settings {
+        version: SETTINGS_VERSION,
+        ..settings.clone()
+    };
+    let content = serde_json::to_string_pretty(&settings)?;
+    // write then rename, so a crash does not leave a truncated file
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content).context(format!("Failed to write settings: {}", temp.display()))?;
+    fs::rename(&temp, &path).context(format!("Failed to write settings: {}", path.display()))?;
+    Ok(())
+}
+
+/// Upgrade settings of any older version to the current one.
+pub fn migrate(mut value: Value) -> eyre::Result<Settings> {
+    let version = file_version(&value)?;
+    if version > SETTINGS_VERSION {
+        eyre::bail!(
+            "Settings version {} is newer than supported version {}, please update the app",
+            version,
+            SETTINGS_VERSION
+        );
+    }
+    if version < 1 {
+        value = migrate_v0(&value);
+    }
+
+    let mut settings: Settings =
+        serde_json::from_value(value).context("Invalid settings content")?;
+    settings.version = SETTINGS_VERSION;
+    Ok(settings)
+}
+
+/// Check paths in settings exist.
+pub fn validate(settings: &Settings) -> Vec<SettingsIssue> {
+    let mut issues = vec![];
+    let tools = [
+        ("tools.ffmpeg", &settings.tools.ffmpeg),
+        ("tools.wwiseConsole", &settings.tools.wwise_console),
+        ("tools.vgmstream", &settings.tools.vgmstream),
+    ];
+    for (field, path) in tools {
+        // bare command names are looked up in PATH
+        if let Some(path) = path
+            && is_file_path(path)
+            && !Path::new(path).is_file()
+        {
+            issues.push(SettingsIssue::new(
+                field,
+                format!("File not found: {}", path),
+            ));
+        }
+    }
+    if let Some(path) = &settings.tools.codebooks
+        && !Path::new(path).is_file()
+    {
+        issues.push(SettingsIssue::new(
+            "tools.codebooks",
+            format!("File not found: {}", path),
+        ));
+    }
+    if let ConversionPreset::ShareSet(name) = &settings.transcode.conversion
+        && name.trim().is_empty()
+    {
+        issues.push(SettingsIssue::new(
+            "transcode.conversion",
+            "Conversion ShareSet name is empty",
+        ));
+    }
+    if let Some(template) = &settings.export.template
+        && template.trim().is_empty()
+    {
+        issues.push(SettingsIssue::new(
+            "export.template",
+            "File name template is empty",
+        ));
+    }
+    for (i, path) in settings.wordlists.iter().enumerate() {
+        if !Path::new(path).is_file() {
+            issues.push(SettingsIssue::new(
+                format!("wordlists.{}", i),
+                format!("File not found: {}", path),
+            ));
+        }
+    }
+    issues
+}
+
+/// Version of a settings file, 0 for the legacy config without one.
+fn file_version(value: &Value) -> eyre::Result<u32> {
+    match value.get("version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| eyre::eyre!("Invalid settings version: {}", version)),
+    }
+}
+
+/// Legacy frontend config: `{"shell": {"ffmpegPath": ...}}`.
+fn migrate_v0(value: &Value) -> Value {
+    let shell = value.get("shell");
+    let path = |key: &str| {
+        shell
+            .and_then(|shell| shell.get(key))
+            .and_then(Value::as_str)
+            .filter(|path| !path.is_empty())
+    };
+    json!({
+        "version": 1,
+        "tools": {
+            "ffmpeg": path("ffmpegPath"),
+            "wwiseConsole": path("wwiseConsolePath"),
+            "vgmstream": path("vgmstreamPath"),
+        },
+    })
+}
+
+fn is_file_path(path: &str) -> bool {
+    Path::new(path).components().count() > 1
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_legacy() {
+        let value = json!({
+            "shell": {
+                "ffmpegPath": "C:/tools/ffmpeg.exe",
+                "wwiseConsolePath": "",
+            }
+        });
+        let settings = migrate(value).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(
+            settings.tools.ffmpeg.as_deref(),
+            Some("C:/tools/ffmpeg.exe")
+        );
+        assert_eq!(settings.tools.wwise_console, None);
+        assert_eq!(settings.transcode.encoder, WemEncoder::Auto);
+    }
+
+    #[test]
+    fn test_migrate_version() {
+        let value = json!({
+            "version": 1,
+            "transcode": { "decoder": "native", "conversion": { "shareSet": "Music" } },
+            "export": { "template": "{event}_{id}" },
+        });
+        let settings = migrate(value).unwrap();
+        assert_eq!(settings.transcode.decoder, WemDecoder::Native);
+        assert_eq!(
+            settings.transcode.conversion,
+            ConversionPreset::ShareSet("Music".to_string())
+        );
+        assert_eq!(settings.export.template.as_deref(), Some("{event}_{id}"));
+
+        assert!(migrate(json!({ "version": SETTINGS_VERSION + 1 })).is_err());
+        assert!(migrate(json!({ "version": "1" })).is_err());
+        assert!(migrate(json!({ "version": 1, "wordlists": "a.txt" })).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut settings = Settings::default();
+        settings.tools.ffmpeg = Some("ffmpeg".to_string());
+        settings.tools.vgmstream = Some("missing/vgmstream-cli.exe".to_string());
+        settings.wordlists = vec!["missing/names.txt".to_string()];
+        let fields: Vec<_> = validate(&settings)
+            .into_iter()
+            .map(|issue| issue.field)
+            .collect();
+        assert_eq!(fields, ["tools.vgmstream", "wordlists.0"]);
+    }
+}
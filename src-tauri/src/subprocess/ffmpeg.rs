@@ -46,6 +46,10 @@ impl FFmpegCli {
         self.path = Some(path.as_ref().to_path_buf());
     }
 
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     /// Simple transcode, only provide input and output file path.
     pub fn simple_transcode(
         &self,
@@ -111,6 +115,38 @@ impl FFmpegCli {
         result.status.success()
     }
 
+    /// Run `-version`, returns the version if found in output.
+    pub fn version(path: impl AsRef<Path>) -> Result<Option<String>> {
+        let mut command = Command::new(path.as_ref());
+        command.args(["-version"]);
+
+        #[cfg(target_os = "windows")]
+        {
+            // 隐藏控制台窗口 (CREATE_NO_WINDOW = 0x08000000)
+            command.creation_flags(0x08000000);
+        }
+
+        let result = command
+            .output()
+            .map_err(FFmpegError::CommandExecutionFailed)?;
+        if !result.status.success() {
+            return Err(FFmpegError::command_failed(
+                result.status.code(),
+                &result.stdout,
+                &result.stderr,
+            ));
+        }
+
+        // ffmpeg version 7.1-full_build-www.gyan.dev Copyright ...
+        let stdout = String::from_utf8_lossy(&result.stdout);
+        Ok(stdout
+            .lines()
+            .next()
+            .and_then(|line| line.strip_prefix("ffmpeg version "))
+            .and_then(|rest| rest.split_whitespace().next())
+            .map(|version| version.to_string()))
+    }
+
     pub fn auto_detect() -> Result<String> {
         let mut try_paths = vec![];
         // env
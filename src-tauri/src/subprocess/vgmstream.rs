@@ -46,6 +46,10 @@ impl VgmstreamCli {
         self.path = Some(path.to_string());
     }
 
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
     pub fn wem_to_wav(&self, input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<()> {
         let input = input.as_ref();
         let output = output.as_ref();
@@ -97,6 +101,38 @@ impl VgmstreamCli {
         result.status.code() == Some(1)
     }
 
+    /// Run `-h`, returns the version if found in usage text.
+    pub fn version(path: impl AsRef<Path>) -> Result<Option<String>> {
+        let mut command = Command::new(path.as_ref());
+        command.args(["-h"]);
+
+        #[cfg(target_os = "windows")]
+        {
+            // 隐藏控制台窗口 (CREATE_NO_WINDOW = 0x08000000)
+            command.creation_flags(0x08000000);
+        }
+
+        let result = command
+            .output()
+            .map_err(VgmstreamError::CommandExecutionFailed)?;
+        // usage exits with 1
+        if result.status.code() != Some(1) {
+            return Err(VgmstreamError::command_failed(
+                result.status.code(),
+                &result.stdout,
+                &result.stderr,
+            ));
+        }
+
+        // vgmstream CLI decoder r1980 (Jan 1 2025)
+        let stdout = String::from_utf8_lossy(&result.stdout);
+        Ok(stdout
+            .lines()
+            .find_map(|line| line.split_once("vgmstream CLI decoder "))
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .map(|version| version.to_string()))
+    }
+
     pub fn auto_detect() -> Result<String> {
         let mut try_paths = vec![];
         let vgmstream_root = "vgmstream-win64";
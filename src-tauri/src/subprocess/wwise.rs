@@ -1,14 +1,40 @@
 use std::{
     env, fs,
     io::{self, Write},
+    ops::Deref,
     path::{Path, PathBuf},
-    process::Command,
+    process::{self, Command},
+    sync::{
+        Once,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
 };
 
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 
+use parking_lot::Mutex;
+
+use crate::names;
+
 const WWISE_BASE_DEFAULT_PATH: &str = r"C:\Program Files (x86)\Audiokinetic";
+/// Conversion ShareSet used by default, a factory ShareSet of new projects.
+pub const DEFAULT_CONVERSION: &str = "Vorbis Quality High";
+/// Directory of conversion ShareSet work units in a project.
+const CONVERSION_SETTINGS_DIR: &str = "Conversion Settings";
+/// Name of temp projects, and of the optional template next to the executable.
+const TEMP_PROJECT_NAME: &str = "SoundModderTemp";
+/// Directory of template and job projects in the system temp dir.
+const TEMP_ROOT_NAME: &str = "mhws-sound-modder";
+/// Job directories older than this are left by crashed processes.
+const STALE_JOB_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Outputs and caches of a project, not copied from the template.
+const TEMPLATE_SKIP: &[&str] = &["Windows", "list.wsource", ".cache"];
+
+/// Serializes template creation in the process.
+static TEMPLATE_LOCK: Mutex<()> = Mutex::new(());
+static JOB_COUNTER: AtomicU32 = AtomicU32::new(0);
 
 type Result<T> = std::result::Result<T, WwiseError>;
 
@@ -53,24 +79,75 @@ impl WwiseConsole {
         self.path = Some(path.as_ref().to_path_buf());
     }
 
-    pub fn acquire_temp_project(&self) -> Result<WwiseProject> {
-        const TEMP_PROJECT_NAME: &str = "SoundModderTemp";
-
-        let exe_path = env::current_exe()?;
-        let tool_dir = exe_path.parent().unwrap();
-        let proj_path = tool_dir
-            .join(TEMP_PROJECT_NAME)
-            .join(format!("{}.wproj", TEMP_PROJECT_NAME));
-        if proj_path.exists() {
-            let project = WwiseProject::new(self, proj_path);
-            return Ok(project);
-        }
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 
-        // not exist, try to create the project
-        let project = self.create_new_project(tool_dir, TEMP_PROJECT_NAME)?;
+    /// Copy the template project into a new job directory in the system
+    /// temp dir. Each job has its own project, source list and outputs,
+    /// all removed when the returned project is dropped.
+    pub fn acquire_temp_project(&self) -> Result<TempProject> {
+        let template = self.template_project()?;
+        let jobs_dir = temp_root().join("jobs");
+        static CLEANUP: Once = Once::new();
+        CLEANUP.call_once(|| remove_stale_jobs(&jobs_dir));
+
+        let dir = jobs_dir.join(unique_name());
+        let project_dir = dir.join(TEMP_PROJECT_NAME);
+        // guard first, so a partial copy is removed as well
+        let project = TempProject {
+            project: WwiseProject::new(self, project_file(&project_dir)),
+            dir,
+        };
+        copy_dir(
+            template.project_path().parent().unwrap(),
+            &project_dir,
+            TEMPLATE_SKIP,
+        )?;
         Ok(project)
     }
 
+    /// Pristine project copied for each job.
+    ///
+    /// `SoundModderTemp` next to the executable is used if exists, so custom
+    /// ShareSets can be added to it in Wwise. Otherwise a new project is
+    /// created once per console in the system temp dir.
+    pub fn template_project(&self) -> Result<WwiseProject> {
+        let Some(console_path) = &self.path else {
+            return Err(WwiseError::WwiseConsoleNotFound);
+        };
+        if let Some(tool_dir) = env::current_exe()?.parent() {
+            let path = project_file(&tool_dir.join(TEMP_PROJECT_NAME));
+            if path.is_file() {
+                return Ok(WwiseProject::new(self, path));
+            }
+        }
+
+        // newer Wwise upgrades the project, keep one per console,
+        // named by a hash stable across builds to be found by later runs
+        let console_hash = names::hash(&console_path.to_string_lossy());
+        let template_dir = temp_root().join(format!("template-{:08x}", console_hash));
+        let path = project_file(&template_dir.join(TEMP_PROJECT_NAME));
+
+        let _guard = TEMPLATE_LOCK.lock();
+        if path.is_file() {
+            return Ok(WwiseProject::new(self, path));
+        }
+        // create aside and move in place, other processes never see a partial project
+        let building = temp_root().join(format!("building-{}", unique_name()));
+        let result = self
+            .create_new_project(&building, TEMP_PROJECT_NAME)
+            .and_then(|_| Ok(fs::rename(&building, &template_dir)?));
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&building);
+            // created by another process meanwhile
+            if !path.is_file() {
+                return Err(e);
+            }
+        }
+        Ok(WwiseProject::new(self, path))
+    }
+
     pub fn create_new_project(
         &self,
         root_path: impl AsRef<Path>,
@@ -129,6 +206,12 @@ impl WwiseConsole {
 
     /// Test if the console can be executed.
     pub fn test_console(path: impl AsRef<Path>) -> bool {
+        Self::version(path).is_ok()
+    }
+
+    /// Check the console can run, returns the version of the Wwise
+    /// installation it belongs to, e.g. `2023.1.3.8471`.
+    pub fn version(path: impl AsRef<Path>) -> Result<Option<String>> {
         let path = path.as_ref();
         let mut command = Command::new(path);
         command.args(["create-new-project", "--help"]);
@@ -139,12 +222,26 @@ impl WwiseConsole {
             command.creation_flags(0x08000000);
         }
 
-        let result = command.output();
-        let Ok(result) = result else {
-            return false;
-        };
+        let result = command
+            .output()
+            .map_err(WwiseError::CommandExecutionFailed)?;
+        if !result.status.success() {
+            return Err(WwiseError::command_failed(
+                result.status.code(),
+                &result.stdout,
+                &result.stderr,
+            ));
+        }
 
-        result.status.success()
+        // installed to Audiokinetic/Wwise2023.1.3.8471/Authoring/...
+        let version = path.ancestors().find_map(|dir| {
+            let name = dir.file_name()?.to_str()?;
+            let version = name.strip_prefix("Wwise")?;
+            version
+                .starts_with(|c: char| c.is_ascii_digit())
+                .then(|| version.to_string())
+        });
+        Ok(version)
     }
 
     pub fn auto_detect() -> Result<String> {
@@ -199,30 +296,50 @@ impl WwiseConsole {
     }
 }
 
-pub struct WwiseProject<'a> {
-    console: &'a WwiseConsole,
+pub struct WwiseProject {
+    /// Console the project is used with, not locked during conversions.
+    console_path: Option<PathBuf>,
     project_path: PathBuf,
 }
 
-impl<'a> WwiseProject<'a> {
-    fn new(console: &'a WwiseConsole, project_path: PathBuf) -> Self {
+impl WwiseProject {
+    fn new(console: &WwiseConsole, project_path: PathBuf) -> Self {
         Self {
-            console,
+            console_path: console.path.clone(),
             project_path,
         }
     }
 
-    #[allow(dead_code)]
     pub fn project_path(&self) -> &Path {
         &self.project_path
     }
 
+    /// Names of the conversion ShareSets defined in the project.
+    pub fn conversion_share_sets(&self) -> Result<Vec<String>> {
+        let dir = self
+            .project_path
+            .parent()
+            .unwrap()
+            .join(CONVERSION_SETTINGS_DIR);
+        let mut names = vec![];
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "wwu") {
+                let content = fs::read_to_string(&path)?;
+                names.extend(parse_conversion_names(&content));
+            }
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
     pub fn convert_external_source(
         &self,
         wsource: &WwiseSource,
         output_dir: impl AsRef<str>,
     ) -> Result<()> {
-        let Some(console_path) = &self.console.path else {
+        let Some(console_path) = &self.console_path else {
             return Err(WwiseError::WwiseConsoleNotFound);
         };
 
@@ -268,9 +385,92 @@ impl<'a> WwiseProject<'a> {
     }
 }
 
+/// Project of a single conversion job. Its directory is removed with the
+/// source list and the `Windows/` outputs when dropped.
+pub struct TempProject {
+    project: WwiseProject,
+    dir: PathBuf,
+}
+
+impl TempProject {
+    /// Job directory, for staged sources and conversion outputs.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Deref for TempProject {
+    type Target = WwiseProject;
+
+    fn deref(&self) -> &Self::Target {
+        &self.project
+    }
+}
+
+impl Drop for TempProject {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir)
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            log::warn!("Failed to remove temp project {}: {e}", self.dir.display());
+        }
+    }
+}
+
+fn temp_root() -> PathBuf {
+    env::temp_dir().join(TEMP_ROOT_NAME)
+}
+
+/// Name unique to the process and call.
+fn unique_name() -> String {
+    format!(
+        "{}-{}",
+        process::id(),
+        JOB_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn project_file(project_dir: &Path) -> PathBuf {
+    project_dir.join(format!("{}.wproj", TEMP_PROJECT_NAME))
+}
+
+/// Copy a directory recursively, except top level entries named in `skip`.
+fn copy_dir(from: &Path, to: &Path, skip: &[&str]) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if skip.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target, &[])?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_stale_jobs(jobs_dir: &Path) {
+    let Ok(entries) = fs::read_dir(jobs_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|time| time.elapsed().is_ok_and(|age| age > STALE_JOB_AGE));
+        if is_stale && let Err(e) = fs::remove_dir_all(entry.path()) {
+            log::warn!("Failed to remove stale temp project: {e}");
+        }
+    }
+}
+
 pub struct WwiseSource {
     root: String,
-    sources: Vec<String>,
+    /// Source paths with their conversion ShareSet names.
+    sources: Vec<(String, String)>,
 }
 
 impl WwiseSource {
@@ -282,17 +482,19 @@ impl WwiseSource {
         }
     }
 
-    pub fn add_source(&mut self, source: impl AsRef<str>) {
+    /// Add a source converted with the conversion ShareSet named `conversion`.
+    pub fn add_source(&mut self, source: impl AsRef<str>, conversion: impl AsRef<str>) {
         let source = source.as_ref().replace("/", "\\").replace(r"\\?\", "");
-        self.sources.push(source);
+        self.sources.push((source, conversion.as_ref().to_string()));
     }
 
     fn to_xml(&self) -> String {
         let mut sources = String::new();
-        for source in self.sources.iter() {
+        for (source, conversion) in self.sources.iter() {
             sources += &format!(
-                "    <Source Path=\"{}\" Conversion=\"Vorbis Quality High\"/>\n",
-                source
+                "    <Source Path=\"{}\" Conversion=\"{}\"/>\n",
+                escape_xml(source),
+                escape_xml(conversion)
             );
         }
         format!(
@@ -300,12 +502,104 @@ impl WwiseSource {
 <ExternalSourcesList SchemaVersion="1" Root="{root}">
 {sources}
 </ExternalSourcesList>"#,
-            root = self.root,
+            root = escape_xml(&self.root),
             sources = sources
         )
     }
 }
 
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Names of `<Conversion Name="...">` elements in a work unit.
+fn parse_conversion_names(wwu: &str) -> Vec<String> {
+    const TAG: &str = "<Conversion ";
+    let mut names = vec![];
+    let mut rest = wwu;
+    while let Some(start) = rest.find(TAG) {
+        rest = &rest[start + TAG.len()..];
+        let element = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if let Some(value) = element.split_once("Name=\"").map(|(_, value)| value)
+            && let Some((name, _)) = value.split_once('"')
+        {
+            names.push(unescape_xml(name));
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_xml() {
+        let mut source = WwiseSource::new("C:/audio");
+        source.add_source("C:/audio/a&b.wav", "PCM");
+        let xml = source.to_xml();
+        assert!(xml.contains(r#"Root="C:\audio""#));
+        assert!(xml.contains(r#"<Source Path="C:\audio\a&amp;b.wav" Conversion="PCM"/>"#));
+    }
+
+    #[test]
+    fn test_temp_project_cleanup() {
+        let template = temp_root().join(format!("test-{}", unique_name()));
+        fs::create_dir_all(template.join("Conversion Settings")).unwrap();
+        fs::write(template.join("Conversion Settings/Default.wwu"), "").unwrap();
+        // outputs left in a legacy template next to the executable
+        fs::create_dir_all(template.join("Windows")).unwrap();
+        fs::write(template.join("list.wsource"), "").unwrap();
+
+        let dir = temp_root().join(format!("test-{}", unique_name()));
+        let project_dir = dir.join(TEMP_PROJECT_NAME);
+        let project = TempProject {
+            project: WwiseProject::new(&WwiseConsole::default(), project_file(&project_dir)),
+            dir: dir.clone(),
+        };
+        copy_dir(&template, &project_dir, TEMPLATE_SKIP).unwrap();
+        assert!(
+            project_dir
+                .join("Conversion Settings/Default.wwu")
+                .is_file()
+        );
+        assert!(!project_dir.join("Windows").exists());
+        assert!(!project_dir.join("list.wsource").exists());
+        fs::create_dir_all(project.dir().join("Windows")).unwrap();
+        drop(project);
+        assert!(!dir.exists());
+
+        fs::remove_dir_all(&template).unwrap();
+    }
+
+    #[test]
+    fn test_parse_conversion_names() {
+        let wwu = r#"<WorkUnit Name="Factory Conversion Settings">
+<ChildrenList>
+<Conversion Name="Vorbis Quality High" ID="{1}">
+<Conversion ID="{2}" Name="Music &amp; Ambience">
+<ConversionPluginInfo Name="Vorbis"/>
+</ChildrenList>"#;
+        assert_eq!(
+            parse_conversion_names(wwu),
+            ["Vorbis Quality High", "Music & Ambience"]
+        );
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
@@ -0,0 +1,104 @@
+//! LSB-first bit streams, the packing used by Vorbis packets.
+
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Read up to 32 bits.
+    pub fn read(&mut self, bits: u32) -> eyre::Result<u32> {
+        if self.pos + bits as usize > self.data.len() * 8 {
+            eyre::bail!("Unexpected end of packet");
+        }
+        let mut value = 0u32;
+        for i in 0..bits {
+            let byte = self.data[self.pos / 8];
+            let bit = (byte >> (self.pos % 8)) & 1;
+            value |= (bit as u32) << i;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    pub fn read_flag(&mut self) -> eyre::Result<bool> {
+        Ok(self.read(1)? != 0)
+    }
+
+    pub fn bits_read(&self) -> usize {
+        self.pos
+    }
+}
+
+#[derive(Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    /// Length in bits.
+    len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the low `bits` bits of value, up to 32.
+    pub fn write(&mut self, value: u32, bits: u32) {
+        for i in 0..bits {
+            if self.len.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.data.last_mut().unwrap() |= bit << (self.len % 8);
+            self.len += 1;
+        }
+    }
+
+    pub fn write_flag(&mut self, flag: bool) {
+        self.write(flag as u32, 1);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte as u32, 8);
+        }
+    }
+
+    /// Bytes written, the last one padded with zero bits.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Number of bits needed to store the value, as `ilog` in the Vorbis spec.
+pub fn ilog(value: u32) -> u32 {
+    u32::BITS - value.leading_zeros()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_roundtrip() {
+        let mut writer = BitWriter::new();
+        writer.write(0b101, 3);
+        writer.write(0x564342, 24);
+        writer.write_flag(true);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), 4);
+
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(reader.read(3).unwrap(), 0b101);
+        assert_eq!(reader.read(24).unwrap(), 0x564342);
+        assert!(reader.read_flag().unwrap());
+        assert_eq!(reader.bits_read(), 28);
+        assert!(reader.read(8).is_err());
+        assert_eq!((ilog(0), ilog(1), ilog(7), ilog(8)), (0, 1, 3, 4));
+    }
+}
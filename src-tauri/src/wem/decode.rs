@@ -0,0 +1,105 @@
+//! In-process wem decoding, for codecs which do not need vgmstream.
+
+use std::{fs, io::Cursor, path::Path};
+
+use eyre::Context;
+
+use super::{
+    CODEC_IMA_ADPCM, CODEC_PCM, CODEC_PCM_EXTENSIBLE, CODEC_VORBIS, Pcm, RiffChunks, ima,
+    vorbis::{self, CodebookLibrary},
+};
+
+/// Whether the codec can be decoded natively.
+pub fn is_supported(codec: u16) -> bool {
+    matches!(
+        codec,
+        CODEC_PCM | CODEC_PCM_EXTENSIBLE | CODEC_IMA_ADPCM | CODEC_VORBIS
+    )
+}
+
+/// Decode wem to 16-bit PCM. Codebooks are required for Vorbis.
+pub fn decode(wem: &[u8], codebooks: Option<&CodebookLibrary>) -> eyre::Result<Pcm> {
+    let riff = RiffChunks::read(&mut Cursor::new(wem))?;
+    let e = &riff.endian;
+    let fmt = riff.fmt()?;
+    let codec = e.u16(fmt);
+    let channels = e.u16(&fmt[0x02..]);
+    let sample_rate = e.u32(&fmt[0x04..]);
+    let data = || {
+        let start = riff.data_offset as usize;
+        wem.get(start..start + riff.data_size as usize)
+            .ok_or_else(|| eyre::eyre!("data chunk out of range"))
+    };
+    match codec {
+        CODEC_PCM | CODEC_PCM_EXTENSIBLE => {
+            let bits_per_sample = e.u16(&fmt[0x0E..]);
+            if bits_per_sample != 16 {
+                eyre::bail!("Unsupported PCM bits per sample: {}", bits_per_sample);
+            }
+            Ok(Pcm {
+                channels,
+                sample_rate,
+                samples: data()?.chunks_exact(2).map(|b| e.u16(b) as i16).collect(),
+            })
+        }
+        CODEC_IMA_ADPCM => {
+            if e.big || channels == 0 {
+                eyre::bail!("Unsupported IMA ADPCM layout");
+            }
+            let channels_len = channels as usize;
+            let mut samples = vec![];
+            let mut block = Vec::with_capacity(ima::BLOCK_SAMPLES * channels_len);
+            for frame in data()?.chunks_exact(ima::BLOCK_SIZE * channels_len) {
+                block.clear();
+                // each channel has its own block and header within a frame
+                for channel in frame.chunks_exact(ima::BLOCK_SIZE) {
+                    ima::decode_block(channel, &mut block);
+                }
+                // channel blocks to interleaved
+                for i in 0..ima::BLOCK_SAMPLES {
+                    samples.extend((0..channels_len).map(|c| block[c * ima::BLOCK_SAMPLES + i]));
+                }
+            }
+            Ok(Pcm {
+                channels,
+                sample_rate,
+                samples,
+            })
+        }
+        CODEC_VORBIS => {
+            let Some(codebooks) = codebooks else {
+                eyre::bail!(
+                    "Vorbis codebooks not found, place {} next to the executable",
+                    vorbis::CODEBOOKS_FILE
+                );
+            };
+            vorbis::decode(wem, codebooks)
+        }
+        _ => eyre::bail!("Native decoding of codec {:#06x} is not supported", codec),
+    }
+}
+
+pub fn wem_to_wav(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    codebooks: Option<&CodebookLibrary>,
+) -> eyre::Result<()> {
+    let input = input.as_ref();
+    let output = output.as_ref();
+    let wem = fs::read(input).context(format!("Failed to read wem: {}", input.display()))?;
+    let pcm = decode(&wem, codebooks).context(format!("Failed to decode: {}", input.display()))?;
+
+    let spec = hound::WavSpec {
+        channels: pcm.channels,
+        sample_rate: pcm.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(output, spec)
+        .context(format!("Failed to create wav: {}", output.display()))?;
+    for sample in pcm.samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
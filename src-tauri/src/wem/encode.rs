@@ -0,0 +1,211 @@
+//! In-process wem encoding to PCM, without WwiseConsole.
+
+use std::{fs, path::Path};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use super::{CODEC_PCM_EXTENSIBLE, LoopPoints, Pcm, WemInfo};
+
+/// Size of fmt written by Wwise: `WAVEFORMATEX` and the channel config.
+const FMT_SIZE: u32 = 0x18;
+/// Size of smpl with a single loop.
+const SMPL_SIZE: u32 = 0x3C;
+/// `AkChannelConfig` type of standard speaker setups.
+const CHANNEL_CONFIG_STANDARD: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WemFormat {
+    /// Lossless 16-bit PCM.
+    #[default]
+    Pcm,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodeOptions {
+    pub format: WemFormat,
+    /// Loop to write in smpl, taken from the smpl of input wav if not set.
+    pub loop_points: Option<LoopPoints>,
+}
+
+/// Encode samples to a wem file.
+pub fn encode(pcm: &Pcm, options: &EncodeOptions) -> eyre::Result<Vec<u8>> {
+    let channels = pcm.channels as u32;
+    if channels == 0 || channels > 255 {
+        eyre::bail!("Invalid channel count: {}", channels);
+    }
+    let frames = pcm.samples.len() / channels as usize;
+    if let Some(loop_points) = &options.loop_points
+        && (loop_points.start > loop_points.end || loop_points.end as usize >= frames)
+    {
+        eyre::bail!(
+            "Loop {}-{} out of range of {} samples",
+            loop_points.start,
+            loop_points.end,
+            frames
+        );
+    }
+
+    let (codec, block_align, bits_per_sample, data) = match options.format {
+        WemFormat::Pcm => {
+            let data: Vec<u8> = pcm.samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+            (CODEC_PCM_EXTENSIBLE, 2 * channels, 16u16, data)
+        }
+    };
+    let avg_bytes_per_sec = pcm.sample_rate * block_align;
+
+    let mut chunks: Vec<u8> = vec![];
+    chunks.extend(b"fmt ");
+    chunks.extend(FMT_SIZE.to_le_bytes());
+    chunks.extend(codec.to_le_bytes());
+    chunks.extend((channels as u16).to_le_bytes());
+    chunks.extend(pcm.sample_rate.to_le_bytes());
+    chunks.extend(avg_bytes_per_sec.to_le_bytes());
+    chunks.extend((block_align as u16).to_le_bytes());
+    chunks.extend(bits_per_sample.to_le_bytes());
+    chunks.extend(6u16.to_le_bytes()); // extra size
+    chunks.extend(bits_per_sample.to_le_bytes()); // valid bits
+    chunks.extend(channel_config(channels).to_le_bytes());
+
+    if let Some(loop_points) = &options.loop_points {
+        chunks.extend(b"smpl");
+        chunks.extend(SMPL_SIZE.to_le_bytes());
+        chunks.extend([0; 8]); // manufacturer, product
+        chunks.extend((1_000_000_000 / pcm.sample_rate.max(1)).to_le_bytes());
+        chunks.extend(60u32.to_le_bytes()); // MIDI unity note
+        chunks.extend([0; 12]); // pitch fraction, SMPTE format and offset
+        chunks.extend(1u32.to_le_bytes()); // loop count
+        chunks.extend([0; 4]); // sampler data
+        chunks.extend([0; 8]); // cue id, loop type
+        chunks.extend(loop_points.start.to_le_bytes());
+        chunks.extend(loop_points.end.to_le_bytes());
+        chunks.extend([0; 8]); // fraction, play count (infinite)
+    }
+
+    chunks.extend(b"data");
+    chunks.extend((data.len() as u32).to_le_bytes());
+    chunks.extend(&data);
+    if data.len() % 2 == 1 {
+        chunks.push(0);
+    }
+
+    let mut wem = Vec::with_capacity(chunks.len() + 12);
+    wem.extend(b"RIFF");
+    wem.extend((chunks.len() as u32 + 4).to_le_bytes());
+    wem.extend(b"WAVE");
+    wem.extend(chunks);
+    Ok(wem)
+}
+
+/// Read wav as 16-bit samples, with its loop if any.
+pub fn read_wav(path: impl AsRef<Path>) -> eyre::Result<(Pcm, Option<LoopPoints>)> {
+    let path = path.as_ref();
+    let mut reader =
+        hound::WavReader::open(path).context(format!("Failed to open wav: {}", path.display()))?;
+    let spec = reader.spec();
+    let samples: Vec<i16> = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader.samples::<i16>().collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Int, bits @ 1..=32) => {
+            let shift = bits as i32 - 16;
+            reader
+                .samples::<i32>()
+                .map(|s| {
+                    s.map(|s| match shift {
+                        ..0 => (s << -shift) as i16,
+                        _ => (s >> shift) as i16,
+                    })
+                })
+                .collect::<Result<_, _>>()?
+        }
+        (hound::SampleFormat::Float, 32) => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<_, _>>()?,
+        (format, bits) => eyre::bail!("Unsupported wav format: {:?} {} bits", format, bits),
+    };
+    let pcm = Pcm {
+        channels: spec.channels,
+        sample_rate: spec.sample_rate,
+        samples,
+    };
+    // the header parser reads smpl of wav files as well
+    let loop_points = WemInfo::from_file(path)
+        .ok()
+        .and_then(|info| info.loop_points);
+
+    Ok((pcm, loop_points))
+}
+
+pub fn wav_to_wem(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &EncodeOptions,
+) -> eyre::Result<()> {
+    let input = input.as_ref();
+    let output = output.as_ref();
+    let (pcm, wav_loop) = read_wav(input)?;
+    let options = EncodeOptions {
+        loop_points: options.loop_points.clone().or(wav_loop),
+        ..options.clone()
+    };
+    let wem = encode(&pcm, &options).context(format!("Failed to encode: {}", input.display()))?;
+    fs::write(output, wem).context(format!("Failed to write wem: {}", output.display()))?;
+    Ok(())
+}
+
+/// `AkChannelConfig` of the default speaker setup for the channel count,
+/// anonymous if there is none.
+fn channel_config(channels: u32) -> u32 {
+    let mask = match channels {
+        1 => 0x4,   // FC
+        2 => 0x3,   // FL FR
+        3 => 0x7,   // FL FR FC
+        4 => 0x603, // FL FR SL SR
+        5 => 0x607, // FL FR FC SL SR
+        6 => 0x60F, // FL FR FC LFE SL SR
+        8 => 0x63F, // FL FR FC LFE BL BR SL SR
+        _ => return channels,
+    };
+    channels | (CHANNEL_CONFIG_STANDARD << 8) | (mask << 12)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wem::decode;
+
+    fn sine(frames: usize) -> Pcm {
+        let samples = (0..frames)
+            .flat_map(|i| {
+                let s = ((i as f32 / 20.0).sin() * 8000.0) as i16;
+                [s, -s]
+            })
+            .collect();
+        Pcm {
+            channels: 2,
+            sample_rate: 48000,
+            samples,
+        }
+    }
+
+    #[test]
+    fn test_encode_pcm() {
+        let pcm = sine(1000);
+        let options = EncodeOptions {
+            format: WemFormat::Pcm,
+            loop_points: Some(LoopPoints {
+                start: 100,
+                end: 999,
+            }),
+        };
+        let wem = encode(&pcm, &options).unwrap();
+        let info = WemInfo::from_bytes(&wem).unwrap();
+        assert_eq!(info.sample_count, 1000);
+        assert_eq!(info.channel_layout.unwrap().speakers, ["FL", "FR"]);
+        let loop_points = info.loop_points.unwrap();
+        assert_eq!((loop_points.start, loop_points.end), (100, 999));
+        assert_eq!(decode::decode(&wem, None).unwrap().samples, pcm.samples);
+    }
+}
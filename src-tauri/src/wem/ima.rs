@@ -0,0 +1,90 @@
+//! Wwise IMA ADPCM.
+//!
+//! Same nibbles as MS IMA, but each channel has its own block of 0x24 bytes
+//! per frame, in channel order. The header sample is the first sample of the
+//! block, the nibbles are the 63 samples after it, and the last nibble is
+//! not used. Matches the layout decoded by vgmstream.
+
+/// Bytes of a channel block: 4 bytes header and 64 nibbles.
+pub const BLOCK_SIZE: usize = 0x24;
+/// Samples per channel block, the header sample and 63 nibbles.
+pub const BLOCK_SAMPLES: usize = 64;
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+const INDEX_TABLE: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+struct State {
+    predictor: i32,
+    step_index: i32,
+}
+
+impl State {
+    fn expand(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.step_index as usize];
+        let mut delta = step >> 3;
+        if nibble & 1 != 0 {
+            delta += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            delta += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            delta += step;
+        }
+        if nibble & 8 != 0 {
+            delta = -delta;
+        }
+        self.predictor = (self.predictor + delta).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.step_index = (self.step_index + INDEX_TABLE[(nibble & 7) as usize]).clamp(0, 88);
+        self.predictor as i16
+    }
+}
+
+/// Decode one channel block of [`BLOCK_SIZE`] bytes to [`BLOCK_SAMPLES`].
+pub fn decode_block(block: &[u8], out: &mut Vec<i16>) {
+    let header = i16::from_le_bytes([block[0], block[1]]);
+    let mut state = State {
+        predictor: header as i32,
+        step_index: (block[2] as i32).clamp(0, 88),
+    };
+    out.push(header);
+    let nibbles = block[4..BLOCK_SIZE]
+        .iter()
+        .flat_map(|byte| [byte & 0x0F, byte >> 4])
+        .take(BLOCK_SAMPLES - 1);
+    for nibble in nibbles {
+        out.push(state.expand(nibble));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_layout() {
+        let mut block = vec![0; BLOCK_SIZE];
+        block[..2].copy_from_slice(&1000i16.to_le_bytes());
+        block[2] = 10; // step 19
+        // first nibble after the header sample is the low one
+        block[4] = 0x04;
+
+        let mut decoded = vec![];
+        decode_block(&block, &mut decoded);
+        assert_eq!(decoded.len(), BLOCK_SAMPLES);
+        assert_eq!(decoded[0], 1000);
+        assert_eq!(decoded[1], 1000 + 19 + 2);
+
+        // the last nibble is ignored
+        block[BLOCK_SIZE - 1] = 0x70;
+        let mut last_ignored = vec![];
+        decode_block(&block, &mut last_ignored);
+        assert_eq!(last_ignored, decoded);
+    }
+}
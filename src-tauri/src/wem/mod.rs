@@ -0,0 +1,416 @@
+//! Wwise RIFF wem header parsing and native decoding.
+//!
+//! Header parsing only reads chunks, audio data is skipped.
+
+mod bits;
+pub mod decode;
+pub mod encode;
+mod ima;
+pub mod vorbis;
+
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+pub const CODEC_PCM: u16 = 0x0001;
+pub const CODEC_IMA_ADPCM: u16 = 0x0002;
+pub const CODEC_PCM_EXTENSIBLE: u16 = 0xFFFE;
+pub const CODEC_VORBIS: u16 = 0xFFFF;
+pub const CODEC_OPUS_NX: u16 = 0x3039;
+pub const CODEC_OPUS: u16 = 0x3040;
+pub const CODEC_OPUS_WEM: u16 = 0x3041;
+
+/// Speaker names by channel mask bit, same order as `WAVEFORMATEXTENSIBLE`.
+const SPEAKERS: &[&str] = &[
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC", "SL", "SR", "TC", "TFL", "TFC", "TFR",
+    "TBL", "TBC", "TBR",
+];
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WemInfo {
+    pub codec: u16,
+    pub codec_name: String,
+    pub channels: u16,
+    pub channel_layout: Option<ChannelLayout>,
+    pub sample_rate: u32,
+    pub avg_bytes_per_sec: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// Samples per channel.
+    pub sample_count: u64,
+    pub duration_ms: f64,
+    pub loop_points: Option<LoopPoints>,
+    pub markers: Vec<Marker>,
+    /// Size of the whole file in bytes.
+    pub file_size: u64,
+    pub data_size: u64,
+}
+
+/// Wwise `AkChannelConfig` of the fmt chunk.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelLayout {
+    /// 0 anonymous, 1 standard, 2 ambisonic.
+    pub config_type: u8,
+    pub mask: u32,
+    /// Speaker names of standard configs.
+    pub speakers: Vec<String>,
+}
+
+/// Loop range in samples, end inclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoopPoints {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marker {
+    pub id: u32,
+    /// Position in samples.
+    pub position: u32,
+    pub label: Option<String>,
+}
+
+/// Interleaved 16-bit samples.
+pub struct Pcm {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+/// Chunk reader honoring the file byte order.
+pub(crate) struct Endian {
+    pub big: bool,
+}
+
+impl Endian {
+    pub fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        if self.big {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        }
+    }
+
+    pub fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        if self.big {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    }
+}
+
+/// Chunks of a RIFF/RIFX file, with the audio data located but not read.
+pub(crate) struct RiffChunks {
+    pub endian: Endian,
+    pub chunks: Vec<([u8; 4], Vec<u8>)>,
+    pub data_offset: u64,
+    pub data_size: u64,
+    pub file_size: u64,
+}
+
+impl RiffChunks {
+    pub fn read<R: Read + Seek>(reader: &mut R) -> eyre::Result<Self> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        let endian = match &header[0..4] {
+            b"RIFF" => Endian { big: false },
+            b"RIFX" => Endian { big: true },
+            _ => eyre::bail!("Not a RIFF wem file"),
+        };
+        if &header[8..12] != b"WAVE" {
+            eyre::bail!("Not a RIFF WAVE file");
+        }
+
+        let mut chunks = vec![];
+        let mut data = None;
+        let mut chunk_header = [0; 8];
+        while reader.read_exact(&mut chunk_header).is_ok() {
+            let id: [u8; 4] = chunk_header[0..4].try_into().unwrap();
+            let size = endian.u32(&chunk_header[4..8]) as u64;
+            if &id == b"data" {
+                data = Some((reader.stream_position()?, size));
+                reader.seek(SeekFrom::Current(size as i64))?;
+            } else {
+                chunks.push((id, read_chunk(reader, size)?));
+            }
+            // chunks are word aligned
+            if size % 2 == 1 {
+                reader.seek(SeekFrom::Current(1))?;
+            }
+        }
+        let Some((data_offset, data_size)) = data else {
+            eyre::bail!("data chunk not found");
+        };
+
+        Ok(Self {
+            endian,
+            chunks,
+            data_offset,
+            data_size,
+            file_size,
+        })
+    }
+
+    pub fn get(&self, id: &[u8; 4]) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|(i, _)| i == id)
+            .map(|(_, c)| c.as_slice())
+    }
+
+    /// The fmt chunk, at least the size of `WAVEFORMAT`.
+    pub fn fmt(&self) -> eyre::Result<&[u8]> {
+        self.get(b"fmt ")
+            .filter(|f| f.len() >= 0x10)
+            .ok_or_else(|| eyre::eyre!("fmt chunk not found"))
+    }
+}
+
+impl WemInfo {
+    pub fn from_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).context(format!("Failed to open wem: {}", path.display()))?;
+        Self::from_reader(&mut BufReader::new(file))
+            .context(format!("Failed to parse wem: {}", path.display()))
+    }
+
+    pub fn from_bytes(data: &[u8]) -> eyre::Result<Self> {
+        Self::from_reader(&mut std::io::Cursor::new(data))
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> eyre::Result<Self> {
+        let riff = RiffChunks::read(reader)?;
+        let e = &riff.endian;
+        let fmt = riff.fmt()?;
+        let data_size = riff.data_size;
+        let mut info = WemInfo {
+            codec: e.u16(&fmt[0x00..]),
+            channels: e.u16(&fmt[0x02..]),
+            sample_rate: e.u32(&fmt[0x04..]),
+            avg_bytes_per_sec: e.u32(&fmt[0x08..]),
+            block_align: e.u16(&fmt[0x0C..]),
+            bits_per_sample: e.u16(&fmt[0x0E..]),
+            file_size: riff.file_size,
+            data_size,
+            ..Default::default()
+        };
+        info.codec_name = codec_name(info.codec).to_string();
+
+        // extensible fmt, channel config at 0x14
+        if fmt.len() >= 0x18 {
+            let config = e.u32(&fmt[0x14..]);
+            if config & 0xFF == info.channels as u32 {
+                let mask = config >> 12;
+                info.channel_layout = Some(ChannelLayout {
+                    config_type: ((config >> 8) & 0x0F) as u8,
+                    mask,
+                    speakers: SPEAKERS
+                        .iter()
+                        .enumerate()
+                        .filter(|(bit, _)| mask & (1 << bit) != 0)
+                        .map(|(_, name)| name.to_string())
+                        .collect(),
+                });
+            }
+        }
+
+        info.sample_count = match info.codec {
+            CODEC_PCM | CODEC_PCM_EXTENSIBLE if info.block_align > 0 => {
+                data_size / info.block_align as u64
+            }
+            CODEC_IMA_ADPCM if info.block_align > 0 => {
+                data_size / info.block_align as u64 * ima::BLOCK_SAMPLES as u64
+            }
+            // sample count in extended fmt, or the vorb chunk of older versions
+            CODEC_VORBIS | CODEC_OPUS_NX | CODEC_OPUS | CODEC_OPUS_WEM => {
+                match (riff.get(b"vorb"), fmt.len() >= 0x1C) {
+                    (Some(vorb), _) if vorb.len() >= 4 => e.u32(vorb) as u64,
+                    (_, true) => e.u32(&fmt[0x18..]) as u64,
+                    _ => eyre::bail!("Sample count not found in {} header", info.codec_name),
+                }
+            }
+            _ => {
+                // estimate from bitrate
+                if info.avg_bytes_per_sec == 0 {
+                    eyre::bail!("Unsupported wem codec: {:#06x}", info.codec);
+                }
+                data_size * info.sample_rate as u64 / info.avg_bytes_per_sec as u64
+            }
+        };
+        if info.sample_rate > 0 {
+            info.duration_ms = info.sample_count as f64 * 1000.0 / info.sample_rate as f64;
+        }
+
+        if let Some(smpl) = riff.get(b"smpl")
+            && smpl.len() >= 0x24 + 0x18
+            && e.u32(&smpl[0x1C..]) > 0
+        {
+            info.loop_points = Some(LoopPoints {
+                start: e.u32(&smpl[0x24 + 0x08..]),
+                end: e.u32(&smpl[0x24 + 0x0C..]),
+            });
+        }
+        if let Some(cue) = riff.get(b"cue ")
+            && cue.len() >= 4
+        {
+            let count = e.u32(cue) as usize;
+            for entry in cue[4..].chunks_exact(0x18).take(count) {
+                info.markers.push(Marker {
+                    id: e.u32(&entry[0x00..]),
+                    position: e.u32(&entry[0x04..]),
+                    label: None,
+                });
+            }
+        }
+        if let Some(list) = riff.get(b"LIST")
+            && list.starts_with(b"adtl")
+        {
+            for (id, label) in read_labels(&list[4..], e) {
+                if let Some(marker) = info.markers.iter_mut().find(|m| m.id == id) {
+                    marker.label = Some(label);
+                }
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+pub fn codec_name(codec: u16) -> &'static str {
+    match codec {
+        CODEC_PCM | CODEC_PCM_EXTENSIBLE => "PCM",
+        CODEC_IMA_ADPCM => "IMA ADPCM",
+        CODEC_VORBIS => "Vorbis",
+        CODEC_OPUS_NX | CODEC_OPUS | CODEC_OPUS_WEM => "Opus",
+        _ => "Unknown",
+    }
+}
+
+/// `labl` sub-chunks of an `adtl` list: cue id and text.
+fn read_labels(mut data: &[u8], e: &Endian) -> Vec<(u32, String)> {
+    let mut labels = vec![];
+    while data.len() >= 8 {
+        let size = e.u32(&data[4..8]) as usize;
+        let Some(body) = data.get(8..8 + size) else {
+            break;
+        };
+        if &data[0..4] == b"labl" && body.len() >= 4 {
+            let text = &body[4..];
+            let text = text.split(|b| *b == 0).next().unwrap_or_default();
+            labels.push((e.u32(body), String::from_utf8_lossy(text).to_string()));
+        }
+        data = data.get(8 + size + size % 2..).unwrap_or_default();
+    }
+    labels
+}
+
+fn read_chunk<R: Read>(reader: &mut R, size: u64) -> eyre::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(size).read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        eyre::bail!("Unexpected end of file in chunk");
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PCM wem with `samples` frames of 16-bit stereo, followed by extra chunks.
+    fn pcm_wem(samples: u32, chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let data_size = samples * 4;
+        let mut body = vec![];
+        body.extend(b"WAVE");
+        body.extend(b"fmt ");
+        body.extend(0x18u32.to_le_bytes());
+        body.extend(CODEC_PCM_EXTENSIBLE.to_le_bytes());
+        body.extend(2u16.to_le_bytes());
+        body.extend(48000u32.to_le_bytes());
+        body.extend((48000u32 * 4).to_le_bytes());
+        body.extend(4u16.to_le_bytes());
+        body.extend(16u16.to_le_bytes());
+        body.extend([0; 4]);
+        // standard stereo: FL | FR
+        body.extend((2u32 | (1 << 8) | (0b11 << 12)).to_le_bytes());
+        body.extend(b"data");
+        body.extend(data_size.to_le_bytes());
+        body.extend(vec![0; data_size as usize]);
+        for (id, chunk) in chunks {
+            body.extend(*id);
+            body.extend((chunk.len() as u32).to_le_bytes());
+            body.extend(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+
+        let mut wem = vec![];
+        wem.extend(b"RIFF");
+        wem.extend((body.len() as u32).to_le_bytes());
+        wem.extend(body);
+        wem
+    }
+
+    fn words(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_pcm() {
+        let wem = pcm_wem(24000, &[]);
+        let info = WemInfo::from_bytes(&wem).unwrap();
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.sample_count, 24000);
+        assert_eq!(info.file_size, wem.len() as u64);
+        assert_eq!(info.duration_ms, 500.0);
+        assert_eq!(info.codec_name, "PCM");
+        assert_eq!(info.channel_layout.unwrap().speakers, ["FL", "FR"]);
+        assert!(info.loop_points.is_none());
+    }
+
+    #[test]
+    fn test_decode_pcm() {
+        let wem = pcm_wem(100, &[]);
+        let pcm = decode::decode(&wem, None).unwrap();
+        assert_eq!((pcm.channels, pcm.sample_rate), (2, 48000));
+        assert_eq!(pcm.samples.len(), 200);
+    }
+
+    #[test]
+    fn test_parse_loop_and_markers() {
+        let smpl = words(&[0, 0, 20833, 60, 0, 0, 0, 1, 0, 0, 0, 1000, 23999, 0, 0]);
+        let cue = words(&[2, 1, 0, 0, 0, 0, 0, 2, 12000, 0, 0, 0, 12000]);
+        let mut list = b"adtl".to_vec();
+        list.extend(b"labl");
+        list.extend(7u32.to_le_bytes());
+        list.extend(2u32.to_le_bytes());
+        list.extend(b"Mid\0");
+        list.push(0);
+        let wem = pcm_wem(24000, &[(b"smpl", smpl), (b"cue ", cue), (b"LIST", list)]);
+
+        let info = WemInfo::from_bytes(&wem).unwrap();
+        let loop_points = info.loop_points.unwrap();
+        assert_eq!((loop_points.start, loop_points.end), (1000, 23999));
+        assert_eq!(info.markers.len(), 2);
+        assert_eq!(info.markers[0].label, None);
+        assert_eq!(info.markers[1].position, 12000);
+        assert_eq!(info.markers[1].label.as_deref(), Some("Mid"));
+    }
+}
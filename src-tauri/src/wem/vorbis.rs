@@ -0,0 +1,615 @@
+//! Wwise Vorbis decoding.
+//!
+//! Wwise strips the Vorbis headers, replaces codebooks by ids into a shared
+//! codebook library and packs the first byte of audio packets. The standard
+//! headers and packets are rebuilt the same way as ww2ogg, then decoded by
+//! lewton without an Ogg container.
+
+use std::{fs, path::Path};
+
+use eyre::Context;
+use lewton::{
+    audio::{PreviousWindowRight, read_audio_packet},
+    header::{IdentHeader, SetupHeader, read_header_ident, read_header_setup},
+};
+
+use super::{
+    Pcm, RiffChunks,
+    bits::{BitReader, BitWriter, ilog},
+};
+
+/// Codebook library used by Wwise 2012 and later, shipped with ww2ogg.
+pub const CODEBOOKS_FILE: &str = "packed_codebooks_aoTuV_603.bin";
+
+/// Size of the Vorbis setup appended to fmt since Wwise 2012.
+const VORB_SIZE: usize = 0x2A;
+/// Offset of the Vorbis setup in fmt.
+const VORB_OFFSET: usize = 0x18;
+/// Codebook id matching the start of an inline `BCV` codebook.
+const INLINE_CODEBOOK_ID: u32 = 0x342;
+
+/// Packed codebooks, addressed by the 10 bit ids in Wwise setup packets.
+pub struct CodebookLibrary {
+    data: Vec<u8>,
+    /// Start of each codebook, followed by the end of the last one.
+    offsets: Vec<usize>,
+}
+
+impl CodebookLibrary {
+    pub fn from_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let data =
+            fs::read(path).context(format!("Failed to read codebooks: {}", path.display()))?;
+        Self::from_bytes(data).context(format!("Invalid codebooks file: {}", path.display()))
+    }
+
+    /// The offset table is at the end of file, pointed by the last 4 bytes.
+    pub fn from_bytes(mut data: Vec<u8>) -> eyre::Result<Self> {
+        if data.len() < 4 {
+            eyre::bail!("File too small");
+        }
+        let table_offset = u32::from_le_bytes(data[data.len() - 4..].try_into().unwrap()) as usize;
+        if table_offset > data.len() - 4 {
+            eyre::bail!("Offset table out of range");
+        }
+        let offsets: Vec<usize> = data[table_offset..]
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .collect();
+        if offsets.windows(2).any(|w| w[0] > w[1]) || offsets.last() > Some(&table_offset) {
+            eyre::bail!("Offset table is not ordered");
+        }
+        data.truncate(table_offset);
+
+        Ok(Self { data, offsets })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&[u8]> {
+        let id = id as usize;
+        let start = *self.offsets.get(id)?;
+        let end = *self.offsets.get(id + 1)?;
+        self.data.get(start..end)
+    }
+
+    /// Write the codebook as in a standard Vorbis setup header.
+    fn rebuild(&self, id: u32, w: &mut BitWriter) -> eyre::Result<()> {
+        let Some(codebook) = self.get(id) else {
+            eyre::bail!("Invalid codebook id: {}", id);
+        };
+        let r = &mut BitReader::new(codebook);
+
+        let dimensions = r.read(4)?;
+        let entries = r.read(14)?;
+        w.write(0x564342, 24); // "BCV"
+        w.write(dimensions, 16);
+        w.write(entries, 24);
+
+        let ordered = r.read_flag()?;
+        w.write_flag(ordered);
+        if ordered {
+            let initial_length = r.read(5)?;
+            w.write(initial_length, 5);
+            let mut current = 0;
+            while current < entries {
+                let bits = ilog(entries - current);
+                let count = r.read(bits)?;
+                w.write(count, bits);
+                current += count;
+            }
+            if current > entries {
+                eyre::bail!("Codebook {} has too many entries", id);
+            }
+        } else {
+            let length_bits = r.read(3)?;
+            let sparse = r.read_flag()?;
+            if length_bits == 0 || length_bits > 5 {
+                eyre::bail!("Codebook {} has invalid codeword length size", id);
+            }
+            w.write_flag(sparse);
+            for _ in 0..entries {
+                let present = !sparse || {
+                    let present = r.read_flag()?;
+                    w.write_flag(present);
+                    present
+                };
+                if present {
+                    w.write(r.read(length_bits)?, 5);
+                }
+            }
+        }
+
+        let lookup_type = r.read(1)?;
+        w.write(lookup_type, 4);
+        if lookup_type == 1 {
+            let min = r.read(32)?;
+            let max = r.read(32)?;
+            let value_length = r.read(4)?;
+            let sequence = r.read_flag()?;
+            w.write(min, 32);
+            w.write(max, 32);
+            w.write(value_length, 4);
+            w.write_flag(sequence);
+            for _ in 0..quantvals(entries, dimensions) {
+                w.write(r.read(value_length + 1)?, value_length + 1);
+            }
+        }
+
+        // the last byte is always padded, even if fully used
+        if r.bits_read() / 8 + 1 != codebook.len() {
+            eyre::bail!("Codebook {} size mismatch", id);
+        }
+        Ok(())
+    }
+}
+
+/// Setup fields appended to fmt.
+struct VorbSetup {
+    channels: u8,
+    sample_rate: u32,
+    avg_bytes_per_sec: u32,
+    sample_count: u32,
+    /// Whether the first byte of audio packets is packed.
+    mod_packets: bool,
+    setup_offset: usize,
+    audio_offset: usize,
+    blocksize_0: u8,
+    blocksize_1: u8,
+}
+
+impl VorbSetup {
+    fn parse(riff: &RiffChunks) -> eyre::Result<Self> {
+        let e = &riff.endian;
+        let fmt = riff.fmt()?;
+        if riff.get(b"vorb").is_some() || fmt.len() < VORB_OFFSET + VORB_SIZE {
+            eyre::bail!("Vorbis wem before Wwise 2012 is not supported");
+        }
+        let vorb = &fmt[VORB_OFFSET..];
+        // seen on packets without modified header
+        let mod_signal = e.u32(&vorb[0x04..]);
+
+        Ok(Self {
+            channels: e.u16(&fmt[0x02..]) as u8,
+            sample_rate: e.u32(&fmt[0x04..]),
+            avg_bytes_per_sec: e.u32(&fmt[0x08..]),
+            sample_count: e.u32(&vorb[0x00..]),
+            mod_packets: !matches!(mod_signal, 0x4A | 0x4B | 0x69 | 0x70),
+            setup_offset: e.u32(&vorb[0x10..]) as usize,
+            audio_offset: e.u32(&vorb[0x14..]) as usize,
+            blocksize_0: vorb[0x28],
+            blocksize_1: vorb[0x29],
+        })
+    }
+
+    fn ident_packet(&self) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.write(1, 8);
+        w.write_bytes(b"vorbis");
+        w.write(0, 32); // version
+        w.write(self.channels as u32, 8);
+        w.write(self.sample_rate, 32);
+        w.write(0, 32); // bitrate max
+        w.write(self.avg_bytes_per_sec * 8, 32); // bitrate nominal
+        w.write(0, 32); // bitrate min
+        w.write(self.blocksize_0 as u32, 4);
+        w.write(self.blocksize_1 as u32, 4);
+        w.write_flag(true); // framing
+        w.into_bytes()
+    }
+}
+
+/// Modes of the setup header, needed to rebuild audio packets.
+struct Modes {
+    blockflags: Vec<bool>,
+    bits: u32,
+}
+
+/// Decode a Wwise Vorbis wem to 16-bit PCM.
+pub fn decode(wem: &[u8], codebooks: &CodebookLibrary) -> eyre::Result<Pcm> {
+    let riff = RiffChunks::read(&mut std::io::Cursor::new(wem))?;
+    let setup = VorbSetup::parse(&riff)?;
+    let data_start = riff.data_offset as usize;
+    let data = wem
+        .get(data_start..data_start + riff.data_size as usize)
+        .ok_or_else(|| eyre::eyre!("data chunk out of range"))?;
+
+    let ident = read_header_ident(&setup.ident_packet())
+        .map_err(|e| eyre::eyre!("Invalid Vorbis identification header: {:?}", e))?;
+    let setup_packet = packet_at(data, setup.setup_offset, &riff)?
+        .ok_or_else(|| eyre::eyre!("Vorbis setup packet not found"))?;
+    let (setup_packet, modes) = rebuild_setup(setup_packet, setup.channels as u32, codebooks)
+        .context("Failed to rebuild Vorbis setup header")?;
+    let setup_header = read_header_setup(
+        &setup_packet,
+        ident.audio_channels,
+        (ident.blocksize_0, ident.blocksize_1),
+    )
+    .map_err(|e| eyre::eyre!("Invalid Vorbis setup header: {:?}", e))?;
+
+    let mut packets = vec![];
+    let mut offset = setup.audio_offset;
+    while let Some(packet) = packet_at(data, offset, &riff)? {
+        offset += 2 + packet.len();
+        packets.push(packet);
+    }
+
+    let mut samples = decode_packets(&ident, &setup_header, &packets, &modes, setup.mod_packets)?;
+    samples.truncate(setup.sample_count as usize * setup.channels as usize);
+
+    Ok(Pcm {
+        channels: setup.channels as u16,
+        sample_rate: setup.sample_rate,
+        samples,
+    })
+}
+
+fn decode_packets(
+    ident: &IdentHeader,
+    setup: &SetupHeader,
+    packets: &[&[u8]],
+    modes: &Modes,
+    mod_packets: bool,
+) -> eyre::Result<Vec<i16>> {
+    let mut samples = vec![];
+    let mut pwr = PreviousWindowRight::new();
+    let mut prev_blockflag = false;
+    for (i, packet) in packets.iter().enumerate() {
+        if packet.is_empty() {
+            continue;
+        }
+        let rebuilt;
+        let packet = if mod_packets {
+            let next = packets.get(i + 1).copied();
+            rebuilt = rebuild_audio_packet(packet, next, modes, &mut prev_blockflag)?;
+            &rebuilt
+        } else {
+            *packet
+        };
+        // channel order of Wwise is the same as wav, no remapping needed
+        let decoded = read_audio_packet(ident, setup, packet, &mut pwr)
+            .map_err(|e| eyre::eyre!("Failed to decode Vorbis packet {}: {:?}", i, e))?;
+        let frames = decoded.first().map_or(0, |c| c.len());
+        for frame in 0..frames {
+            samples.extend(decoded.iter().map(|channel| channel[frame]));
+        }
+    }
+    Ok(samples)
+}
+
+/// Packet with a 2 byte size header, `None` at the end of data.
+fn packet_at<'a>(
+    data: &'a [u8],
+    offset: usize,
+    riff: &RiffChunks,
+) -> eyre::Result<Option<&'a [u8]>> {
+    if offset + 2 > data.len() {
+        return Ok(None);
+    }
+    let size = riff.endian.u16(&data[offset..]) as usize;
+    data.get(offset + 2..offset + 2 + size)
+        .map(Some)
+        .ok_or_else(|| eyre::eyre!("Vorbis packet at {:#x} out of range", offset))
+}
+
+/// Rebuild the standard setup header from the stripped Wwise one.
+fn rebuild_setup(
+    packet: &[u8],
+    channels: u32,
+    codebooks: &CodebookLibrary,
+) -> eyre::Result<(Vec<u8>, Modes)> {
+    let r = &mut BitReader::new(packet);
+    let w = &mut BitWriter::new();
+    w.write(5, 8);
+    w.write_bytes(b"vorbis");
+
+    // copy fields of given bit sizes
+    macro_rules! copy {
+        ($bits:expr) => {{
+            let bits = $bits;
+            let value = r.read(bits)?;
+            w.write(value, bits);
+            value
+        }};
+    }
+
+    let codebook_count = copy!(8) + 1;
+    for _ in 0..codebook_count {
+        let id = r.read(10)?;
+        if id == INLINE_CODEBOOK_ID && codebooks.get(id).is_none() {
+            eyre::bail!("Inline codebooks are not supported");
+        }
+        codebooks.rebuild(id, w)?;
+    }
+
+    // time domain transforms, placeholder
+    w.write(0, 6);
+    w.write(0, 16);
+
+    let floor_count = copy!(6) + 1;
+    for _ in 0..floor_count {
+        // always floor type 1
+        w.write(1, 16);
+        let partitions = copy!(5);
+        let mut partition_classes = vec![];
+        for _ in 0..partitions {
+            partition_classes.push(copy!(4));
+        }
+        let max_class = partition_classes.iter().copied().max().unwrap_or(0);
+        let mut class_dimensions = vec![];
+        for _ in 0..=max_class {
+            class_dimensions.push(copy!(3) + 1);
+            let subclasses = copy!(2);
+            if subclasses != 0 && copy!(8) >= codebook_count {
+                eyre::bail!("Invalid floor1 masterbook");
+            }
+            for _ in 0..(1 << subclasses) {
+                let book_plus1 = copy!(8);
+                if book_plus1 > codebook_count {
+                    eyre::bail!("Invalid floor1 subclass book");
+                }
+            }
+        }
+        copy!(2); // multiplier
+        let range_bits = copy!(4);
+        for class in partition_classes {
+            for _ in 0..class_dimensions[class as usize] {
+                copy!(range_bits);
+            }
+        }
+    }
+
+    let residue_count = copy!(6) + 1;
+    for _ in 0..residue_count {
+        let residue_type = r.read(2)?;
+        if residue_type > 2 {
+            eyre::bail!("Invalid residue type: {}", residue_type);
+        }
+        w.write(residue_type, 16);
+        copy!(24); // begin
+        copy!(24); // end
+        copy!(24); // partition size
+        let classifications = copy!(6) + 1;
+        if copy!(8) >= codebook_count {
+            eyre::bail!("Invalid residue classbook");
+        }
+        let mut cascade = vec![];
+        for _ in 0..classifications {
+            let low_bits = copy!(3);
+            let high_bits = if copy!(1) != 0 { copy!(5) } else { 0 };
+            cascade.push(high_bits * 8 + low_bits);
+        }
+        for bits in cascade {
+            for k in 0..8 {
+                if bits & (1 << k) != 0 && copy!(8) >= codebook_count {
+                    eyre::bail!("Invalid residue book");
+                }
+            }
+        }
+    }
+
+    let mapping_count = copy!(6) + 1;
+    for _ in 0..mapping_count {
+        // always mapping type 0
+        w.write(0, 16);
+        let submaps = if copy!(1) != 0 { copy!(4) + 1 } else { 1 };
+        if copy!(1) != 0 {
+            let coupling_steps = copy!(8) + 1;
+            let channel_bits = ilog(channels - 1);
+            for _ in 0..coupling_steps {
+                let magnitude = copy!(channel_bits);
+                let angle = copy!(channel_bits);
+                if magnitude == angle || magnitude >= channels || angle >= channels {
+                    eyre::bail!("Invalid channel coupling");
+                }
+            }
+        }
+        if copy!(2) != 0 {
+            eyre::bail!("Mapping reserved field is not zero");
+        }
+        if submaps > 1 {
+            for _ in 0..channels {
+                if copy!(4) >= submaps {
+                    eyre::bail!("Invalid mapping mux");
+                }
+            }
+        }
+        for _ in 0..submaps {
+            copy!(8); // time config
+            if copy!(8) >= floor_count {
+                eyre::bail!("Invalid mapping floor");
+            }
+            if copy!(8) >= residue_count {
+                eyre::bail!("Invalid mapping residue");
+            }
+        }
+    }
+
+    let mode_count = copy!(6) + 1;
+    let mut blockflags = vec![];
+    for _ in 0..mode_count {
+        blockflags.push(copy!(1) != 0);
+        // window type and transform type
+        w.write(0, 16);
+        w.write(0, 16);
+        if copy!(8) >= mapping_count {
+            eyre::bail!("Invalid mode mapping");
+        }
+    }
+    w.write_flag(true); // framing
+
+    if r.bits_read().div_ceil(8) != packet.len() {
+        log::warn!(
+            "Vorbis setup packet has {} unused bytes",
+            packet.len() - r.bits_read().div_ceil(8)
+        );
+    }
+    let modes = Modes {
+        blockflags,
+        bits: ilog(mode_count - 1),
+    };
+    Ok((std::mem::take(w).into_bytes(), modes))
+}
+
+/// Restore the packet type and window flags stripped from audio packets.
+fn rebuild_audio_packet(
+    packet: &[u8],
+    next: Option<&[u8]>,
+    modes: &Modes,
+    prev_blockflag: &mut bool,
+) -> eyre::Result<Vec<u8>> {
+    let r = &mut BitReader::new(packet);
+    let mut w = BitWriter::new();
+    // audio packet type
+    w.write(0, 1);
+    let mode = r.read(modes.bits)?;
+    w.write(mode, modes.bits);
+    let remainder = r.read(8 - modes.bits)?;
+
+    let blockflag = |mode: u32| modes.blockflags.get(mode as usize).copied();
+    let long_window = blockflag(mode).ok_or_else(|| eyre::eyre!("Invalid mode: {}", mode))?;
+    if long_window {
+        // long windows need the sizes of neighbour windows
+        let next_blockflag = match next.filter(|n| !n.is_empty()) {
+            Some(next) => blockflag(BitReader::new(next).read(modes.bits)?).unwrap_or(false),
+            None => false,
+        };
+        w.write_flag(*prev_blockflag);
+        w.write_flag(next_blockflag);
+    }
+    *prev_blockflag = long_window;
+
+    w.write(remainder, 8 - modes.bits);
+    w.write_bytes(&packet[1..]);
+    Ok(w.into_bytes())
+}
+
+/// Number of values in a lookup type 1 codebook, as `_book_maptype1_quantvals`
+/// in libvorbis.
+fn quantvals(entries: u32, dimensions: u32) -> u32 {
+    if dimensions == 0 {
+        return 0;
+    }
+    let bits = ilog(entries);
+    let mut vals = entries >> ((bits.saturating_sub(1)) * (dimensions - 1) / dimensions);
+    loop {
+        let acc = (vals as u64).saturating_pow(dimensions);
+        let acc1 = (vals as u64 + 1).saturating_pow(dimensions);
+        if acc <= entries as u64 && acc1 > entries as u64 {
+            return vals;
+        }
+        if acc > entries as u64 {
+            vals -= 1;
+        } else {
+            vals += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Library with one unordered, non-sparse codebook of 4 entries.
+    fn library() -> CodebookLibrary {
+        let mut w = BitWriter::new();
+        w.write(1, 4); // dimensions
+        w.write(4, 14); // entries
+        w.write_flag(false); // ordered
+        w.write(3, 3); // codeword length bits
+        w.write_flag(false); // sparse
+        for length in [0, 1, 2, 2] {
+            w.write(length, 3);
+        }
+        w.write(0, 1); // lookup type
+        let mut data = w.into_bytes();
+        let end = data.len() as u32;
+        data.extend(0u32.to_le_bytes());
+        data.extend(end.to_le_bytes());
+        CodebookLibrary::from_bytes(data).unwrap()
+    }
+
+    #[test]
+    fn test_rebuild_codebook() {
+        let library = library();
+        assert!(library.get(1).is_none());
+
+        let mut w = BitWriter::new();
+        library.rebuild(0, &mut w).unwrap();
+        let bytes = w.into_bytes();
+        assert_eq!(&bytes[0..3], b"BCV");
+
+        let mut r = BitReader::new(&bytes[3..]);
+        assert_eq!(r.read(16).unwrap(), 1);
+        assert_eq!(r.read(24).unwrap(), 4);
+        assert!(!r.read_flag().unwrap()); // ordered
+        assert!(!r.read_flag().unwrap()); // sparse
+        for length in [0, 1, 2, 2] {
+            assert_eq!(r.read(5).unwrap(), length);
+        }
+        assert_eq!(r.read(4).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_quantvals() {
+        assert_eq!(quantvals(81, 4), 3);
+        assert_eq!(quantvals(625, 4), 5);
+        assert_eq!(quantvals(289, 2), 17);
+        assert_eq!(quantvals(300, 1), 300);
+    }
+
+    #[test]
+    fn test_rebuild_audio_packet() {
+        // mode 0 is a short window, mode 1 a long one
+        let modes = Modes {
+            blockflags: vec![false, true],
+            bits: 1,
+        };
+        let long = [0x01 | (0x55 << 1), 0xAB];
+        let short = [0x2A << 1, 0xCD];
+
+        let mut prev_blockflag = true;
+        let packet =
+            rebuild_audio_packet(&long, Some(&short), &modes, &mut prev_blockflag).unwrap();
+        let mut r = BitReader::new(&packet);
+        assert_eq!(r.read(1).unwrap(), 0); // audio packet
+        assert_eq!(r.read(1).unwrap(), 1); // mode
+        assert!(r.read_flag().unwrap()); // previous window
+        assert!(!r.read_flag().unwrap()); // next window
+        assert_eq!(r.read(7).unwrap(), 0x55);
+        assert_eq!(r.read(8).unwrap(), 0xAB);
+        assert!(prev_blockflag);
+
+        // short windows have no neighbour flags
+        let packet = rebuild_audio_packet(&short, None, &modes, &mut prev_blockflag).unwrap();
+        let mut r = BitReader::new(&packet);
+        assert_eq!(r.read(2).unwrap(), 0);
+        assert_eq!(r.read(7).unwrap(), 0x2A);
+        assert_eq!(r.read(8).unwrap(), 0xCD);
+        assert!(!prev_blockflag);
+
+        let invalid = [0x01];
+        let modes = Modes {
+            blockflags: vec![false],
+            bits: 1,
+        };
+        assert!(rebuild_audio_packet(&invalid, None, &modes, &mut prev_blockflag).is_err());
+    }
+
+    /// Decodes a wem made by Wwise, the files are not redistributable.
+    #[test]
+    #[ignore = "needs test_files/vorbis.wem and the codebook library in test_files"]
+    fn test_decode() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_files");
+        let codebooks = CodebookLibrary::from_file(dir.join(CODEBOOKS_FILE)).unwrap();
+        let wem = fs::read(dir.join("vorbis.wem")).unwrap();
+        let info = crate::wem::WemInfo::from_bytes(&wem).unwrap();
+
+        let pcm = decode(&wem, &codebooks).unwrap();
+        assert_eq!(pcm.channels, info.channels);
+        assert_eq!(pcm.sample_rate, info.sample_rate);
+        assert_eq!(
+            pcm.samples.len(),
+            info.sample_count as usize * info.channels as usize
+        );
+        assert!(pcm.samples.iter().any(|&s| s != 0));
+    }
+}
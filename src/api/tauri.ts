@@ -1,4 +1,5 @@
 import type { BnkData } from '@/models/bnk'
+import type { HircEntry } from '@/models/bnk/hirc'
 import type { PckHeader } from '@/models/pck'
 import { stringToU32LE } from '@/utils'
 import { invoke } from '@tauri-apps/api/core'
@@ -13,35 +14,357 @@ export interface LoudnessInfo {
   lufs?: number
 }
 
+export interface MediaRemoval {
+  id: number
+  removed: boolean
+  references: {
+    hircId: number
+    entryType: string
+    pointer: string
+    rewritten: boolean
+  }[]
+}
+
+export interface ValidationIssue {
+  severity: 'error' | 'warning'
+  hircId: number
+  entryType: string
+  kind: 'media' | 'action' | 'target' | 'parent' | 'bus' | 'child'
+  referencedId: number
+  pointer: string
+  message: string
+}
+
+export interface ValidationReport {
+  errors: number
+  warnings: number
+  issues: ValidationIssue[]
+}
+
+export interface BankReport extends ValidationReport {
+  path: string
+}
+
+export type ChangeStatus = 'added' | 'removed' | 'changed'
+
+export interface BnkDiff {
+  sections: { magic: string; status: ChangeStatus }[]
+  media: {
+    id: number
+    status: ChangeStatus
+    oldSize?: number
+    newSize?: number
+    sizeDelta: number
+    oldHash?: string
+    newHash?: string
+  }[]
+  hirc: {
+    id: number
+    entryType: string
+    status: ChangeStatus
+    fields: { pointer: string; old: any; new: any }[]
+  }[]
+}
+
+export interface ExtractOptions {
+  /**
+   * File name template without extension.
+   * Placeholders: `{id}`, `{name}`, `{event}`, `{bank}`, `{index}`.
+   */
+  template?: string
+  /** Other bnk files with HIRC objects playing the wems. */
+  referencePaths?: string[]
+}
+
+export type ConflictPolicy = 'fail' | 'ours' | 'theirs'
+
+export interface MergeReport {
+  mediaChanged: number
+  hircChanged: number
+  conflicts: {
+    kind: 'section' | 'media' | 'hirc'
+    id: number
+    pointer: string
+    ours: any
+    theirs: any
+    resolution?: 'ours' | 'theirs'
+  }[]
+}
+
+export interface EventGraphNode {
+  id: number
+  /** Undefined if the object is not in this bank. */
+  entryType?: string
+  /** Link from parent is inferred from raw container data. */
+  inferred: boolean
+  media: number[]
+  children: EventGraphNode[]
+}
+
+export interface EventGraph {
+  root: EventGraphNode
+  media: number[]
+  missing: number[]
+}
+
+/** MusicTrack fields updated from replaced wems on save. */
+export interface TrackSyncReport {
+  changes: {
+    hircId: number
+    sourceId: number
+    pointer: string
+    old: any
+    new: any
+  }[]
+  /** Playlist items trimmed to nothing of the new wem, trims are kept. */
+  emptyClips: {
+    hircId: number
+    sourceId: number
+    pointer: string
+    clipLength: number
+  }[]
+}
+
+/** Bank save result, HIRC references are checked before writing. */
+export interface SaveReport {
+  /** False if reference errors were found and saving was not forced. */
+  saved: boolean
+  validation: BankReport
+  trackSync: TrackSyncReport
+}
+
+export interface BnkSessionInfo {
+  handle: number
+  sections: string[]
+  hircCount: number
+  /** Embedded wem ids in DIDX order. */
+  media: number[]
+}
+
+/** All filters are optional and combined with AND. */
+export interface HircQuery {
+  types?: string[]
+  idMin?: number
+  idMax?: number
+  parentId?: number
+  /** Case insensitive match on id, entry type or name. */
+  text?: string
+  offset?: number
+  /** 200 if not provided. */
+  limit?: number
+}
+
+export interface HircPage {
+  total: number
+  entries: HircEntry[]
+  /** Names of the entries and the ids they reference. */
+  names: Record<number, string>
+}
+
 export class BnkApi {
+  /**
+   * Load bnk sections, HIRC is left out by default as it can be huge.
+   * Opened banks fetch HIRC entries with `queryHirc` instead.
+   */
   public static async loadFile(
     path: string,
     sectionFilter?: number[]
-  ): Promise<BnkData> {
+  ): Promise<BnkData & { names: Record<number, string> }> {
     if (!sectionFilter) {
-      sectionFilter = [
-        stringToU32LE('BKHD'),
-        stringToU32LE('DIDX'),
-        stringToU32LE('HIRC'),
-      ]
+      sectionFilter = [stringToU32LE('BKHD'), stringToU32LE('DIDX')]
     }
 
     return invoke('bnk_load_file', { path, sectionFilter })
   }
 
+  /**
+   * @param options Naming template of extracted files, export settings by default.
+   */
   public static async extractData(
     path: string,
-    targetPath: string
+    targetPath: string,
+    options?: ExtractOptions
   ): Promise<void> {
-    return invoke('bnk_extract_data', { path, targetPath })
+    return invoke('bnk_extract_data', { path, targetPath, options })
   }
 
+  /**
+   * Save bnk with wem files replaced or added, edits of opened banks are
+   * saved with `sessionSave`.
+   * @param dataDir Directory of replaced or added wem files, named by id.
+   * @param referencePaths Other bnk or pck files loaded along with it.
+   * @param force Write even if HIRC references have errors.
+   */
   public static async saveFile(
     path: string,
-    bnk: BnkData,
-    dataDir?: string
+    sourcePath: string,
+    dataDir: string | undefined,
+    referencePaths: string[],
+    force: boolean = false
+  ): Promise<SaveReport> {
+    return invoke('bnk_save_file', {
+      path,
+      sourcePath,
+      dataDir,
+      referencePaths,
+      force,
+    })
+  }
+
+  /**
+   * Check HIRC references of saved bnk files resolve in the files themselves
+   * or reference files.
+   * @param referencePaths Other bnk or pck files loaded along with them.
+   */
+  public static async validate(
+    paths: string[],
+    referencePaths: string[]
+  ): Promise<BankReport[]> {
+    return invoke('bnk_validate', { paths, referencePaths })
+  }
+
+  /**
+   * Load bnk into backend memory, entries are then fetched with `queryHirc`.
+   */
+  public static async openSession(path: string): Promise<BnkSessionInfo> {
+    return invoke('bnk_session_open', { path })
+  }
+
+  public static async closeSession(handle: number): Promise<void> {
+    return invoke('bnk_session_close', { handle })
+  }
+
+  /**
+   * Fetch HIRC entries of an opened bnk by filters, with offset and limit paging.
+   */
+  public static async queryHirc(
+    handle: number,
+    query: HircQuery
+  ): Promise<HircPage> {
+    return invoke('bnk_query_hirc', { handle, query })
+  }
+
+  /**
+   * Set a field of HIRC entry in an opened bnk by JSON pointer.
+   * Arrays and objects must keep their length and keys.
+   * @returns The updated entry.
+   */
+  public static async sessionSetField(
+    handle: number,
+    id: number,
+    pointer: string,
+    value: any
+  ): Promise<HircEntry> {
+    return invoke('bnk_session_set_field', { handle, id, pointer, value })
+  }
+
+  /**
+   * Replace the HIRC entry with the same id in an opened bnk.
+   * Count fields and length are recomputed from the entry.
+   * @returns The stored entry.
+   */
+  public static async sessionReplaceEntry(
+    handle: number,
+    entry: HircEntry
+  ): Promise<HircEntry> {
+    return invoke('bnk_session_replace_entry', { handle, entry })
+  }
+
+  /**
+   * Replace or add embedded wem of an opened bnk from file.
+   */
+  public static async sessionReplaceMedia(
+    handle: number,
+    id: number,
+    path: string
   ): Promise<void> {
-    return invoke('bnk_save_file', { path, bnk, dataDir })
+    return invoke('bnk_session_replace_media', { handle, id, path })
+  }
+
+  /**
+   * Remove embedded wems of an opened bnk, and report HIRC references to them.
+   * @param cleanup Also remove references in MusicTrack.
+   */
+  public static async sessionRemoveMedia(
+    handle: number,
+    ids: number[],
+    cleanup: boolean
+  ): Promise<MediaRemoval[]> {
+    return invoke('bnk_session_remove_media', { handle, ids, cleanup })
+  }
+
+  /**
+   * Write an opened bnk, to its source path if no path provided.
+   * MusicTrack sources using the replaced wems are updated to match them.
+   * @param referencePaths Other bnk or pck files loaded along with it,
+   * HIRC references are checked against them before writing.
+   * @param force Write even if HIRC references have errors.
+   */
+  public static async sessionSave(
+    handle: number,
+    path: string | undefined,
+    referencePaths: string[],
+    force: boolean = false
+  ): Promise<SaveReport> {
+    return invoke('bnk_session_save', { handle, path, referencePaths, force })
+  }
+
+  /**
+   * Resolve the tree of objects and wem sources an event in an opened bnk can play.
+   */
+  public static async resolveEvent(
+    handle: number,
+    eventId: number
+  ): Promise<EventGraph> {
+    return invoke('bnk_resolve_event', { handle, eventId })
+  }
+
+  /**
+   * Structured diff of two bnk files, e.g. vanilla and modded.
+   */
+  public static async diff(oldPath: string, newPath: string): Promise<BnkDiff> {
+    return invoke('bnk_diff', { oldPath, newPath })
+  }
+
+  /**
+   * Export bnk as an editable json document, wem data is written
+   * to `<document stem>.media/` next to it.
+   */
+  public static async exportText(
+    path: string,
+    outputPath: string
+  ): Promise<void> {
+    return invoke('bnk_export_text', { path, outputPath })
+  }
+
+  /**
+   * Rebuild bnk from a document written by `exportText`.
+   */
+  public static async importText(
+    path: string,
+    outputPath: string
+  ): Promise<void> {
+    return invoke('bnk_import_text', { path, outputPath })
+  }
+
+  /**
+   * Three-way merge of two modded bnk files on top of their base.
+   * Output is only written if all conflicts are resolved.
+   */
+  public static async merge(
+    basePath: string,
+    oursPath: string,
+    theirsPath: string,
+    outputPath: string,
+    onConflict: ConflictPolicy = 'fail'
+  ): Promise<MergeReport> {
+    return invoke('bnk_merge', {
+      basePath,
+      oursPath,
+      theirsPath,
+      outputPath,
+      onConflict,
+    })
   }
 }
 
@@ -50,19 +373,77 @@ export class PckApi {
     return invoke('pck_load_basic_data', { path })
   }
 
+  /**
+   * Open pck in backend memory, edits are applied on save.
+   */
+  public static async openSession(
+    path: string
+  ): Promise<{ handle: number; header: PckHeader; hasData: boolean }> {
+    return invoke('pck_session_open', { path })
+  }
+
+  public static async closeSession(handle: number): Promise<void> {
+    return invoke('pck_session_close', { handle })
+  }
+
+  /**
+   * Replace wem of an opened pck from file, adding the entry if missing.
+   * @param language Language name in pck string table, sfx if not provided.
+   * @returns The updated header.
+   */
+  public static async sessionReplaceWem(
+    handle: number,
+    id: number,
+    path: string,
+    language?: string
+  ): Promise<PckHeader> {
+    return invoke('pck_session_replace_wem', { handle, id, language, path })
+  }
+
+  /**
+   * Remove wem entries of an opened pck, in all languages if not provided.
+   * @returns The updated header.
+   */
+  public static async sessionRemoveWem(
+    handle: number,
+    id: number,
+    language?: string
+  ): Promise<PckHeader> {
+    return invoke('pck_session_remove_wem', { handle, id, language })
+  }
+
+  /**
+   * Write an opened pck, output path must differ from its source.
+   */
+  public static async sessionSave(
+    handle: number,
+    outputPath: string
+  ): Promise<void> {
+    return invoke('pck_session_save', { handle, outputPath })
+  }
+
+  /**
+   * @param options Naming template of extracted files, export settings by default.
+   */
   public static async extractData(
     path: string,
-    targetPath: string
+    targetPath: string,
+    options?: ExtractOptions
   ): Promise<void> {
-    return invoke('pck_extract_data', { path, targetPath })
+    return invoke('pck_extract_data', { path, targetPath, options })
   }
 
+  /**
+   * Save pck with wem files replaced, edits of opened packages are saved
+   * with `sessionSave`.
+   * @param dataPath Directory of replaced wem files, named by id.
+   */
   public static async saveFile(
-    header: PckHeader,
     outputPath: string,
+    sourcePath: string,
     dataPath?: string
   ): Promise<void> {
-    return invoke('pck_save_file', { header, outputPath, dataPath })
+    return invoke('pck_save_file', { outputPath, sourcePath, dataPath })
   }
 }
 
@@ -78,11 +459,276 @@ export async function getLoudnessInfo(path: string): Promise<LoudnessInfo> {
   return invoke('loudness_get_info', { path })
 }
 
+export interface WemInfo {
+  codec: number
+  codecName: string
+  channels: number
+  channelLayout: {
+    /** 0 anonymous, 1 standard, 2 ambisonic. */
+    configType: number
+    mask: number
+    speakers: string[]
+  } | null
+  sampleRate: number
+  avgBytesPerSec: number
+  blockAlign: number
+  bitsPerSample: number
+  sampleCount: number
+  durationMs: number
+  /** Loop range in samples, end inclusive. */
+  loopPoints: { start: number; end: number } | null
+  markers: { id: number; position: number; label: string | null }[]
+  fileSize: number
+  dataSize: number
+}
+
+/**
+ * Read wem header without decoding audio.
+ */
+export async function getWemInfo(path: string): Promise<WemInfo> {
+  return invoke('wem_get_info', { path })
+}
+
+export interface BuildReport {
+  banks: {
+    output: string
+    mediaReplaced: number
+    hircEdited: number
+  }[]
+}
+
+export class ManifestApi {
+  public static async build(path: string): Promise<BuildReport> {
+    return invoke('manifest_build', { path })
+  }
+}
+
+export interface PatchSummary {
+  kind: 'bnk' | 'pck'
+  sourceHash: string
+  resultHash: string
+  mediaChanged: number
+  hircChanged: number
+  blobSize: number
+}
+
+export interface PatchApplyReport {
+  expectedHash: string
+  resultHash: string
+  matched: boolean
+}
+
+export class PatchApi {
+  /**
+   * Create a patch with the changes from a vanilla bnk or pck to a modded one.
+   */
+  public static async create(
+    vanillaPath: string,
+    moddedPath: string,
+    outputPath: string
+  ): Promise<PatchSummary> {
+    return invoke('patch_create', { vanillaPath, moddedPath, outputPath })
+  }
+
+  /**
+   * Rebuild a modded file from the vanilla file, which must match the patch.
+   */
+  public static async apply(
+    patchPath: string,
+    vanillaPath: string,
+    outputPath: string
+  ): Promise<PatchApplyReport> {
+    return invoke('patch_apply', { patchPath, vanillaPath, outputPath })
+  }
+}
+
+export class NamesApi {
+  /**
+   * Load wordlists of Wwise object names, one name per line.
+   * @returns Count of names read.
+   */
+  public static async loadFiles(paths: string[]): Promise<number> {
+    return invoke('names_load_files', { paths })
+  }
+
+  /**
+   * Wwise id of a name, FNV-1 hash of the lowercased name.
+   */
+  public static async hash(name: string): Promise<number> {
+    return invoke('names_hash', { name })
+  }
+
+  /**
+   * Names of the ids found in loaded wordlists.
+   */
+  public static async lookup(ids: number[]): Promise<Record<number, string>> {
+    return invoke('names_lookup', { ids })
+  }
+}
+
+/** Whether an external tool works, and why not. */
+export interface ToolStatus {
+  path: string | null
+  version: string | null
+  ok: boolean
+  error: string | null
+}
+
+export interface ToolsStatus {
+  ffmpeg: ToolStatus
+  wwiseConsole: ToolStatus
+  vgmstream: ToolStatus
+}
+
+/** Backend used to decode wem, `auto` prefers native decoding. */
+export type WemDecoder = 'auto' | 'native' | 'vgmstream'
+
+/**
+ * Backend used to encode wem, `pcm` is native and needs no WwiseConsole.
+ * `auto` uses WwiseConsole if available, PCM otherwise.
+ */
+export type WemEncoder = 'auto' | 'wwise' | 'pcm'
+
+/**
+ * Wwise conversion of wav to wem. `matchSource` uses the codec of the
+ * replaced wem, `shareSet` a conversion ShareSet of the temp project.
+ */
+export type ConversionPreset =
+  | 'matchSource'
+  | 'pcm'
+  | 'adpcm'
+  | 'vorbisHigh'
+  | 'vorbisMedium'
+  | 'vorbisLow'
+  | { shareSet: string }
+
+export interface TranscodeOptions {
+  /** Default conversion of settings if not set. */
+  conversion?: ConversionPreset
+  /** Wem being replaced, for `matchSource`. */
+  sourceWem?: string
+}
+
+/** A conversion of a batch. */
+export interface TranscodeJob {
+  input: string
+  output: string
+  options?: TranscodeOptions
+}
+
+export interface TranscodeJobResult {
+  input: string
+  output: string
+  /** Why the job failed, null on success. */
+  error: string | null
+}
+
 export class Transcode {
   public static async autoTranscode(
     input: string,
-    output: string
+    output: string,
+    options?: TranscodeOptions
   ): Promise<void> {
-    return invoke('transcode_auto_transcode', { input, output })
+    return invoke('transcode_auto_transcode', { input, output, options })
+  }
+
+  /**
+   * Convert files in a batch, wavs to wem with a single WwiseConsole run.
+   * @returns Results in the order of jobs, failed jobs have `error` set.
+   */
+  public static async batchTranscode(
+    jobs: TranscodeJob[]
+  ): Promise<TranscodeJobResult[]> {
+    return invoke('transcode_batch', { jobs })
+  }
+
+  /**
+   * Names of the conversion ShareSets in the Wwise temp project.
+   */
+  public static async conversionShareSets(): Promise<string[]> {
+    return invoke('transcode_conversion_share_sets')
+  }
+
+  /**
+   * Detect tool paths, configured paths are kept for tools not found.
+   */
+  public static async autoDetectPaths(): Promise<ToolsStatus> {
+    return invoke('transcode_auto_detect_paths')
+  }
+
+  /**
+   * Check the configured tools can run.
+   */
+  public static async check(): Promise<ToolsStatus> {
+    return invoke('transcode_check')
+  }
+
+  /**
+   * Select the wem decoding backend.
+   * @param codebooksPath Vorbis codebooks file for native decoding.
+   */
+  public static async setWemDecoder(
+    decoder: WemDecoder,
+    codebooksPath?: string
+  ): Promise<void> {
+    return invoke('transcode_set_wem_decoder', { decoder, codebooksPath })
+  }
+
+  /**
+   * Select the wem encoding backend.
+   */
+  public static async setWemEncoder(encoder: WemEncoder): Promise<void> {
+    return invoke('transcode_set_wem_encoder', { encoder })
+  }
+}
+
+/** Paths of external tools, detected on startup if not set. */
+export interface ToolPaths {
+  ffmpeg?: string | null
+  wwiseConsole?: string | null
+  vgmstream?: string | null
+  /** Vorbis codebooks for native decoding. */
+  codebooks?: string | null
+}
+
+export interface Settings {
+  version: number
+  tools: ToolPaths
+  /** Default conversion backends. */
+  transcode: {
+    decoder: WemDecoder
+    encoder: WemEncoder
+    /** Conversion of wav to wem if not chosen per source. */
+    conversion: ConversionPreset
+  }
+  /** Default options of extract commands. */
+  export: ExtractOptions
+  /** Wordlists loaded on startup. */
+  wordlists: string[]
+}
+
+export interface SettingsIssue {
+  /** Path of the field, e.g. `tools.ffmpeg`, empty for the whole file. */
+  field: string
+  message: string
+}
+
+export interface SettingsState {
+  settings: Settings
+  issues: SettingsIssue[]
+  /** Path of the settings file. */
+  path: string
+}
+
+export class SettingsApi {
+  public static async get(): Promise<SettingsState> {
+    return invoke('settings_get')
+  }
+
+  /**
+   * Save settings and apply them. Settings with issues are still saved.
+   */
+  public static async update(settings: Settings): Promise<SettingsState> {
+    return invoke('settings_update', { newSettings: settings })
   }
 }
@@ -1,11 +1,10 @@
 import { BnkApi } from '@/api/tauri'
 import type {
-  BnkData,
-  DataSection,
-  DidxEntry,
-  DidxSection,
-  Section,
-} from '@/models/bnk'
+  BnkSessionInfo,
+  ConversionPreset,
+  SaveReport,
+  TrackSyncReport,
+} from '@/api/tauri'
 import type {
   HircEntry,
   HircEventEntry,
@@ -19,7 +18,7 @@ import { getExtension, getFileName } from '@/utils/path'
 import { reactive, type Reactive, ref, toRef } from 'vue'
 import { SourceManager } from '@/libs/source'
 import { useWorkspaceStore } from '@/stores/workspace'
-import { exists, rename, mkdir, remove, copyFile } from '@tauri-apps/plugin-fs'
+import { exists, rename, remove, copyFile } from '@tauri-apps/plugin-fs'
 import { Transcoder, type TargetFormat } from '@/libs/transcode'
 import { LocalDir } from '@/libs/localDir'
 import { join } from '@tauri-apps/api/path'
@@ -30,38 +29,99 @@ export type OverrideSource = {
   path: string
 }
 
+type TrackSyncChange = TrackSyncReport['changes'][number]
+
+/** HIRC types shown in the workspace tree, other entries stay in the backend. */
+const TREE_ENTRY_TYPES = ['MusicSegment', 'MusicTrack']
+const HIRC_PAGE_SIZE = 500
+
 export class Bnk {
-  public data: BnkData
+  /** HIRC entries of `TREE_ENTRY_TYPES` in file order. */
+  public entries: HircEntry[]
+  /** Embedded wem ids in DIDX order. */
+  public media: number[]
   public name: string = ''
   public filePath: string = ''
+  /** Backend session holding the bank, edits are sent to it on export. */
+  public handle: number = 0
   public overrideMap: Reactive<Record<number, OverrideSource>> = reactive({})
+  /** Names of ids found in loaded wordlists. */
+  public names: Record<number, string> = {}
   private _label: string = ''
+  private sections: string[]
   private segmentTree: SegmentTree | null = null
   private _managedSources: number[] = []
   private _unmanagedSources: number[] = []
+  /** HIRC entries as in the source file, by id. */
+  private sourceEntries = new Map<number, string>()
+  /** HIRC entries as last sent to the session, by id. */
+  private sessionEntries = new Map<number, string>()
+  /** Override audio paths as last sent to the session, by id. */
+  private sessionMedia: Record<number, string> = {}
+  /** MusicTrack fields updated by the session on export. */
+  private trackSyncChanges: TrackSyncChange[] = []
   private workspace = useWorkspaceStore()
 
-  constructor(data: BnkData) {
-    this.data = data
+  constructor(info: BnkSessionInfo, entries: HircEntry[]) {
+    this.handle = info.handle
+    this.sections = info.sections
+    this.media = info.media
+    this.entries = entries
   }
 
   public static async load(filePath: string): Promise<Bnk> {
-    const bnkData = await BnkApi.loadFile(filePath)
-    const bnk = new Bnk(bnkData)
+    const info = await BnkApi.openSession(filePath)
+    // fetch only the entries of the tree, page by page
+    const entries: HircEntry[] = []
+    const names: Record<number, string> = {}
+    let total = Infinity
+    while (entries.length < total) {
+      const page = await BnkApi.queryHirc(info.handle, {
+        types: TREE_ENTRY_TYPES,
+        offset: entries.length,
+        limit: HIRC_PAGE_SIZE,
+      })
+      if (page.entries.length === 0) break
+      total = page.total
+      entries.push(...page.entries)
+      Object.assign(names, page.names)
+    }
+
+    const bnk = new Bnk(info, entries)
+    bnk.names = names
     bnk.filePath = filePath
     bnk.name = getFileName(filePath)
     bnk._label = (await sha256(filePath)).substring(0, 8)
+    entries.forEach((entry) => {
+      bnk.sourceEntries.set(entry.id, JSON.stringify(entry))
+    })
+    bnk.sessionEntries = new Map(bnk.sourceEntries)
     return bnk
   }
 
+  /**
+   * Release the backend session.
+   */
+  public async close(): Promise<void> {
+    await BnkApi.closeSession(this.handle)
+  }
+
   public hasSection(ty: 'Bkhd' | 'Didx' | 'Hirc' | 'Data'): boolean {
-    return this.data.sections.some((section) => section.type === ty)
+    return this.sections.includes(ty.toUpperCase())
   }
 
   public getLabel(): string {
     return this._label
   }
 
+  /**
+   * Name of the id with the id itself, or only the id if unknown.
+   */
+  public displayName(id: number): string {
+    const name = this.names[id]
+    return name ? `${name} (${id})` : `${id}`
+  }
+
   public visit(visitor: BnkVisitor): void {
     visitor.visitBnk(this)
   }
@@ -78,10 +138,6 @@ export class Bnk {
     return this.segmentTree
   }
 
-  public getDidxSection(): DidxSection | null {
-    return this.data.sections.find((section) => section.type === 'Didx') ?? null
-  }
-
   public getManagedSources(): number[] {
     if (this._managedSources.length > 0) {
       return this._managedSources
@@ -98,30 +154,38 @@ export class Bnk {
       return this._unmanagedSources
     }
 
-    const didx = this.getDidxSection()
-    if (!didx) return []
-    this._unmanagedSources = didx.entries
-      .filter((entry) => !this._managedSources.includes(entry.id))
-      .map((entry) => entry.id)
+    this._unmanagedSources = this.media.filter(
+      (id) => !this._managedSources.includes(id)
+    )
     return this._unmanagedSources
   }
 
   public async extractData(outputDir: string): Promise<void> {
-    return BnkApi.extractData(this.filePath, outputDir)
+    // named by id regardless of export settings
+    return BnkApi.extractData(this.filePath, outputDir, {})
   }
 
   /**
    * 导出 BNK 文件到指定路径，包含音源替换处理
+   *
+   * Only the replaced audio and the edited HIRC entries are sent to the
+   * backend session, which checks HIRC references and writes the bank.
    * @param exportPath 导出文件路径
+   * @param referencePaths Other bnk or pck files loaded along with it.
    * @param logger 可选的日志记录器
+   * @param force Write even if HIRC references have errors.
+   * @returns Not saved if HIRC references have errors and not forced.
    */
   public async exportFile(
     exportPath: string,
+    referencePaths: string[],
     logger?: {
       debug: (message: string, data?: any) => void
       info: (message: string, data?: any) => void
-    }
-  ): Promise<void> {
+      warn: (message: string, data?: any) => void
+    },
+    force: boolean = false
+  ): Promise<SaveReport> {
     logger?.debug(`Processing BNK file: ${this.getLabel()}`)
 
     // 收集需要替换的音源
@@ -130,134 +194,122 @@ export class Bnk {
       replacedSourceIds: replacedSources.map((s) => s.id),
     })
 
-    // 在导出时处理DIDX section
-    if (replacedSources.length > 0) {
-      this.updateDidxForExport(replacedSources, logger)
+    // replaced media can not be restored in the session, start over
+    // from the source file if an override was removed since last export
+    const removedIds = Object.keys(this.sessionMedia)
+      .map(Number)
+      .filter((id) => !this.overrideMap[id])
+    if (removedIds.length > 0) {
+      logger?.debug('Reopening bnk session to drop removed audio sources', {
+        removedIds,
+      })
+      await this.reopenSession(removedIds)
     }
 
-    let tempSourceDir: string | undefined
-
-    // 如果bnk包含数据或者有新增的音频源，则需要处理音源数据
-    if (this.hasSection('Data') || replacedSources.length > 0) {
-      logger?.debug(
-        'BNK file contains data section or has new audio sources, need to process audio source replacement'
-      )
-
-      logger?.info(`Need to process ${replacedSources.length} audio sources`, {
-        replacedSourceIds: replacedSources.map((s) => s.id),
+    // 只发送替换/新增的音源，其余音源保留在会话中
+    for (const source of replacedSources) {
+      if (this.sessionMedia[source.id] === source.path) continue
+      await BnkApi.sessionReplaceMedia(this.handle, source.id, source.path)
+      this.sessionMedia[source.id] = source.path
+      logger?.debug(`Processed audio source: ${source.id}.wem`, {
+        sourcePath: source.path,
       })
+    }
 
-      // 创建临时目录存放替换的音源
-      const tempDir = await LocalDir.getTempDir()
-      tempSourceDir = await join(tempDir, 'export', this.getLabel())
-      if (await exists(tempSourceDir)) {
-        await remove(tempSourceDir, { recursive: true })
-        logger?.debug('Cleaned up existing temporary directory')
-      }
-      await mkdir(tempSourceDir, { recursive: true })
-      logger?.debug(`Created temporary directory: ${tempSourceDir}`)
-
-      // 如果原本有数据，先提取所有音源
-      if (this.hasSection('Data')) {
-        logger?.debug('Starting to extract BNK data')
-        await this.extractData(tempSourceDir)
-        logger?.debug('BNK data extraction completed')
-      }
-
-      // 复制替换/新增的音源到临时目录
-      for (const source of replacedSources) {
-        const sourcePath = source.path
-        const targetPath = await join(tempSourceDir, `${source.id}.wem`)
-        await copyFile(sourcePath, targetPath)
-        logger?.debug(`Processed audio source: ${source.id}.wem`, {
-          sourcePath,
-          targetPath,
-        })
-      }
-    } else {
-      logger?.debug(
-        'BNK file does not contain data section and no new audio sources, exporting directly'
-      )
+    // 发送修改过的 HIRC 条目
+    for (const entry of this.entries) {
+      const json = JSON.stringify(entry)
+      if (this.sessionEntries.get(entry.id) === json) continue
+      await BnkApi.sessionReplaceEntry(this.handle, entry)
+      this.sessionEntries.set(entry.id, json)
+      logger?.debug(`Updated HIRC entry: ${entry.id}`)
     }
 
     // 保存BNK文件
     logger?.debug('Starting to save BNK file')
-    await BnkApi.saveFile(exportPath, this.data, tempSourceDir)
+    const report = await BnkApi.sessionSave(
+      this.handle,
+      exportPath,
+      referencePaths,
+      force
+    )
+    const { validation, trackSync } = report
+    if (validation.issues.length > 0) {
+      logger?.warn(
+        `Found ${validation.errors} errors and ${validation.warnings} warnings in HIRC references of ${this.name}`,
+        { issues: validation.issues }
+      )
+    }
+    if (!report.saved) {
+      logger?.warn(`BNK file not saved: ${exportPath}`)
+      return report
+    }
+
+    trackSync.changes.forEach((change) => {
+      const entry = this.applyTrackSync(change, change.new)
+      if (entry) {
+        this.sessionEntries.set(entry.id, JSON.stringify(entry))
+      }
+      this.trackSyncChanges.push(change)
+      logger?.info(
+        `MusicTrack ${change.hircId} source ${change.sourceId}: ${change.pointer} ${change.old} -> ${change.new}`
+      )
+    })
+    trackSync.emptyClips.forEach((clip) => {
+      logger?.warn(
+        `MusicTrack ${clip.hircId} source ${clip.sourceId}: ${clip.pointer} is trimmed to ${clip.clipLength} ms of the new wem, fix its trims`
+      )
+    })
     logger?.info(`BNK file saved successfully: ${exportPath}`)
+    return report
   }
 
   /**
-   * 在导出时更新DIDX section以包含新增的音频源
-   * @param replacedSources 替换的音频源列表
-   * @param logger 可选的日志记录器
+   * Open a new session from the source file, dropping all edits sent to
+   * the current one.
+   * @param removedIds Overrides removed since last export, their track
+   * fields are restored.
    */
-  private updateDidxForExport(
-    replacedSources: OverrideSource[],
-    logger?: {
-      debug: (message: string, data?: any) => void
-      info: (message: string, data?: any) => void
-    }
-  ): void {
-    // 确保DIDX section存在
-    let didx = this.getDidxSection()
-    if (!didx) {
-      logger?.debug('Creating new DIDX section')
-      didx = {
-        type: 'Didx',
-        magic: [68, 73, 68, 88], // "DIDX"
-        section_length: 0, // 将在保存时计算
-        entries: [],
-      } as DidxSection
-      this.data.sections.push(didx)
-    }
-
-    // 确保Data section存在（如果有新增音频源）
-    if (!this.hasSection('Data')) {
-      logger?.debug('Creating new DATA section')
-      this.data.sections.push({
-        type: 'Data',
-        magic: [68, 65, 84, 65], // "DATA"
-        section_length: 0, // 将在保存时计算
-        data_list: [],
-      } as DataSection)
-    }
-
-    // 获取现有音频源ID
-    const managedSources = this.getManagedSources()
-    const unmanagedSources = this.getUnmanagedSources()
-    const existingSources = [...managedSources, ...unmanagedSources]
-
-    // 为新增的音频源添加DIDX条目
-    for (const source of replacedSources) {
-      const existingEntryIndex = didx.entries.findIndex(
-        (entry) => entry.id === source.id
-      )
+  private async reopenSession(removedIds: number[]): Promise<void> {
+    const { handle } = await BnkApi.openSession(this.filePath)
+    await BnkApi.closeSession(this.handle).catch((err) => {
+      console.warn(`Failed to close bnk session ${this.handle}: ${err}`)
+    })
+    this.handle = handle
+    this.sessionMedia = {}
+    this.sessionEntries = new Map(this.sourceEntries)
+
+    this.trackSyncChanges = this.trackSyncChanges.filter((change) => {
+      if (!removedIds.includes(change.sourceId)) return true
+      this.applyTrackSync(change, change.old)
+      return false
+    })
+  }
 
-      if (existingEntryIndex >= 0) {
-        // 如果已存在，更新现有条目（用于替换现有音频源）
-        logger?.debug(`Updating existing DIDX entry for source ${source.id}`)
-        didx.entries[existingEntryIndex] = {
-          id: source.id,
-          offset: 0, // 偏移量将在保存时由后端计算
-          length: 0, // 长度将在保存时由后端计算
-        }
-      } else if (!existingSources.includes(source.id)) {
-        // 如果不存在且不是原有音频源，添加新条目（用于新增音频源）
-        logger?.debug(`Adding new DIDX entry for source ${source.id}`)
-        didx.entries.push({
-          id: source.id,
-          offset: 0, // 偏移量将在保存时由后端计算
-          length: 0, // 长度将在保存时由后端计算
-        })
-      } else {
-        // 如果是替换现有音频源但DIDX中没有条目，说明可能是管理的音频源
-        logger?.debug(
-          `Source ${source.id} is a managed source, no DIDX entry needed`
-        )
-      }
+  /**
+   * Set a MusicTrack field updated by the session, so the entry is not
+   * sent back with stale values.
+   * @returns The updated entry, null if the field is not found.
+   */
+  private applyTrackSync(
+    change: TrackSyncChange,
+    value: any
+  ): HircEntry | null {
+    const entry = this.entries.find((e) => e.id === change.hircId)
+    if (!entry) return null
+
+    const keys = change.pointer
+      .split('/')
+      .slice(1)
+      .map((key) => key.replace(/~1/g, '/').replace(/~0/g, '~'))
+    const field = keys.pop()
+    const parent = keys.reduce<any>((obj, key) => obj?.[key], entry)
+    if (field === undefined || !parent || !(field in parent)) {
+      console.warn(`Track sync field not found: ${change.pointer}`)
+      return null
     }
-
-    logger?.debug(`DIDX section updated with ${didx.entries.length} entries`)
+    parent[field] = value
+    return entry
   }
 
   /**
@@ -315,13 +367,28 @@ export class Bnk {
     return results
   }
 
-  public async addOverrideAudio(id: number, filePath: string): Promise<void> {
+  /**
+   * @param conversion Conversion of non-wem audio, settings default if not provided.
+   */
+  public async addOverrideAudio(
+    id: number,
+    filePath: string,
+    conversion?: ConversionPreset
+  ): Promise<void> {
     // transcode and store to temp dir
     let storePath = null
     const ext = getExtension(filePath)
     if (ext !== 'wem') {
-      // transcode to wem
-      storePath = await Transcoder.getInstance().transcode(filePath, 'wem')
+      // transcode to wem, matching the replaced wem if any
+      const sourceWem = await SourceManager.getInstance()
+        .getSourceFilePath(id)
+        .catch(() => null)
+      storePath = await Transcoder.getInstance().transcode(
+        filePath,
+        'wem',
+        undefined,
+        { conversion, sourceWem: sourceWem ?? undefined }
+      )
     } else {
       // copy
       const randomId = uuidv4()
@@ -429,23 +496,15 @@ const MARKER_TYPE_MAP: { [id: number]: MarkerType } = {
 
 export class BnkVisitor {
   public visitBnk(bnk: Bnk): void {
-    bnk.data.sections.forEach((section) => {
-      this.visitSection(section)
+    bnk.entries.forEach((entry, idx) => {
+      try {
+        this.visitHircEntry(entry)
+      } catch (e) {
+        throw new Error(`Error while visiting HIRC entry ${idx}: ${e}`)
+      }
     })
   }
 
-  public visitSection(section: Section): void {
-    if (section.type === 'Hirc') {
-      section.entries.forEach((entry, idx) => {
-        try {
-          this.visitHircEntry(entry)
-        } catch (e) {
-          throw new Error(`Error while visiting HIRC entry ${idx}: ${e}`)
-        }
-      })
-    }
-  }
-
   public visitHircEntry(entry: HircEntry): void {
     switch (entry.entry_type) {
       case 'Sound':
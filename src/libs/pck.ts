@@ -1,9 +1,10 @@
 import { PckApi } from '@/api/tauri'
+import type { ConversionPreset } from '@/api/tauri'
 import type { PckHeader } from '@/models/pck'
 import { getExtension, getFileName } from '@/utils/path'
 import { sha256 } from '@/utils'
 import { SourceManager } from '@/libs/source'
-import { exists, rename, mkdir, remove, copyFile } from '@tauri-apps/plugin-fs'
+import { exists, rename, remove, copyFile } from '@tauri-apps/plugin-fs'
 import { Transcoder, type TargetFormat } from '@/libs/transcode'
 import { reactive, type Reactive } from 'vue'
 import type { OverrideSource } from '@/libs/bnk'
@@ -16,9 +17,13 @@ export class Pck {
   public header: PckHeader
   public name: string = ''
   public filePath: string = ''
+  /** Backend session holding the package, edits are sent to it on export. */
+  public handle: number = 0
   public overrideMap: Reactive<Record<number, OverrideSource>> = reactive({})
   private _hasData: boolean
   private _label: string = ''
+  /** Override audio paths as last sent to the session, by id. */
+  private sessionWems: Record<number, string> = {}
   private workspace = useWorkspaceStore()
 
   constructor(header: PckHeader, hasData: boolean) {
@@ -27,14 +32,22 @@ export class Pck {
   }
 
   public static async load(filePath: string): Promise<Pck> {
-    const { header, hasData } = await PckApi.loadBasicData(filePath)
+    const { handle, header, hasData } = await PckApi.openSession(filePath)
     const pck = new Pck(header, hasData)
+    pck.handle = handle
     pck.filePath = filePath
     pck.name = getFileName(filePath)
     pck._label = (await sha256(filePath)).substring(0, 8)
     return pck
   }
 
+  /**
+   * Release the backend session.
+   */
+  public async close(): Promise<void> {
+    await PckApi.closeSession(this.handle)
+  }
+
   public hasData(): boolean {
     return this._hasData
   }
@@ -44,7 +57,8 @@ export class Pck {
   }
 
   public async extractData(outputDir: string): Promise<void> {
-    return PckApi.extractData(this.filePath, outputDir)
+    // named by id regardless of export settings
+    return PckApi.extractData(this.filePath, outputDir, {})
   }
 
   /**
@@ -67,50 +81,56 @@ export class Pck {
       replacedSourceIds: replacedSources.map((s) => s.id),
     })
 
-    let tempSourceDir: string | undefined
-
-    // 如果PCK包含数据，则替换音源，否则直接导出
-    if (this.hasData()) {
-      logger?.debug(
-        'PCK file contains data, need to process audio source replacement'
-      )
+    // replaced wems can not be restored in the session, start over
+    // from the source file if an override was removed since last export
+    const removed = Object.keys(this.sessionWems).some(
+      (id) => !this.overrideMap[Number(id)]
+    )
+    if (removed) {
+      logger?.debug('Reopening pck session to drop removed audio sources')
+      const { handle } = await PckApi.openSession(this.filePath)
+      await PckApi.closeSession(this.handle).catch((err) => {
+        console.warn(`Failed to close pck session ${this.handle}: ${err}`)
+      })
+      this.handle = handle
+      this.sessionWems = {}
+    }
 
+    // 只发送替换的音源，其余音源由后端从原始PCK流式复制
+    if (this.hasData() && replacedSources.length > 0) {
       logger?.info(`Need to replace ${replacedSources.length} audio sources`, {
         replacedSourceIds: replacedSources.map((s) => s.id),
       })
 
-      // 创建临时目录存放替换的音源
-      const tempDir = await LocalDir.getTempDir()
-      tempSourceDir = await join(tempDir, 'export', this.getLabel())
-      if (await exists(tempSourceDir)) {
-        await remove(tempSourceDir, { recursive: true })
-        logger?.debug('Cleaned up existing temporary directory')
-      }
-      await mkdir(tempSourceDir, { recursive: true })
-      logger?.debug(`Created temporary directory: ${tempSourceDir}`)
-
-      // 先提取PCK内容
-      logger?.debug('Starting to extract PCK data')
-      await this.extractData(tempSourceDir)
-      logger?.debug('PCK data extraction completed')
-
-      // 复制替换的音源到临时目录,覆盖已提取的内容
       for (const source of replacedSources) {
-        const sourcePath = source.path
-        const targetPath = await join(tempSourceDir, `${source.id}.wem`)
-        await copyFile(sourcePath, targetPath)
+        if (this.sessionWems[source.id] === source.path) continue
+        // replace the wem in every language it has
+        const entries = this.header.wem_entries.filter(
+          (entry) => entry.id === source.id
+        )
+        for (const entry of entries) {
+          const language = this.header.string_table.find(
+            (string) => string.index === entry.language_id
+          )?.value
+          await PckApi.sessionReplaceWem(
+            this.handle,
+            source.id,
+            source.path,
+            language
+          )
+        }
+        this.sessionWems[source.id] = source.path
         logger?.debug(`Replaced audio source: ${source.id}.wem`, {
-          sourcePath,
-          targetPath,
+          sourcePath: source.path,
         })
       }
-    } else {
+    } else if (!this.hasData()) {
       logger?.debug('PCK file does not contain data, exporting directly')
     }
 
     // 保存PCK文件
     logger?.debug('Starting to save PCK file')
-    await PckApi.saveFile(this.header, exportPath, tempSourceDir)
+    await PckApi.sessionSave(this.handle, exportPath)
     logger?.info(`PCK file saved successfully: ${exportPath}`)
   }
 
@@ -172,8 +192,13 @@ export class Pck {
   /**
    * Add override audio.
    * Can only override existing audio. Adding is not allowed.
+   * @param conversion Conversion of non-wem audio, settings default if not provided.
    */
-  public async addOverrideAudio(id: number, filePath: string): Promise<void> {
+  public async addOverrideAudio(
+    id: number,
+    filePath: string,
+    conversion?: ConversionPreset
+  ): Promise<void> {
     // check if id exists
     if (!this.header.wem_entries.some((entry) => entry.id === id)) {
       throw new Error(`Audio ID ${id} not found in this PCK file`)
@@ -184,8 +209,16 @@ export class Pck {
     // transcode and store to temp dir
     let storePath = null
     if (ext !== 'wem') {
-      // transcode to wem
-      storePath = await Transcoder.getInstance().transcode(filePath, 'wem')
+      // transcode to wem, matching the replaced wem
+      const sourceWem = await SourceManager.getInstance()
+        .getSourceFilePath(id)
+        .catch(() => null)
+      storePath = await Transcoder.getInstance().transcode(
+        filePath,
+        'wem',
+        undefined,
+        { conversion, sourceWem: sourceWem ?? undefined }
+      )
     } else {
       // copy
       const randomId = uuidv4()
@@ -2,6 +2,7 @@ import { v4 as uuidv4 } from 'uuid'
 import { LocalDir } from './localDir'
 import { join } from '@tauri-apps/api/path'
 import { Transcode } from '@/api/tauri'
+import type { TranscodeOptions } from '@/api/tauri'
 import { exists } from '@tauri-apps/plugin-fs'
 
 export type TargetFormat = 'wav' | 'wem' | 'ogg' | 'flac' | 'mp3' | 'aac'
@@ -23,12 +24,14 @@ export class Transcoder {
   /**
    * Transcodes the input file to target format.
    * If outputPath provided, will ignore format parameter.
+   * @param options Conversion of wav to wem, settings default if not provided.
    * @returns The path of the transcoded file.
    */
   public async transcode(
     inputPath: string,
     format: TargetFormat,
-    outputPath?: string
+    outputPath?: string,
+    options?: TranscodeOptions
   ): Promise<string> {
     // check if input file exists
     if (!(await exists(inputPath))) {
@@ -41,7 +44,7 @@ export class Transcoder {
       outputPath = await join(tempDir, outputFilename)
     }
     // transcode the file
-    await Transcode.autoTranscode(inputPath, outputPath)
+    await Transcode.autoTranscode(inputPath, outputPath, options)
     return outputPath
   }
 }
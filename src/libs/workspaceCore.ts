@@ -1,16 +1,17 @@
 import { watch, type Reactive, reactive, computed, toRef } from 'vue'
 import { useWorkspaceStore } from '@/stores/workspace'
 import type { DataNode, WorkspaceFile } from '@/stores/workspace'
-import type { LoudnessInfo } from '@/api/tauri'
+import type { ConversionPreset, LoudnessInfo } from '@/api/tauri'
 import type { TreeNode } from '@/components/DragOverTree.vue'
 import type { SearchResult } from '@/components/Toolbar.vue'
 import { SourceManager } from '@/libs/source'
 import { Transcoder } from '@/libs/transcode'
 import { Bnk, type HircNode } from '@/libs/bnk'
 import { Pck } from '@/libs/pck'
-import { ShowError, ShowInfo, ShowWarn } from '@/utils/message'
+import { ShowConfirm, ShowError, ShowInfo, ShowWarn } from '@/utils/message'
 import { arrayCompare, readFileMagic } from '@/utils'
 import { getExtension } from '@/utils/path'
+import type { ExportLogger } from '@/utils/logger'
 import { convertFileSrc } from '@tauri-apps/api/core'
 import { exists } from '@tauri-apps/plugin-fs'
 
@@ -130,7 +131,10 @@ export class WorkspaceCore {
       (file) => file.data.filePath === filePath
     )
     if (index !== -1) {
-      this.workspace.files.splice(index, 1)
+      const [file] = this.workspace.files.splice(index, 1)
+      await file.data.close().catch((err) => {
+        console.warn(`Failed to close session of ${filePath}: ${err}`)
+      })
       this.emit('file:closed', filePath)
       return
     }
@@ -158,7 +162,15 @@ export class WorkspaceCore {
 
   // ==================== Audio Operations ====================
 
-  public async importAudio(nodeId: string, audioPath: string): Promise<void> {
+  /**
+   * @param conversion Conversion of non-wem audio, matching the codec of the
+   * replaced source by default.
+   */
+  public async importAudio(
+    nodeId: string,
+    audioPath: string,
+    conversion?: ConversionPreset
+  ): Promise<void> {
     this.workspace.isLoading = true
     try {
       const node = this.workspace.flattenNodeMap[nodeId]
@@ -187,7 +199,11 @@ export class WorkspaceCore {
 
       // Import audio
       const sourceId = node.data.id
-      await node.belongToFile.data.addOverrideAudio(sourceId, audioPath)
+      await node.belongToFile.data.addOverrideAudio(
+        sourceId,
+        audioPath,
+        conversion
+      )
 
       this.emit('audio:imported', nodeId, audioPath)
       ShowInfo(`Audio imported successfully: ${sourceId}`)
@@ -376,7 +392,11 @@ export class WorkspaceCore {
           })
 
           const exportPath = `${exportDir}/${file.data.name}`
-          await file.data.exportFile(exportPath, exportLogger)
+          if (file.data instanceof Bnk) {
+            await this.exportBnk(file.data, exportPath, exportLogger)
+          } else {
+            await file.data.exportFile(exportPath, exportLogger)
+          }
 
           successCount++
           exportResults.push({
@@ -447,6 +467,33 @@ export class WorkspaceCore {
     }
   }
 
+  /**
+   * Export bnk, HIRC references are checked against the other opened files
+   * before writing. With errors the bank is only written if confirmed.
+   */
+  private async exportBnk(
+    bnk: Bnk,
+    exportPath: string,
+    logger: ExportLogger
+  ): Promise<void> {
+    const referencePaths = this.workspace.files
+      .map((file) => file.data.filePath)
+      .filter((path) => path !== bnk.filePath)
+    const report = await bnk.exportFile(exportPath, referencePaths, logger)
+    if (report.saved) return
+
+    const { errors, warnings } = report.validation
+    const confirmed = await ShowConfirm(
+      `${bnk.name} has ${errors} errors and ${warnings} warnings in HIRC references, objects using them will be silent in game. See the export log for details.`,
+      'Broken HIRC references',
+      'Save anyway'
+    )
+    if (!confirmed) {
+      throw new Error(`${errors} errors found in HIRC references, not saved`)
+    }
+    await bnk.exportFile(exportPath, referencePaths, logger, true)
+  }
+
   // ==================== Refresh Methods ====================
 
   public refreshNodeTree(): void {
@@ -728,7 +775,7 @@ export class WorkspaceCore {
       return toRef(node, 'dirty') ?? false
     }
 
-    const iterNodes = (parent: TreeNode, node: HircNode) => {
+    const iterNodes = (parent: TreeNode, node: HircNode, bnk: Bnk) => {
       if (!parent.children) {
         parent.children = []
       }
@@ -737,20 +784,20 @@ export class WorkspaceCore {
       switch (node.type) {
         case 'MusicSegment':
           childNode = {
-            label: `Segment ${node.id}`,
+            label: `Segment ${bnk.displayName(node.id)}`,
             key: node.id,
             icon: 'mdi-segment',
             dirty: getDirtyRef.call(this, node.id) as unknown as boolean,
             children: [],
           }
           node.children.forEach((child) => {
-            iterNodes.call(this, childNode, child)
+            iterNodes.call(this, childNode, child, bnk)
           })
           parent.children.push(childNode)
           break
         case 'MusicTrack':
           childNode = {
-            label: `Track ${node.id}`,
+            label: `Track ${bnk.displayName(node.id)}`,
             key: node.id,
             icon: 'mdi-waveform',
             dirty: getDirtyRef.call(this, node.id) as unknown as boolean,
@@ -788,7 +835,7 @@ export class WorkspaceCore {
         root.type = 'bnk'
         // Build segment tree
         file.data.getSegmentTree().nodes.forEach((node) => {
-          iterNodes.call(this, root, node)
+          iterNodes.call(this, root, node, file.data)
         })
         // Collect unmanaged wem files
         const unmanagedSources = file.data.getUnmanagedSources()
@@ -1,72 +1,65 @@
-import { LocalDir } from '@/libs/localDir'
-import { join } from '@tauri-apps/api/path'
-import { exists, readTextFile, writeTextFile } from '@tauri-apps/plugin-fs'
+import { SettingsApi } from '@/api/tauri'
+import type { Settings, SettingsIssue, SettingsState } from '@/api/tauri'
 import { defineStore } from 'pinia'
 import { ref, watch } from 'vue'
 
-const CONFIG_FILE_NAME = 'config.json'
-
-export type Config = {
-  shell: {
-    ffmpegPath?: string
-    wwiseConsolePath?: string
-    vgmstreamPath?: string
-  }
-}
-
+/**
+ * Settings stored by the backend in the app config directory.
+ */
 export const useConfigStore = defineStore('config', () => {
-  const config = ref<Config | null>(null)
+  const config = ref<Settings | null>(null)
+  /** Problems found in settings, by field path. Empty field for the whole file. */
+  const issues = ref<SettingsIssue[]>([])
+  const configPath = ref('')
   const saveOnChange = ref(true)
+  // skip saving the settings just received from backend
+  let syncing = false
 
   const loadConfig = async () => {
-    const configPath = await _getConfigPath()
-    if (await exists(configPath)) {
-      const content = await readTextFile(configPath)
-      config.value = JSON.parse(content)
-      console.info('Config file loaded from', configPath)
-      console.debug('Config', config.value)
-    } else {
-      console.info('Config file not found. Using default config.')
-      config.value = await _getDefaultConfig()
+    const state = await SettingsApi.get()
+    _setState(state)
+    console.info('Settings loaded from', state.path)
+    console.debug('Settings', state.settings)
+    if (state.issues.length > 0) {
+      console.warn('Settings issues', state.issues)
     }
   }
 
   const saveConfig = async () => {
-    const configPath = await _getConfigPath()
-    const content = JSON.stringify(config.value, null, 2)
-    await writeTextFile(configPath, content)
-    console.debug('Config file saved to', configPath)
+    if (!config.value) return
+    const state = await SettingsApi.update(config.value)
+    _setState(state)
+    console.debug('Settings saved to', state.path)
   }
 
-  const _getConfigPath = async () => {
-    const rootDir = await LocalDir.getRoot()
-    return await join(rootDir, CONFIG_FILE_NAME)
-  }
-
-  const _getDefaultConfig = async (): Promise<Config> => {
-    return {
-      shell: {},
-    }
+  const _setState = (state: SettingsState) => {
+    syncing = true
+    config.value = state.settings
+    issues.value = state.issues
+    configPath.value = state.path
   }
 
   watch(
     config,
     async () => {
-      // Auto-save config on change
+      if (syncing) {
+        syncing = false
+        return
+      }
+      // Auto-save config on change, backend applies it to services
       if (saveOnChange.value) {
         await saveConfig()
       }
-      // sync to backend
     },
     { deep: true }
   )
 
   return {
     config,
+    issues,
+    configPath,
     saveOnChange,
     loadConfig,
     saveConfig,
-    _getConfigPath,
-    _getDefaultConfig,
   }
 })
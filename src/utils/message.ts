@@ -1,4 +1,4 @@
-import { ElMessage } from "element-plus"
+import { ElMessage, ElMessageBox } from "element-plus"
 
 // global message invoker
 export const ShowError = (message: any) => {
@@ -30,3 +30,17 @@ export const ShowInfo = (message: any) => {
   })
   console.info('[INFO]', message)
 }
+
+/** Ask to go on with a warning, resolves false if cancelled. */
+export const ShowConfirm = async (message: string, title: string, confirmText: string) => {
+  try {
+    await ElMessageBox.confirm(message, title, {
+      type: 'warning',
+      confirmButtonText: confirmText,
+      cancelButtonText: 'Cancel'
+    })
+    return true
+  } catch {
+    return false
+  }
+}